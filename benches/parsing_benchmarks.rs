@@ -225,13 +225,56 @@ fn bench_tokenization(c: &mut Criterion) {
     group.finish();
 }
 
+/// Benchmark the borrowing parser against owned copies of its output
+///
+/// `owned` reproduces the cost of allocating a `String` per name, value and
+/// comment, which is what every parse paid before the AST borrowed its text.
+fn bench_borrowed_vs_owned(c: &mut Criterion) {
+    let mut group = c.benchmark_group("borrowed_vs_owned");
+
+    for pattern in ["movement_heavy", "parameter_heavy", "mixed"] {
+        let content = generate_gcode_content(10_000, pattern);
+
+        group.throughput(Throughput::Bytes(content.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::new("borrowed", pattern),
+            &content,
+            |b, content| {
+                b.iter(|| {
+                    let results: Vec<ParsedLine> = content
+                        .lines()
+                        .map(|line| parse_line(black_box(line)))
+                        .collect();
+                    black_box(results)
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("owned", pattern),
+            &content,
+            |b, content| {
+                b.iter(|| {
+                    let results: Vec<ParsedLine<'static>> = content
+                        .lines()
+                        .map(|line| parse_line(black_box(line)).into_owned())
+                        .collect();
+                    black_box(results)
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
 criterion_group!(
     parsing_benches,
     bench_single_line_parsing,
     bench_file_parsing,
     bench_parsing_throughput,
     bench_real_files,
-    bench_tokenization
+    bench_tokenization,
+    bench_borrowed_vs_owned
 );
 
 criterion_main!(parsing_benches);
@@ -1,7 +1,26 @@
 use anyhow::Result;
+use gcode_language_server::Config;
+use gcode_language_server::cli::run_check;
+use gcode_language_server::config::CliCommand;
 use gcode_language_server::lsp::server::serve;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    serve().await
+    let config = Config::from_args_and_env()?;
+
+    // Logs go to stderr; stdout carries the protocol or the check report
+    env_logger::Builder::new()
+        .parse_filters(&config.log_level)
+        .init();
+
+    match &config.command {
+        Some(CliCommand::Check(args)) => match run_check(&config, args) {
+            Ok(code) => std::process::exit(code),
+            Err(e) => {
+                eprintln!("Error: {:#}", e);
+                std::process::exit(2);
+            }
+        },
+        None => serve(config).await,
+    }
 }
@@ -0,0 +1,250 @@
+//! Check Command
+//!
+//! Validates G-code files with the same flavor, machine and project
+//! configuration as the language server.
+
+use anyhow::Result;
+use std::collections::HashSet;
+use std::io::Write;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::cli::report;
+use crate::config::{CheckArgs, Config, OutputFormat, ProjectConfig, Settings};
+use crate::core::diagnostics::utf16_column;
+use crate::core::files::collect_files;
+use crate::flavor::FlavorRegistry;
+use crate::machine::MachineRegistry;
+use crate::validation::engine::{Diagnostic, Severity, ValidationOptions, validate_document_with};
+
+/// Diagnostics found in one file
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileReport {
+    pub path: PathBuf,
+    pub diagnostics: Vec<Diagnostic>,
+    /// Span of each diagnostic in UTF-16 code units, as SARIF counts columns
+    pub columns: Vec<Option<Range<u32>>>,
+}
+
+impl FileReport {
+    /// Collect the diagnostics of `content`, converting their spans to columns
+    pub fn new(path: &Path, content: &str, diagnostics: Vec<Diagnostic>) -> Self {
+        let lines: Vec<&str> = content.lines().collect();
+        let columns = diagnostics
+            .iter()
+            .map(|diagnostic| {
+                let span = diagnostic.span.as_ref()?;
+                let line = lines.get(diagnostic.line - 1).copied().unwrap_or("");
+                Some(utf16_column(line, span.start)..utf16_column(line, span.end))
+            })
+            .collect();
+        Self {
+            path: path.to_path_buf(),
+            diagnostics,
+            columns,
+        }
+    }
+
+    /// Count the diagnostics of a given severity
+    pub fn count(&self, severity: Severity) -> usize {
+        self.diagnostics
+            .iter()
+            .filter(|d| d.severity == severity)
+            .count()
+    }
+}
+
+/// Validates files, loading project configuration as it goes
+pub struct Checker<'a> {
+    config: &'a Config,
+    flavors: FlavorRegistry,
+    machines: MachineRegistry,
+    loaded_dirs: HashSet<PathBuf>,
+}
+
+impl<'a> Checker<'a> {
+    pub fn new(config: &'a Config) -> Self {
+        Self {
+            config,
+            flavors: config.flavor_registry(),
+            machines: config.machine_registry(),
+            loaded_dirs: HashSet::new(),
+        }
+    }
+
+    /// Validate the content of a file
+    ///
+    /// Returns `None` when the file is excluded by its project configuration.
+    pub fn check(&mut self, path: &Path, content: &str) -> Option<FileReport> {
+        let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        let project = ProjectConfig::discover(&absolute);
+        if project
+            .as_ref()
+            .is_some_and(|project| !project.includes(&absolute))
+        {
+            return None;
+        }
+
+        let settings = self.config.resolve(&Settings::default(), project.as_ref());
+        self.load_settings_dirs(&settings);
+
+        let default_flavor = self.flavors.get_active_flavor().map(|f| f.name.clone());
+        if let Some(flavor) = self
+            .flavors
+            .select_flavor(content, settings.flavor.as_deref())
+        {
+            self.flavors.set_active_flavor(&flavor);
+        }
+
+        let machine_name = self
+            .machines
+            .select_profile(content, settings.machine.as_deref());
+        let options = ValidationOptions {
+            machine: machine_name
+                .as_deref()
+                .and_then(|name| self.machines.get_profile(name)),
+            severities: Some(&settings.rules),
+            ..Default::default()
+        };
+        let result = validate_document_with(content, &self.flavors, &options);
+
+        // Restore the default so the next file starts from the same flavor
+        if let Some(flavor) = default_flavor {
+            self.flavors.set_active_flavor(&flavor);
+        }
+
+        Some(FileReport::new(path, content, result.diagnostics))
+    }
+
+    /// Load the flavor and machine directories of a settings layer once
+    fn load_settings_dirs(&mut self, settings: &Settings) {
+        for dir in &settings.flavor_dirs {
+            if self.loaded_dirs.insert(dir.clone()) {
+                self.flavors.load_directory(dir);
+            }
+        }
+        for dir in &settings.machine_dirs {
+            if self.loaded_dirs.insert(dir.clone()) {
+                self.machines.load_directory(dir);
+            }
+        }
+    }
+}
+
+/// Read a file, replacing bytes that are not valid UTF-8
+///
+/// G-code is ASCII apart from comments, which slicers write in any encoding.
+pub fn read_file(path: &Path) -> std::io::Result<String> {
+    let bytes = std::fs::read(path)?;
+    Ok(match String::from_utf8(bytes) {
+        Ok(content) => content,
+        Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+    })
+}
+
+/// Run the `check` subcommand and return the process exit code
+///
+/// The exit code is 2 if a file could not be read, 1 if any file has an
+/// error diagnostic, 0 otherwise. Unreadable files are reported and skipped.
+pub fn run_check(config: &Config, args: &CheckArgs) -> Result<i32> {
+    let files = collect_files(&args.paths)?;
+
+    let mut checker = Checker::new(config);
+    let mut reports = Vec::new();
+    let mut unreadable = false;
+    for path in &files {
+        match read_file(path) {
+            Ok(content) => reports.extend(checker.check(path, &content)),
+            Err(e) => {
+                eprintln!("Failed to read {}: {}", path.display(), e);
+                unreadable = true;
+            }
+        }
+    }
+
+    let output = match args.format {
+        OutputFormat::Human => report::human(&reports),
+        OutputFormat::Json => serde_json::to_string_pretty(&report::json(&reports))?,
+        OutputFormat::Sarif => serde_json::to_string_pretty(&report::sarif(&reports))?,
+    };
+    writeln!(std::io::stdout(), "{}", output)?;
+
+    let has_errors = reports
+        .iter()
+        .any(|report| report.count(Severity::Error) > 0);
+    Ok(if unreadable {
+        2
+    } else if has_errors {
+        1
+    } else {
+        0
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn config(args: &[&str]) -> Config {
+        Config::from_args(crate::Args::parse_from(args)).unwrap()
+    }
+
+    #[test]
+    fn test_check_uses_project_config() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join(".gcode-ls.toml"),
+            "machine = \"prusa-mk4\"\n\n[rules]\nunknown-command = \"error\"\n\n[files]\nexclude = [\"skip.gcode\"]\n",
+        )
+        .unwrap();
+
+        let config = config(&["gcode-ls"]);
+        let mut checker = Checker::new(&config);
+
+        let report = checker
+            .check(&dir.path().join("part.gcode"), "G28\nG999\nM104 S400\n")
+            .unwrap();
+        let rules: Vec<(usize, &str, Severity)> = report
+            .diagnostics
+            .iter()
+            .map(|d| (d.line, d.rule.code(), d.severity))
+            .collect();
+        assert!(rules.contains(&(2, "unknown-command", Severity::Error)));
+        assert!(rules.contains(&(3, "temperature-limit", Severity::Error)));
+
+        assert!(
+            checker
+                .check(&dir.path().join("skip.gcode"), "G999\n")
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_read_file_lossy() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("latin1.gcode");
+        std::fs::write(&path, b"G28 ; caf\xe9\n").unwrap();
+
+        assert_eq!(read_file(&path).unwrap(), "G28 ; caf\u{fffd}\n");
+        assert!(read_file(&dir.path().join("missing.gcode")).is_err());
+    }
+
+    #[test]
+    fn test_utf16_columns() {
+        let diagnostic = |line, span| Diagnostic {
+            line,
+            message: "Arc radius mismatch".to_string(),
+            severity: Severity::Error,
+            tags: Vec::new(),
+            span,
+            rule: crate::validation::Rule::ArcGeometry,
+        };
+        let report = FileReport::new(
+            Path::new("part.gcode"),
+            "G28\n(é) G2 X1 R5\n",
+            vec![diagnostic(2, Some(11..13)), diagnostic(1, None)],
+        );
+        assert_eq!(report.columns, vec![Some(10..12), None]);
+    }
+}
@@ -0,0 +1,9 @@
+//! Command-Line Interface
+//!
+//! Subcommands of the `gcode-ls` binary that run without an editor.
+
+pub mod check;
+pub mod report;
+
+pub use crate::core::files::collect_files;
+pub use check::{Checker, FileReport, run_check};
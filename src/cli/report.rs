@@ -0,0 +1,222 @@
+//! Check Reports
+//!
+//! Human-readable, JSON and SARIF renderings of check results.
+
+use serde_json::{Value, json};
+
+use crate::cli::check::FileReport;
+use crate::validation::engine::Severity;
+use crate::validation::rules::Rule;
+
+/// SARIF schema the report conforms to
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+fn severity_name(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Info => "info",
+    }
+}
+
+/// One line per diagnostic followed by a summary
+pub fn human(reports: &[FileReport]) -> String {
+    let mut output = String::new();
+    let (mut errors, mut warnings) = (0, 0);
+
+    for report in reports {
+        for diagnostic in &report.diagnostics {
+            output.push_str(&format!(
+                "{}:{}: {}[{}]: {}\n",
+                report.path.display(),
+                diagnostic.line,
+                severity_name(diagnostic.severity),
+                diagnostic.rule.code(),
+                diagnostic.message
+            ));
+        }
+        errors += report.count(Severity::Error);
+        warnings += report.count(Severity::Warning);
+    }
+
+    output.push_str(&format!(
+        "{} error(s), {} warning(s) in {} file(s)",
+        errors,
+        warnings,
+        reports.len()
+    ));
+    output
+}
+
+/// An array of files with their diagnostics
+pub fn json(reports: &[FileReport]) -> Value {
+    let files: Vec<Value> = reports
+        .iter()
+        .map(|report| {
+            let diagnostics: Vec<Value> = report
+                .diagnostics
+                .iter()
+                .zip(&report.columns)
+                .map(|(diagnostic, columns)| {
+                    let mut value = json!({
+                        "line": diagnostic.line,
+                        "severity": severity_name(diagnostic.severity),
+                        "rule": diagnostic.rule.code(),
+                        "message": diagnostic.message,
+                    });
+                    if let Some(columns) = columns {
+                        value["column"] = json!(columns.start + 1);
+                        value["end_column"] = json!(columns.end + 1);
+                    }
+                    value
+                })
+                .collect();
+            json!({
+                "path": report.path.to_string_lossy(),
+                "diagnostics": diagnostics,
+            })
+        })
+        .collect();
+
+    Value::Array(files)
+}
+
+/// A SARIF 2.1.0 log with one run
+pub fn sarif(reports: &[FileReport]) -> Value {
+    let rules: Vec<Value> = Rule::ALL
+        .iter()
+        .map(|rule| {
+            json!({
+                "id": rule.code(),
+                "shortDescription": { "text": rule.description() },
+            })
+        })
+        .collect();
+
+    let results: Vec<Value> = reports
+        .iter()
+        .flat_map(|report| {
+            let uri = report.path.to_string_lossy().replace('\\', "/");
+            let diagnostics = report.diagnostics.iter().zip(&report.columns);
+            diagnostics.map(move |(diagnostic, columns)| {
+                let level = match diagnostic.severity {
+                    Severity::Error => "error",
+                    Severity::Warning => "warning",
+                    Severity::Info => "note",
+                };
+                let mut region = json!({ "startLine": diagnostic.line });
+                if let Some(columns) = columns {
+                    region["startColumn"] = json!(columns.start + 1);
+                    region["endColumn"] = json!(columns.end + 1);
+                }
+                json!({
+                    "ruleId": diagnostic.rule.code(),
+                    "level": level,
+                    "message": { "text": diagnostic.message },
+                    "locations": [{
+                        "physicalLocation": {
+                            "artifactLocation": { "uri": uri },
+                            "region": region,
+                        },
+                    }],
+                })
+            })
+        })
+        .collect();
+
+    json!({
+        "$schema": SARIF_SCHEMA,
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "gcode-ls",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": env!("CARGO_PKG_HOMEPAGE"),
+                    "rules": rules,
+                },
+            },
+            "results": results,
+        }],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validation::engine::Diagnostic;
+    use std::path::PathBuf;
+
+    fn reports() -> Vec<FileReport> {
+        vec![FileReport {
+            path: PathBuf::from("parts/a.gcode"),
+            diagnostics: vec![
+                Diagnostic {
+                    line: 3,
+                    message: "Unknown command 'G999'".to_string(),
+                    severity: Severity::Warning,
+                    tags: Vec::new(),
+                    span: None,
+                    rule: Rule::UnknownCommand,
+                },
+                Diagnostic {
+                    line: 7,
+                    message: "Missing required parameter 'S' for command 'M104'".to_string(),
+                    severity: Severity::Error,
+                    tags: Vec::new(),
+                    span: Some(0..4),
+                    rule: Rule::MissingRequiredParam,
+                },
+            ],
+            columns: vec![None, Some(0..4)],
+        }]
+    }
+
+    #[test]
+    fn test_human_report() {
+        let output = human(&reports());
+        assert_eq!(
+            output,
+            "parts/a.gcode:3: warning[unknown-command]: Unknown command 'G999'\n\
+             parts/a.gcode:7: error[missing-required-param]: Missing required parameter 'S' for command 'M104'\n\
+             1 error(s), 1 warning(s) in 1 file(s)"
+        );
+    }
+
+    #[test]
+    fn test_json_report() {
+        let output = json(&reports());
+        assert_eq!(output[0]["path"], "parts/a.gcode");
+        assert_eq!(
+            output[0]["diagnostics"][1]["rule"],
+            "missing-required-param"
+        );
+        assert_eq!(output[0]["diagnostics"][1]["severity"], "error");
+        assert_eq!(output[0]["diagnostics"][1]["column"], 1);
+        assert!(output[0]["diagnostics"][0].get("column").is_none());
+    }
+
+    #[test]
+    fn test_sarif_report() {
+        let output = sarif(&reports());
+        assert_eq!(output["version"], "2.1.0");
+
+        let run = &output["runs"][0];
+        assert_eq!(
+            run["tool"]["driver"]["rules"].as_array().unwrap().len(),
+            Rule::ALL.len()
+        );
+
+        let result = &run["results"][1];
+        assert_eq!(result["ruleId"], "missing-required-param");
+        assert_eq!(result["level"], "error");
+        assert_eq!(
+            result["locations"][0]["physicalLocation"]["artifactLocation"]["uri"],
+            "parts/a.gcode"
+        );
+        assert_eq!(
+            result["locations"][0]["physicalLocation"]["region"]["startLine"],
+            7
+        );
+    }
+}
@@ -0,0 +1,269 @@
+//! Configuration management for the G-code language server.
+//!
+//! Handles:
+//! - Command-line argument parsing and subcommands
+//! - Flavor directory configuration
+//! - Machine profile selection
+//! - Snippet directory configuration
+//! - Diagnostic severity overrides
+//! - Project configuration files (`.gcode-ls.toml`)
+
+pub mod project;
+
+use anyhow::Result;
+use clap::Parser;
+use std::path::PathBuf;
+
+use crate::flavor::FlavorRegistry;
+use crate::machine::MachineRegistry;
+use crate::validation::rules::SeverityOverrides;
+
+pub use project::{ProjectConfig, Settings};
+
+/// Command-line arguments for the G-code language server
+#[derive(Debug, Parser)]
+#[command(name = "gcode-language-server")]
+#[command(about = "Language server for G-code files")]
+#[command(version)]
+pub struct Args {
+    /// Explicitly specify the G-code flavor to use
+    #[arg(
+        long,
+        global = true,
+        help = "G-code flavor to use (e.g., 'prusa', 'marlin')"
+    )]
+    pub flavor: Option<String>,
+
+    /// Custom flavor directory to search for flavor files
+    #[arg(long, global = true, help = "Directory containing flavor TOML files")]
+    pub flavor_dir: Option<PathBuf>,
+
+    /// Machine profile to check documents against
+    #[arg(
+        long,
+        global = true,
+        help = "Machine profile to use (e.g., 'prusa-mk4')"
+    )]
+    pub machine: Option<String>,
+
+    /// Custom directory to search for machine profiles
+    #[arg(
+        long,
+        global = true,
+        help = "Directory containing machine profile TOML files"
+    )]
+    pub machine_dir: Option<PathBuf>,
+
+    /// Custom directory to search for snippet files
+    #[arg(
+        long,
+        global = true,
+        help = "Directory containing completion snippet TOML files"
+    )]
+    pub snippet_dir: Option<PathBuf>,
+
+    /// Severity overrides for diagnostic rules
+    #[arg(
+        long = "severity",
+        global = true,
+        value_name = "RULE=LEVEL",
+        help = "Override a rule's severity (error, warning, info, off); repeatable"
+    )]
+    pub severities: Vec<String>,
+
+    /// Log level for the language server
+    #[arg(
+        long,
+        global = true,
+        default_value = "info",
+        help = "Log level (trace, debug, info, warn, error)"
+    )]
+    pub log_level: String,
+
+    /// Subcommand to run instead of the language server
+    #[command(subcommand)]
+    pub command: Option<CliCommand>,
+}
+
+/// Subcommands of the `gcode-ls` binary
+#[derive(Debug, Clone, clap::Subcommand)]
+pub enum CliCommand {
+    /// Check G-code files and report diagnostics
+    Check(CheckArgs),
+}
+
+/// Arguments of the `check` subcommand
+#[derive(Debug, Clone, clap::Args)]
+pub struct CheckArgs {
+    /// Files, directories or glob patterns to check
+    #[arg(required = true, value_name = "PATH")]
+    pub paths: Vec<String>,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Human)]
+    pub format: OutputFormat,
+}
+
+/// Output format of the `check` subcommand
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    Human,
+    Json,
+    Sarif,
+}
+
+/// Combined configuration from all sources
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Flavor name explicitly set via command line
+    pub cli_flavor: Option<String>,
+    /// Custom flavor directories to search
+    pub flavor_dirs: Vec<PathBuf>,
+    /// Machine profile name explicitly set via command line
+    pub cli_machine: Option<String>,
+    /// Machine profile directories to search
+    pub machine_dirs: Vec<PathBuf>,
+    /// Snippet directories to search
+    pub snippet_dirs: Vec<PathBuf>,
+    /// Per-rule diagnostic severity overrides
+    pub severity_overrides: SeverityOverrides,
+    /// Log level
+    pub log_level: String,
+    /// Subcommand to run, if any
+    pub command: Option<CliCommand>,
+}
+
+impl Config {
+    /// Create configuration from command-line arguments
+    pub fn from_args_and_env() -> Result<Self> {
+        Self::from_args(Args::parse())
+    }
+
+    /// Create configuration from explicit arguments (useful for testing)
+    pub fn from_args(args: Args) -> Result<Self> {
+        // Determine flavor directories
+        let mut flavor_dirs = Vec::new();
+
+        // Add user-specified directory if provided
+        if let Some(custom_dir) = args.flavor_dir {
+            flavor_dirs.push(custom_dir);
+        }
+
+        // Add default user config directory
+        if let Some(config_dir) = dirs::config_dir() {
+            flavor_dirs.push(config_dir.join("gcode-ls").join("flavors"));
+        }
+
+        // Machine profile directories follow the same layout
+        let mut machine_dirs = Vec::new();
+        if let Some(custom_dir) = args.machine_dir {
+            machine_dirs.push(custom_dir);
+        }
+        if let Some(config_dir) = dirs::config_dir() {
+            machine_dirs.push(config_dir.join("gcode-ls").join("machines"));
+        }
+
+        // And so do snippet directories
+        let mut snippet_dirs = Vec::new();
+        if let Some(custom_dir) = args.snippet_dir {
+            snippet_dirs.push(custom_dir);
+        }
+        if let Some(config_dir) = dirs::config_dir() {
+            snippet_dirs.push(config_dir.join("gcode-ls").join("snippets"));
+        }
+
+        let mut severity_overrides = SeverityOverrides::new();
+        for assignment in &args.severities {
+            severity_overrides
+                .parse_assignment(assignment)
+                .map_err(|e| anyhow::anyhow!("Invalid --severity: {}", e))?;
+        }
+
+        Ok(Config {
+            cli_flavor: args.flavor,
+            flavor_dirs,
+            cli_machine: args.machine,
+            machine_dirs,
+            snippet_dirs,
+            severity_overrides,
+            log_level: args.log_level,
+            command: args.command,
+        })
+    }
+
+    /// Get the effective flavor name from CLI arguments
+    pub fn get_effective_flavor(&self) -> Option<String> {
+        self.cli_flavor.clone()
+    }
+
+    /// Get the effective machine profile name from CLI arguments
+    pub fn get_effective_machine(&self) -> Option<String> {
+        self.cli_machine.clone()
+    }
+
+    /// Build the flavor registry from the embedded and configured flavors
+    ///
+    /// The CLI flavor is active if it exists, `prusa` otherwise.
+    pub fn flavor_registry(&self) -> FlavorRegistry {
+        let mut registry = FlavorRegistry::new();
+        registry.add_embedded_prusa_flavor();
+        for dir in &self.flavor_dirs {
+            registry.load_directory(dir);
+        }
+        for dir in &self.snippet_dirs {
+            registry.load_snippet_directory(dir);
+        }
+
+        registry.set_active_flavor("prusa");
+        if let Some(flavor) = self.get_effective_flavor()
+            && !registry.set_active_flavor(&flavor)
+        {
+            log::warn!("Unknown flavor '{}'", flavor);
+        }
+
+        registry
+    }
+
+    /// Build the machine registry from the embedded and configured profiles
+    ///
+    /// No profile is active unless one is selected on the command line.
+    pub fn machine_registry(&self) -> MachineRegistry {
+        let mut registry = MachineRegistry::new();
+        registry.add_embedded_profiles();
+        for dir in &self.machine_dirs {
+            registry.load_directory(dir);
+        }
+
+        if let Some(machine) = self.get_effective_machine()
+            && !registry.set_active_profile(&machine)
+        {
+            log::warn!("Unknown machine profile '{}'", machine);
+        }
+
+        registry
+    }
+
+    /// Settings given on the command line, as a settings layer
+    pub fn cli_settings(&self) -> Settings {
+        Settings {
+            flavor: self.cli_flavor.clone(),
+            machine: self.cli_machine.clone(),
+            rules: self.severity_overrides.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Resolve the settings for a document
+    ///
+    /// Precedence, highest first: command-line flags, the nearest project
+    /// file, then settings from the client. Modelines in the document itself
+    /// override the resolved flavor and machine.
+    pub fn resolve(&self, client: &Settings, project: Option<&ProjectConfig>) -> Settings {
+        let mut settings = client.clone();
+        if let Some(project) = project {
+            settings.merge(&project.settings);
+        }
+        settings.merge(&self.cli_settings());
+        settings
+    }
+}
@@ -0,0 +1,329 @@
+//! Project Configuration
+//!
+//! Settings read from a `.gcode-ls.toml` file, found by walking up from a
+//! document or workspace folder:
+//!
+//! ```toml
+//! flavor = "marlin"
+//! machine = "prusa-mk4"
+//! flavor_dirs = ["flavors"]
+//! snippet_dirs = ["snippets"]
+//!
+//! [rules]
+//! unknown-command = "error"
+//!
+//! [format]
+//! command_case = "upper"
+//!
+//! [files]
+//! include = ["**/*.gcode"]
+//! exclude = ["build/**"]
+//! ```
+//!
+//! The same [`Settings`] structure is used for settings sent by the client.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+use crate::validation::rules::SeverityOverrides;
+
+/// File name of project configuration files
+pub const PROJECT_FILE_NAME: &str = ".gcode-ls.toml";
+
+/// One layer of settings, from a project file, the client or the CLI
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Flavor for documents without a modeline
+    pub flavor: Option<String>,
+    /// Machine profile for documents without a modeline
+    pub machine: Option<String>,
+    /// Filament type (e.g. `PLA`) for documents that do not name one
+    pub filament: Option<String>,
+    /// Extra directories to search for flavor files
+    #[serde(alias = "flavorDirs")]
+    pub flavor_dirs: Vec<PathBuf>,
+    /// Extra directories to search for machine profiles
+    #[serde(alias = "machineDirs")]
+    pub machine_dirs: Vec<PathBuf>,
+    /// Extra directories to search for snippet files
+    #[serde(alias = "snippetDirs")]
+    pub snippet_dirs: Vec<PathBuf>,
+    /// Per-rule severity overrides
+    pub rules: SeverityOverrides,
+    /// Formatter style
+    pub format: FormatSettings,
+    /// Files the settings apply to
+    pub files: FileSettings,
+}
+
+/// Formatter style options
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct FormatSettings {
+    /// Case of command words
+    #[serde(alias = "commandCase")]
+    pub command_case: Option<CommandCase>,
+    /// Column to align trailing comments to
+    #[serde(alias = "commentColumn")]
+    pub comment_column: Option<usize>,
+}
+
+/// Case of command words such as `G1` or `m104`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CommandCase {
+    Upper,
+    Lower,
+    Preserve,
+}
+
+/// Glob patterns selecting the files to check
+///
+/// Patterns are relative to the directory holding the project file. An empty
+/// `include` list includes every file.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct FileSettings {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl Settings {
+    /// Merge a higher-precedence layer on top of this one
+    ///
+    /// Values set in `higher` win; directory lists are concatenated so that
+    /// files from `higher` directories are loaded last and take precedence.
+    pub fn merge(&mut self, higher: &Settings) {
+        if higher.flavor.is_some() {
+            self.flavor = higher.flavor.clone();
+        }
+        if higher.machine.is_some() {
+            self.machine = higher.machine.clone();
+        }
+        if higher.filament.is_some() {
+            self.filament = higher.filament.clone();
+        }
+        self.flavor_dirs.extend(higher.flavor_dirs.iter().cloned());
+        self.machine_dirs
+            .extend(higher.machine_dirs.iter().cloned());
+        self.snippet_dirs
+            .extend(higher.snippet_dirs.iter().cloned());
+        self.rules.extend(&higher.rules);
+
+        if higher.format.command_case.is_some() {
+            self.format.command_case = higher.format.command_case;
+        }
+        if higher.format.comment_column.is_some() {
+            self.format.comment_column = higher.format.comment_column;
+        }
+
+        if !higher.files.include.is_empty() {
+            self.files.include = higher.files.include.clone();
+        }
+        if !higher.files.exclude.is_empty() {
+            self.files.exclude = higher.files.exclude.clone();
+        }
+    }
+
+    /// Make relative directories absolute against `base`
+    pub fn resolve_paths(&mut self, base: &Path) {
+        let dirs = self
+            .flavor_dirs
+            .iter_mut()
+            .chain(&mut self.machine_dirs)
+            .chain(&mut self.snippet_dirs);
+        for dir in dirs {
+            if dir.is_relative() {
+                *dir = base.join(&*dir);
+            }
+        }
+    }
+}
+
+impl FileSettings {
+    /// Check whether a file is selected by the patterns
+    ///
+    /// Files outside `root` are always selected.
+    pub fn matches(&self, root: &Path, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(root) else {
+            return true;
+        };
+
+        let options = glob::MatchOptions {
+            require_literal_separator: true,
+            ..Default::default()
+        };
+        let any_match = |patterns: &[String]| {
+            patterns
+                .iter()
+                .any(|pattern| match glob::Pattern::new(pattern) {
+                    Ok(pattern) => pattern.matches_path_with(relative, options),
+                    Err(e) => {
+                        log::warn!("Invalid file pattern '{}': {}", pattern, e);
+                        false
+                    }
+                })
+        };
+
+        (self.include.is_empty() || any_match(&self.include)) && !any_match(&self.exclude)
+    }
+}
+
+/// A `.gcode-ls.toml` file and the directory it applies to
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProjectConfig {
+    /// Directory containing the project file
+    pub root: PathBuf,
+    pub settings: Settings,
+}
+
+impl ProjectConfig {
+    /// Load a project file
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read project config {:?}", path))?;
+        let mut settings: Settings = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse project config {:?}", path))?;
+
+        let root = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        settings.resolve_paths(&root);
+
+        Ok(Self { root, settings })
+    }
+
+    /// Find the nearest project file in `start` or one of its ancestors
+    ///
+    /// An invalid project file is logged and stops the search.
+    pub fn discover(start: &Path) -> Option<Self> {
+        let path = start
+            .ancestors()
+            .map(|dir| dir.join(PROJECT_FILE_NAME))
+            .find(|path| path.is_file())?;
+
+        match Self::load(&path) {
+            Ok(project) => Some(project),
+            Err(e) => {
+                log::warn!("{:#}", e);
+                None
+            }
+        }
+    }
+
+    /// Check whether a file is selected by the project's file patterns
+    pub fn includes(&self, path: &Path) -> bool {
+        self.settings.files.matches(&self.root, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validation::{Rule, Severity};
+
+    #[test]
+    fn test_discover_nearest_project_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("parts").join("left");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::write(
+            dir.path().join(PROJECT_FILE_NAME),
+            r#"
+flavor = "marlin"
+flavor_dirs = ["flavors"]
+
+[rules]
+unknown-command = "error"
+
+[format]
+command_case = "upper"
+
+[files]
+exclude = ["build/**"]
+"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("parts").join(PROJECT_FILE_NAME),
+            "machine = \"prusa-mk4\"\n",
+        )
+        .unwrap();
+
+        let project = ProjectConfig::discover(&nested.join("part.gcode")).unwrap();
+        assert_eq!(project.root, dir.path().join("parts"));
+        assert_eq!(project.settings.machine.as_deref(), Some("prusa-mk4"));
+        assert_eq!(project.settings.flavor, None);
+
+        let project = ProjectConfig::discover(&dir.path().join("top.gcode")).unwrap();
+        assert_eq!(project.settings.flavor.as_deref(), Some("marlin"));
+        assert_eq!(
+            project.settings.flavor_dirs,
+            vec![dir.path().join("flavors")]
+        );
+        assert_eq!(
+            project.settings.rules.get(Rule::UnknownCommand),
+            Some(Some(Severity::Error))
+        );
+        assert_eq!(
+            project.settings.format.command_case,
+            Some(CommandCase::Upper)
+        );
+        assert!(project.includes(&dir.path().join("top.gcode")));
+        assert!(!project.includes(&dir.path().join("build").join("out.gcode")));
+    }
+
+    #[test]
+    fn test_invalid_project_file() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join(PROJECT_FILE_NAME),
+            "[rules]\nno-such-rule = \"error\"\n",
+        )
+        .unwrap();
+
+        assert!(ProjectConfig::load(&dir.path().join(PROJECT_FILE_NAME)).is_err());
+        assert!(ProjectConfig::discover(dir.path()).is_none());
+    }
+
+    #[test]
+    fn test_merge_precedence() {
+        let mut settings = Settings {
+            flavor: Some("prusa".to_string()),
+            machine: Some("prusa-mk4".to_string()),
+            flavor_dirs: vec![PathBuf::from("/client")],
+            ..Default::default()
+        };
+        let mut project = Settings {
+            flavor: Some("marlin".to_string()),
+            flavor_dirs: vec![PathBuf::from("/project")],
+            ..Default::default()
+        };
+        project.rules.set(Rule::ColdExtrusion, None);
+
+        settings.merge(&project);
+
+        assert_eq!(settings.flavor.as_deref(), Some("marlin"));
+        assert_eq!(settings.machine.as_deref(), Some("prusa-mk4"));
+        assert_eq!(
+            settings.flavor_dirs,
+            vec![PathBuf::from("/client"), PathBuf::from("/project")]
+        );
+        assert_eq!(settings.rules.get(Rule::ColdExtrusion), Some(None));
+    }
+
+    #[test]
+    fn test_file_patterns() {
+        let files = FileSettings {
+            include: vec!["**/*.gcode".to_string()],
+            exclude: vec!["build/*".to_string()],
+        };
+        let root = Path::new("/project");
+
+        assert!(files.matches(root, Path::new("/project/a.gcode")));
+        assert!(files.matches(root, Path::new("/project/parts/b.gcode")));
+        assert!(!files.matches(root, Path::new("/project/notes.txt")));
+        assert!(!files.matches(root, Path::new("/project/build/c.gcode")));
+        assert!(files.matches(root, Path::new("/elsewhere/notes.txt")));
+    }
+}
@@ -0,0 +1,284 @@
+//! Colors
+//!
+//! LED colors set by Marlin-style `M150 R U B` (0-255) and Klipper
+//! `SET_LED RED= GREEN= BLUE=` (0.0-1.0), for color swatches and pickers.
+//! Works on the raw line, since the lexer does not split Klipper parameters.
+
+use lsp_types::{Color, ColorInformation, ColorPresentation, Position, Range, TextEdit};
+
+use crate::core::diagnostics::utf16_column;
+use crate::core::format::format_number;
+use crate::flavor::registry::FlavorRegistry;
+use crate::parser::{Lexer, TokenKind};
+
+/// How a command writes color components
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LedSyntax {
+    /// `M150 R255 U0 B0`, components from 0 to 255
+    Letters,
+    /// `SET_LED RED=1 GREEN=0 BLUE=0`, components from 0.0 to 1.0
+    Keys,
+}
+
+/// A command setting an LED color, with the byte range of its code
+struct LedCommand<'a> {
+    syntax: LedSyntax,
+    words: Vec<&'a str>,
+    start: usize,
+    end: usize,
+}
+
+impl<'a> LedCommand<'a> {
+    /// Recognize an LED command in a line
+    ///
+    /// The code ends at the first `;` or parenthetical comment.
+    fn parse(line: &'a str) -> Option<Self> {
+        let code_end = Lexer::new(line)
+            .find(|token| token.kind == TokenKind::Comment)
+            .map_or(line.len(), |comment| comment.span.start);
+        let code = &line[..code_end];
+        let words: Vec<&str> = code.split_whitespace().collect();
+        let syntax = match words.first()?.to_uppercase().as_str() {
+            "M150" => LedSyntax::Letters,
+            "SET_LED" => LedSyntax::Keys,
+            _ => return None,
+        };
+        let start = code.len() - code.trim_start().len();
+        Some(Self {
+            syntax,
+            words,
+            start,
+            end: code.trim_end().len(),
+        })
+    }
+
+    /// Value of a component, given its names in this syntax
+    fn component(&self, names: &[&str]) -> Option<f64> {
+        self.words[1..].iter().find_map(|word| {
+            let (name, value) = self.split(word)?;
+            names
+                .iter()
+                .any(|n| n.eq_ignore_ascii_case(name))
+                .then(|| value.parse().ok())?
+        })
+    }
+
+    /// Split a parameter word into its name and value
+    fn split<'w>(&self, word: &'w str) -> Option<(&'w str, &'w str)> {
+        match self.syntax {
+            LedSyntax::Letters => {
+                let letter = word.chars().next()?;
+                Some(word.split_at(letter.len_utf8()))
+            }
+            LedSyntax::Keys => word.split_once('='),
+        }
+    }
+
+    fn color(&self) -> Color {
+        let (red, green, blue, scale) = match self.syntax {
+            LedSyntax::Letters => (&["R"][..], &["U", "G"][..], &["B"][..], 255.0),
+            LedSyntax::Keys => (&["RED"][..], &["GREEN"][..], &["BLUE"][..], 1.0),
+        };
+        let component =
+            |names: &[&str]| (self.component(names).unwrap_or(0.0) / scale).clamp(0.0, 1.0) as f32;
+        Color {
+            red: component(red),
+            green: component(green),
+            blue: component(blue),
+            alpha: 1.0,
+        }
+    }
+
+    /// The command with its color components replaced
+    ///
+    /// Other parameters keep their place; `green` is the letter the flavor
+    /// uses for green in `M150`.
+    fn with_color(&self, color: Color, green: &str) -> String {
+        let components: [(&str, f32); 3] = match self.syntax {
+            LedSyntax::Letters => [("R", color.red), (green, color.green), ("B", color.blue)],
+            LedSyntax::Keys => [
+                ("RED", color.red),
+                ("GREEN", color.green),
+                ("BLUE", color.blue),
+            ],
+        };
+        let format = |value: f32| match self.syntax {
+            LedSyntax::Letters => format!("{}", (value * 255.0).round() as u8),
+            LedSyntax::Keys => format_number(f64::from(value)),
+        };
+        let parameter = |name: &str, value: f32| match self.syntax {
+            LedSyntax::Letters => format!("{}{}", name, format(value)),
+            LedSyntax::Keys => format!("{}={}", name, format(value)),
+        };
+        let is_component = |word: &str| {
+            self.split(word).is_some_and(|(name, _)| {
+                components
+                    .iter()
+                    .map(|(component, _)| *component)
+                    .chain((self.syntax == LedSyntax::Letters).then_some("U"))
+                    .chain((self.syntax == LedSyntax::Letters).then_some("G"))
+                    .any(|component| component.eq_ignore_ascii_case(name))
+            })
+        };
+
+        let mut words = vec![self.words[0].to_string()];
+        let mut written = false;
+        for word in &self.words[1..] {
+            if is_component(word) {
+                // All components go where the first one was
+                if !written {
+                    words.extend(
+                        components
+                            .iter()
+                            .map(|(name, value)| parameter(name, *value)),
+                    );
+                    written = true;
+                }
+            } else {
+                words.push(word.to_string());
+            }
+        }
+        if !written {
+            words.extend(
+                components
+                    .iter()
+                    .map(|(name, value)| parameter(name, *value)),
+            );
+        }
+        words.join(" ")
+    }
+}
+
+/// Colors set by the LED commands of a document
+pub fn document_colors(content: &str) -> Vec<ColorInformation> {
+    content
+        .lines()
+        .enumerate()
+        .filter_map(|(idx, line)| {
+            let command = LedCommand::parse(line)?;
+            Some(ColorInformation {
+                range: code_range(idx, line, &command),
+                color: command.color(),
+            })
+        })
+        .collect()
+}
+
+/// Ways to write `color` into the LED command at `range`
+///
+/// `flavor` has the document's flavor active; its `M150` decides whether
+/// green is written as `U` (Marlin) or `G` (Prusa).
+pub fn color_presentations(
+    content: &str,
+    range: Range,
+    color: Color,
+    flavor: &FlavorRegistry,
+) -> Vec<ColorPresentation> {
+    let idx = range.start.line as usize;
+    let Some(line) = content.lines().nth(idx) else {
+        return Vec::new();
+    };
+    let Some(command) = LedCommand::parse(line) else {
+        return Vec::new();
+    };
+
+    let green = flavor
+        .get_command("M150")
+        .filter(|command_def| {
+            command_def.find_parameter("U").is_none() && command_def.find_parameter("G").is_some()
+        })
+        .map_or("U", |_| "G");
+    let text = command.with_color(color, green);
+    vec![ColorPresentation {
+        label: text.clone(),
+        text_edit: Some(TextEdit::new(code_range(idx, line, &command), text)),
+        additional_text_edits: None,
+    }]
+}
+
+fn code_range(idx: usize, line: &str, command: &LedCommand) -> Range {
+    Range::new(
+        Position::new(idx as u32, utf16_column(line, command.start)),
+        Position::new(idx as u32, utf16_column(line, command.end)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flavor::schema::{Flavor, FlavorFile};
+
+    fn flavor(name: &str) -> FlavorRegistry {
+        let mut registry = FlavorRegistry::new();
+        registry.add_embedded_prusa_flavor();
+        for content in [
+            include_str!("../../resources/flavors/marlin.gcode-flavor.toml"),
+            include_str!("../../resources/flavors/klipper.gcode-flavor.toml"),
+        ] {
+            let file: FlavorFile = toml::from_str(content).unwrap();
+            registry.add_flavor(Flavor::from(file));
+        }
+        registry.set_active_flavor(name);
+        registry
+    }
+
+    fn color(red: f32, green: f32, blue: f32) -> Color {
+        Color {
+            red,
+            green,
+            blue,
+            alpha: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_document_colors() {
+        let content = "G28\n  M150 R255 U128 B0 P255 ; orange\nSET_LED LED=status RED=0.2 BLUE=1\n";
+        let colors = document_colors(content);
+
+        assert_eq!(colors.len(), 2);
+        assert_eq!(colors[0].range.start, Position::new(1, 2));
+        assert_eq!(colors[0].range.end, Position::new(1, 24));
+        assert_eq!(colors[0].color, color(1.0, 128.0 / 255.0, 0.0));
+        assert_eq!(colors[1].color, color(0.2, 0.0, 1.0));
+    }
+
+    #[test]
+    fn test_parenthetical_comment_is_kept() {
+        let content = "M150 R255 (red) ; status\n";
+        let colors = document_colors(content);
+        assert_eq!(colors[0].range.end, Position::new(0, 9));
+
+        let presentations = color_presentations(
+            content,
+            colors[0].range,
+            color(0.0, 1.0, 0.0),
+            &flavor("marlin"),
+        );
+        assert_eq!(presentations[0].label, "M150 R0 U255 B0");
+        let edit = presentations[0].text_edit.as_ref().unwrap();
+        assert_eq!(edit.range, colors[0].range);
+    }
+
+    #[test]
+    fn test_presentation_scale_per_flavor() {
+        let content = "M150 P128 R10 B20 ; keep\nSET_LED LED=status RED=1 TRANSMIT=0\n";
+        let range = Range::new(Position::new(0, 0), Position::new(0, 17));
+
+        let marlin = color_presentations(content, range, color(1.0, 0.5, 0.0), &flavor("marlin"));
+        assert_eq!(marlin[0].label, "M150 P128 R255 U128 B0");
+        let edit = marlin[0].text_edit.as_ref().unwrap();
+        assert_eq!(edit.range.end, Position::new(0, 17));
+
+        let prusa = color_presentations(content, range, color(1.0, 0.5, 0.0), &flavor("prusa"));
+        assert_eq!(prusa[0].label, "M150 P128 R255 G128 B0");
+
+        let range = Range::new(Position::new(1, 0), Position::new(1, 35));
+        let klipper =
+            color_presentations(content, range, color(0.0, 0.25, 1.0), &flavor("klipper"));
+        assert_eq!(
+            klipper[0].label,
+            "SET_LED LED=status RED=0 GREEN=0.25 BLUE=1 TRANSMIT=0"
+        );
+    }
+}
@@ -0,0 +1,898 @@
+//! Completion
+//!
+//! Command names, parameters and parameter values for the text before the
+//! cursor. Works on the raw line, so Klipper `KEY=value` parameters complete
+//! like classic `X10` ones.
+
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionItemTag, CompletionList, CompletionTextEdit,
+    Documentation, InsertTextFormat, MarkupContent, MarkupKind, Position, Range, TextEdit,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::core::diagnostics::{byte_column, utf16_column};
+use crate::flavor::registry::FlavorRegistry;
+use crate::flavor::schema::{CommandDef, Flavor, ParameterDef, ParameterType};
+use crate::machine::MachineProfile;
+use crate::parser::{ParsedDocument, ParsedLine, parse_line};
+
+/// Shortest typed word also matched against descriptions
+const MIN_DESCRIPTION_QUERY: usize = 3;
+
+/// Lines at the end of a document searched for the slicer's filament type
+const FILAMENT_SCAN_LINES: usize = 1000;
+
+/// Typical hotend and bed temperatures of common filaments in Celsius
+const FILAMENT_TEMPERATURES: &[(&str, &[u32], &[u32])] = &[
+    ("PLA", &[200, 210, 215], &[55, 60]),
+    ("PETG", &[230, 240, 250], &[80, 85]),
+    ("ABS", &[240, 250, 255], &[100, 110]),
+    ("ASA", &[250, 260], &[100, 110]),
+    ("TPU", &[220, 230], &[40, 50]),
+    ("PA", &[260, 270, 285], &[90, 100]),
+    ("PC", &[270, 280, 290], &[110, 115]),
+];
+
+/// What completion needs besides the document
+pub struct CompletionContext<'a> {
+    /// Registry whose active flavor is the document's
+    pub flavor: &'a FlavorRegistry,
+    pub machine: Option<&'a MachineProfile>,
+    /// Filament type, e.g. `PLA`
+    pub filament: Option<&'a str>,
+}
+
+/// How a typed word matches an item, best first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Match {
+    Prefix,
+    Fuzzy,
+    Description,
+}
+
+/// Attached to command items so `completionItem/resolve` can document them
+#[derive(Debug, Serialize, Deserialize)]
+struct ResolveData {
+    flavor: String,
+    command: String,
+}
+
+/// The lines before the cursor, searched for values used earlier
+struct History<'a> {
+    parsed: &'a ParsedDocument,
+    /// Text of the lines before the cursor's line
+    above: &'a str,
+    line_idx: usize,
+}
+
+/// A candidate parameter value and why it is offered
+struct ValueCandidate {
+    value: String,
+    detail: String,
+    kind: CompletionItemKind,
+}
+
+/// Completion items at `position` in `content`, parsed as `parsed`
+///
+/// Lists at the start of a line are incomplete: they are filtered here,
+/// including by description, so the client asks again as the word grows.
+pub fn complete(
+    content: &str,
+    parsed: &ParsedDocument,
+    position: Position,
+    context: &CompletionContext,
+) -> CompletionList {
+    let Some(flavor) = context.flavor.get_active_flavor() else {
+        return CompletionList::default();
+    };
+    let line_idx = position.line as usize;
+    let line_start: usize = content
+        .split_inclusive('\n')
+        .take(line_idx)
+        .map(str::len)
+        .sum();
+    let above = &content[..line_start];
+    let line = content[line_start..].lines().next().unwrap_or("");
+    let line_up_to_cursor = &line[..byte_column(line, position.character)];
+    if line_up_to_cursor.contains([';', '(']) {
+        return CompletionList::default();
+    }
+
+    let words: Vec<&str> = line_up_to_cursor.split_whitespace().collect();
+    let is_after_space = line_up_to_cursor.is_empty() || line_up_to_cursor.ends_with(' ');
+
+    let items = match words.as_slice() {
+        [] => line_start_items(flavor, "", context),
+        [command, existing @ ..] if is_after_space => parameter_items(flavor, command, existing),
+        [word] => line_start_items(flavor, word, context),
+        [command, .., partial] => {
+            let start = line_up_to_cursor.len() - partial.len();
+            let range = Range::new(
+                Position::new(position.line, utf16_column(line, start)),
+                position,
+            );
+            let history = History {
+                parsed,
+                above,
+                line_idx,
+            };
+            value_items(&history, line, flavor, command, partial, range, context)
+        }
+    };
+    CompletionList {
+        is_incomplete: words.is_empty() || (words.len() == 1 && !is_after_space),
+        items,
+    }
+}
+
+/// Flavor an item was completed from, to resolve it against
+pub fn resolve_flavor(item: &CompletionItem) -> Option<String> {
+    let data = serde_json::from_value::<ResolveData>(item.data.clone()?).ok()?;
+    Some(data.flavor)
+}
+
+/// Fill in the documentation of a command item
+///
+/// Items are sent without it, since most are never looked at. `flavors`
+/// should hold the flavor named by [`resolve_flavor`].
+pub fn resolve(mut item: CompletionItem, flavors: &FlavorRegistry) -> CompletionItem {
+    let data = item
+        .data
+        .clone()
+        .and_then(|data| serde_json::from_value::<ResolveData>(data).ok());
+    let command_def = data.as_ref().and_then(|data| {
+        flavors
+            .get_flavor(&data.flavor)?
+            .commands
+            .get(&data.command)
+    });
+    if let Some(command_def) = command_def {
+        item.documentation = Some(Documentation::MarkupContent(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: command_documentation(command_def),
+        }));
+    }
+    item
+}
+
+/// Commands and snippets matching the word at the start of a line
+fn line_start_items(
+    flavor: &Flavor,
+    prefix: &str,
+    context: &CompletionContext,
+) -> Vec<CompletionItem> {
+    let mut completions = command_items(flavor, prefix, context);
+    completions.extend(snippet_items(prefix, context));
+    completions
+}
+
+/// Commands of the flavor, and tool changes for each of the machine's extruders
+fn command_items(
+    flavor: &Flavor,
+    prefix: &str,
+    context: &CompletionContext,
+) -> Vec<CompletionItem> {
+    let mut completions = Vec::new();
+
+    for (command_name, command_def) in &flavor.commands {
+        let descriptions = [
+            &command_def.description_short,
+            &command_def.description_long,
+        ];
+        let descriptions: Vec<&str> = descriptions
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect();
+        let Some(matched) = match_word(prefix, command_name, &descriptions) else {
+            continue;
+        };
+
+        let data = ResolveData {
+            flavor: flavor.name.clone(),
+            command: command_name.clone(),
+        };
+        completions.push(CompletionItem {
+            label: command_name.clone(),
+            kind: Some(CompletionItemKind::KEYWORD),
+            // Use short description for completion detail (concise summary)
+            detail: Some(
+                command_def
+                    .description_short
+                    .clone()
+                    .unwrap_or_else(|| "G-code command".to_string()),
+            ),
+            tags: command_def
+                .deprecated
+                .then(|| vec![CompletionItemTag::DEPRECATED]),
+            data: serde_json::to_value(data).ok(),
+            ..ranked(matched, prefix, command_name)
+        });
+    }
+
+    for tool in 0..extruder_count(context) {
+        let label = format!("T{}", tool);
+        if flavor.commands.contains_key(&label) {
+            continue;
+        }
+        if let Some(matched) = match_word(prefix, &label, &[]) {
+            completions.push(CompletionItem {
+                label: label.clone(),
+                kind: Some(CompletionItemKind::KEYWORD),
+                detail: Some(format!("Select tool {}", tool)),
+                ..ranked(matched, prefix, &label)
+            });
+        }
+    }
+
+    completions
+}
+
+/// Markdown documentation of a command and its parameters
+fn command_documentation(command_def: &CommandDef) -> String {
+    // Use long description for documentation (comprehensive info)
+    let mut documentation = command_def
+        .description_long
+        .clone()
+        .or_else(|| command_def.description_short.clone())
+        .unwrap_or_else(|| "G-code command".to_string());
+
+    // Add parameter information to documentation
+    if let Some(parameters) = &command_def.parameters
+        && !parameters.is_empty()
+    {
+        documentation.push_str("\n\n**Parameters:**");
+        for param in parameters {
+            documentation.push_str(&format!(
+                "\n- `{}`: {} ({:?}{})",
+                param.name,
+                param.description,
+                param.param_type,
+                if param.required {
+                    ", required"
+                } else {
+                    ", optional"
+                }
+            ));
+        }
+    }
+
+    documentation
+}
+
+/// Snippets of the flavor and the user matching `prefix`
+fn snippet_items(prefix: &str, context: &CompletionContext) -> Vec<CompletionItem> {
+    context
+        .flavor
+        .snippets()
+        .into_iter()
+        .filter_map(|snippet| {
+            let descriptions: Vec<&str> = snippet.description.as_deref().into_iter().collect();
+            let matched = match_word(prefix, &snippet.name, &descriptions)?;
+            Some(CompletionItem {
+                label: snippet.name.clone(),
+                kind: Some(CompletionItemKind::SNIPPET),
+                detail: snippet.description.clone(),
+                documentation: Some(Documentation::MarkupContent(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: format!("```gcode\n{}\n```", snippet_preview(&snippet.body)),
+                })),
+                insert_text: Some(snippet.body.clone()),
+                insert_text_format: Some(InsertTextFormat::SNIPPET),
+                ..ranked(matched, prefix, &snippet.name)
+            })
+        })
+        .collect()
+}
+
+/// Match a typed word against an item, ignoring case
+///
+/// The word matches if it starts the label, if its characters appear in the
+/// label in order (`m14` matches `M104`), or if a description contains it.
+fn match_word(word: &str, label: &str, descriptions: &[&str]) -> Option<Match> {
+    let word = word.to_lowercase();
+    let label = label.to_lowercase();
+    if label.starts_with(&word) {
+        return Some(Match::Prefix);
+    }
+
+    let mut chars = label.chars();
+    if word.chars().all(|c| chars.any(|l| l == c)) {
+        return Some(Match::Fuzzy);
+    }
+
+    let described = word.len() >= MIN_DESCRIPTION_QUERY
+        && descriptions
+            .iter()
+            .any(|description| description.to_lowercase().contains(&word));
+    described.then_some(Match::Description)
+}
+
+/// Sort and filter text of an item matched by `word`
+///
+/// Clients filter by label on their own; items matched otherwise are given
+/// the typed word as filter text so they are kept.
+fn ranked(matched: Match, word: &str, label: &str) -> CompletionItem {
+    CompletionItem {
+        sort_text: Some(format!("{}{}", matched as u8, label)),
+        filter_text: (matched != Match::Prefix).then(|| word.to_string()),
+        ..Default::default()
+    }
+}
+
+/// Snippet body with placeholders replaced by their defaults
+fn snippet_preview(body: &str) -> String {
+    let mut preview = String::new();
+    let mut rest = body;
+    while let Some(start) = rest.find('$') {
+        preview.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        if let Some(placeholder) = rest.strip_prefix('{')
+            && let Some(end) = placeholder.find('}')
+        {
+            // `${1:default}` shows the default, `${1}` nothing
+            let inner = &placeholder[..end];
+            preview.push_str(inner.split_once(':').map_or("", |(_, default)| default));
+            rest = &placeholder[end + 1..];
+        } else {
+            // `$1` and `$0` are tab stops without text
+            rest = rest.trim_start_matches(|c: char| c.is_ascii_digit());
+        }
+    }
+    preview.push_str(rest);
+    preview
+}
+
+/// Parameters of a command not yet on the line
+fn parameter_items(flavor: &Flavor, command: &str, existing: &[&str]) -> Vec<CompletionItem> {
+    let command_name = command.to_uppercase();
+    let Some(parameters) = flavor
+        .commands
+        .get(&command_name)
+        .and_then(|command_def| command_def.parameters.as_ref())
+    else {
+        return Vec::new();
+    };
+
+    // Parse existing parameters to avoid duplicates
+    let existing_params: Vec<String> = existing
+        .iter()
+        .map(|word| parameter_word(word).0.to_uppercase())
+        .collect();
+
+    parameters
+        .iter()
+        .filter(|param| !existing_params.contains(&param.name.to_uppercase()))
+        .map(|param| CompletionItem {
+            label: param.name.clone(),
+            kind: Some(CompletionItemKind::PROPERTY),
+            detail: Some(format!("{:?}", param.param_type)),
+            documentation: Some(Documentation::String(param.description.clone())),
+            sort_text: Some(format!(
+                "{}{}",
+                if param.required { "0" } else { "1" },
+                param.name
+            )),
+            insert_text: Some(if is_key_value(&param.name) {
+                // Klipper style, the value is completed after the `=`
+                format!("{}=", param.name)
+            } else {
+                match param.param_type {
+                    ParameterType::Float => format!("{}0.0", param.name),
+                    ParameterType::Int => format!("{}0", param.name),
+                    ParameterType::Bool => param.name.clone(),
+                    ParameterType::String => format!("{}\"\"", param.name),
+                }
+            }),
+            insert_text_format: Some(InsertTextFormat::SNIPPET),
+            preselect: Some(param.required),
+            filter_text: Some(param.name.clone()),
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// Values for the parameter being typed on `line`, replacing it in `range`
+fn value_items(
+    history: &History,
+    line: &str,
+    flavor: &Flavor,
+    command: &str,
+    partial: &str,
+    range: Range,
+    context: &CompletionContext,
+) -> Vec<CompletionItem> {
+    let (name, value) = parameter_word(partial);
+    if name.is_empty() || !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return Vec::new();
+    }
+    // The parameter as typed, with its `=` if any
+    let typed_name = &partial[..partial.len() - value.len()];
+
+    let command_name = command.to_uppercase();
+    let param_def = flavor
+        .commands
+        .get(&command_name)
+        .and_then(|command_def| command_def.find_parameter(name));
+
+    let mut candidates = Vec::new();
+    if let Some((last, last_line)) = last_used_value(history, &command_name, name) {
+        candidates.push(ValueCandidate {
+            value: last,
+            detail: format!("Last used on line {}", last_line + 1),
+            kind: CompletionItemKind::VALUE,
+        });
+    }
+    if let Some(param_def) = param_def {
+        candidates.extend(defined_values(&command_name, param_def, line, context));
+    }
+
+    // The same value may come from several sources; keep the first
+    let mut seen = Vec::new();
+    candidates.retain(|candidate| {
+        let new = !seen.contains(&candidate.value);
+        seen.push(candidate.value.clone());
+        new
+    });
+
+    candidates
+        .into_iter()
+        .enumerate()
+        .map(|(idx, candidate)| {
+            let text = format!("{}{}", typed_name, candidate.value);
+            CompletionItem {
+                label: candidate.value,
+                kind: Some(candidate.kind),
+                detail: Some(candidate.detail),
+                sort_text: Some(format!("{:03}", idx)),
+                filter_text: Some(text.clone()),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(range, text))),
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+/// Enum members, tool indices and common temperatures for a parameter
+fn defined_values(
+    command_name: &str,
+    param_def: &ParameterDef,
+    line: &str,
+    context: &CompletionContext,
+) -> Vec<ValueCandidate> {
+    let mut candidates = Vec::new();
+
+    if let Some(values) = param_def
+        .constraints
+        .as_ref()
+        .and_then(|constraints| constraints.enum_values.as_ref())
+    {
+        candidates.extend(values.iter().map(|value| ValueCandidate {
+            value: value.clone(),
+            detail: "Allowed value".to_string(),
+            kind: CompletionItemKind::ENUM_MEMBER,
+        }));
+    }
+
+    if param_def.param_type != ParameterType::Bool
+        && matches!(
+            param_def.name.to_uppercase().as_str(),
+            "T" | "TOOL" | "EXTRUDER"
+        )
+    {
+        let klipper = is_key_value(&param_def.name);
+        candidates.extend((0..extruder_count(context)).map(|tool| ValueCandidate {
+            // Klipper names extruders `extruder`, `extruder1`, ...
+            value: match (klipper, tool) {
+                (true, 0) => "extruder".to_string(),
+                (true, _) => format!("extruder{}", tool),
+                (false, _) => tool.to_string(),
+            },
+            detail: format!("Tool {}", tool),
+            kind: CompletionItemKind::ENUM_MEMBER,
+        }));
+    }
+
+    let description = param_def.description.to_lowercase();
+    if param_def.unit() == Some("°C") || description.contains("temperature") {
+        let bed = description.contains("bed")
+            || matches!(command_name, "M140" | "M190")
+            || line.to_lowercase().contains("heater_bed");
+        candidates.extend(temperatures(bed, context));
+    }
+
+    candidates
+}
+
+/// Common temperatures of the selected filament, or of all known filaments
+///
+/// Temperatures above the machine's limits are left out.
+fn temperatures(bed: bool, context: &CompletionContext) -> Vec<ValueCandidate> {
+    let selected: Vec<_> = FILAMENT_TEMPERATURES
+        .iter()
+        .filter(|(name, _, _)| {
+            context
+                .filament
+                .is_some_and(|f| f.eq_ignore_ascii_case(name))
+        })
+        .collect();
+    let filaments = if selected.is_empty() {
+        FILAMENT_TEMPERATURES.iter().collect()
+    } else {
+        selected
+    };
+
+    let limits = context
+        .machine
+        .and_then(|machine| machine.temperatures.as_ref());
+    let limit = limits.and_then(|limits| {
+        if bed {
+            limits.max_bed
+        } else {
+            limits.max_hotend
+        }
+    });
+
+    let mut candidates: Vec<ValueCandidate> = Vec::new();
+    for (name, hotend, bed_temperatures) in filaments {
+        let values = if bed { *bed_temperatures } else { *hotend };
+        for value in values {
+            if limit.is_some_and(|limit| f64::from(*value) > limit) {
+                continue;
+            }
+            let value = value.to_string();
+            match candidates.iter_mut().find(|c| c.value == value) {
+                Some(candidate) => candidate.detail.push_str(&format!(", {}", name)),
+                None => candidates.push(ValueCandidate {
+                    value,
+                    detail: format!("{} °C: {}", if bed { "Bed" } else { "Hotend" }, name),
+                    kind: CompletionItemKind::VALUE,
+                }),
+            }
+        }
+    }
+    candidates
+}
+
+/// The value a parameter last had on an earlier line with the same command
+///
+/// Walks back from the cursor over the parsed lines. The parser doesn't
+/// split Klipper commands or `NAME=value` parameters, so those are read from
+/// the text above instead, also walking back.
+fn last_used_value(history: &History, command_name: &str, name: &str) -> Option<(String, usize)> {
+    let parsed_whole = matches!(
+        parse_line(command_name),
+        ParsedLine::Command(cmd) if cmd.name == command_name
+    );
+    if is_key_value(name) || !parsed_whole {
+        let lines = history.above.lines().rev();
+        return lines
+            .zip((0..history.line_idx).rev())
+            .find_map(|(line, idx)| {
+                let code = line.split(';').next().unwrap_or("");
+                let mut words = code.split_whitespace();
+                if !words.next()?.eq_ignore_ascii_case(command_name) {
+                    return None;
+                }
+                words
+                    .map(parameter_word)
+                    .find(|(param, value)| param.eq_ignore_ascii_case(name) && !value.is_empty())
+                    .map(|(_, value)| (value.to_string(), idx))
+            });
+    }
+
+    let letter = name.chars().next()?;
+    (0..history.line_idx.min(history.parsed.len()))
+        .rev()
+        .find_map(|idx| {
+            let ParsedLine::Command(cmd) = history.parsed.get(idx)? else {
+                return None;
+            };
+            if !cmd.name.eq_ignore_ascii_case(command_name) {
+                return None;
+            }
+            cmd.parameters
+                .iter()
+                .find(|param| param.letter.eq_ignore_ascii_case(&letter) && !param.value.is_empty())
+                .map(|param| (param.value.to_string(), idx))
+        })
+}
+
+/// Split a parameter word into its name and value: `X10`, `TARGET=200`
+fn parameter_word(word: &str) -> (&str, &str) {
+    match word.split_once('=') {
+        Some((name, value)) => (name, value),
+        None => word.split_at(word.chars().next().map_or(0, char::len_utf8)),
+    }
+}
+
+/// Klipper-style parameters have multi-letter names and take `NAME=value`
+fn is_key_value(name: &str) -> bool {
+    name.len() > 1
+}
+
+fn extruder_count(context: &CompletionContext) -> u32 {
+    context
+        .machine
+        .map_or(1, |machine| machine.machine.extruder_count)
+}
+
+/// Filament type the slicer recorded, e.g. `; filament_type = PETG`
+///
+/// PrusaSlicer writes its configuration at the end of the file; with several
+/// extruders the types are separated by `;`, and the first one is used.
+pub fn detect_filament(content: &str) -> Option<String> {
+    content
+        .lines()
+        .rev()
+        .take(FILAMENT_SCAN_LINES)
+        .find_map(|line| {
+            let setting = line.trim_start().strip_prefix(';')?.trim_start();
+            let value = setting.strip_prefix("filament_type")?.trim_start();
+            let value = value.strip_prefix('=')?.split(';').next()?.trim();
+            (!value.is_empty()).then(|| value.to_string())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flavor::schema::FlavorFile;
+    use crate::machine::MachineRegistry;
+
+    fn registry(flavor: &str) -> FlavorRegistry {
+        let mut registry = FlavorRegistry::new();
+        registry.add_embedded_prusa_flavor();
+        let klipper = include_str!("../../resources/flavors/klipper.gcode-flavor.toml");
+        let file: FlavorFile = toml::from_str(klipper).unwrap();
+        registry.add_flavor(Flavor::from(file));
+        registry.set_active_flavor(flavor);
+        registry
+    }
+
+    fn labels(content: &str, line: u32, filament: Option<&str>, flavor: &str) -> Vec<String> {
+        let registry = registry(flavor);
+        let mut machines = MachineRegistry::new();
+        machines.add_embedded_profiles();
+        let context = CompletionContext {
+            flavor: &registry,
+            machine: machines.get_profile("prusa-mk4"),
+            filament,
+        };
+        let column = content.lines().nth(line as usize).unwrap_or("").len() as u32;
+        complete(
+            content,
+            &ParsedDocument::parse(content),
+            Position::new(line, column),
+            &context,
+        )
+        .items
+        .into_iter()
+        .map(|item| item.label)
+        .collect()
+    }
+
+    #[test]
+    fn test_temperature_values() {
+        let hotend = labels("M104 S", 0, Some("petg"), "prusa");
+        assert_eq!(hotend, vec!["230", "240", "250"]);
+
+        let bed = labels("M140 S1", 0, None, "prusa");
+        assert!(bed.contains(&"110".to_string()));
+        assert!(bed.contains(&"55".to_string()));
+
+        // Temperatures the machine cannot reach are left out
+        let registry = registry("prusa");
+        let mut machines = MachineRegistry::new();
+        machines.add_embedded_profiles();
+        let mut machine = machines.get_profile("prusa-mk4").unwrap().clone();
+        if let Some(limits) = machine.temperatures.as_mut() {
+            limits.max_bed = Some(100.0);
+        }
+        let context = CompletionContext {
+            flavor: &registry,
+            machine: Some(&machine),
+            filament: Some("PC"),
+        };
+        assert!(
+            complete(
+                "M190 S",
+                &ParsedDocument::parse("M190 S"),
+                Position::new(0, 6),
+                &context
+            )
+            .items
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_last_used_value_first() {
+        let content = "M104 S222\nG1 X1\nM104 S";
+        let registry = registry("prusa");
+        let context = CompletionContext {
+            flavor: &registry,
+            machine: None,
+            filament: Some("PLA"),
+        };
+        let items = complete(
+            content,
+            &ParsedDocument::parse(content),
+            Position::new(2, 6),
+            &context,
+        )
+        .items;
+
+        assert_eq!(items[0].label, "222");
+        assert_eq!(items[0].detail.as_deref(), Some("Last used on line 1"));
+        let Some(CompletionTextEdit::Edit(edit)) = &items[0].text_edit else {
+            panic!("expected a text edit");
+        };
+        assert_eq!(edit.new_text, "S222");
+        assert_eq!(edit.range.start, Position::new(2, 5));
+    }
+
+    #[test]
+    fn test_tool_values() {
+        assert_eq!(labels("M104 S200 T", 0, None, "prusa"), vec!["0"]);
+        assert_eq!(
+            labels("ACTIVATE_EXTRUDER EXTRUDER=", 0, None, "klipper"),
+            vec!["extruder"]
+        );
+    }
+
+    #[test]
+    fn test_klipper_parameters() {
+        let registry = registry("klipper");
+        let context = CompletionContext {
+            flavor: &registry,
+            machine: None,
+            filament: Some("PLA"),
+        };
+        let content = "SET_HEATER_TEMPERATURE HEATER=heater_bed ";
+        let items = complete(
+            content,
+            &ParsedDocument::parse(content),
+            Position::new(0, content.len() as u32),
+            &context,
+        )
+        .items;
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].insert_text.as_deref(), Some("TARGET="));
+
+        let content = "SET_HEATER_TEMPERATURE HEATER=heater_bed TARGET=";
+        let items = complete(
+            content,
+            &ParsedDocument::parse(content),
+            Position::new(0, content.len() as u32),
+            &context,
+        )
+        .items;
+        let labels: Vec<&str> = items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(labels, vec!["55", "60"]);
+    }
+
+    #[test]
+    fn test_enum_values() {
+        let labels = labels("BED_MESH_CALIBRATE METHOD=", 0, None, "klipper");
+        assert_eq!(labels, vec!["automatic", "manual", "scan", "rapid_scan"]);
+    }
+
+    #[test]
+    fn test_snippets_at_line_start() {
+        let mut snippets = labels("heat-", 0, None, "prusa");
+        snippets.retain(|label| label.contains('-'));
+        assert_eq!(snippets, vec!["heat-wait"]);
+
+        let registry = registry("klipper");
+        let context = CompletionContext {
+            flavor: &registry,
+            machine: None,
+            filament: None,
+        };
+        let items = complete(
+            "",
+            &ParsedDocument::parse(""),
+            Position::new(0, 0),
+            &context,
+        )
+        .items;
+        let cool_down = items
+            .iter()
+            .find(|item| item.label == "cool-down")
+            .expect("cool-down snippet");
+        assert_eq!(cool_down.kind, Some(CompletionItemKind::SNIPPET));
+        assert!(
+            complete(
+                "G28 cool",
+                &ParsedDocument::parse("G28 cool"),
+                Position::new(0, 8),
+                &context
+            )
+            .items
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_fuzzy_command_match() {
+        let mut matched = labels("m10", 0, None, "prusa");
+        matched.retain(|label| label.starts_with("M10"));
+        assert!(matched.contains(&"M104".to_string()));
+        assert!(matched.contains(&"M109".to_string()));
+
+        assert!(labels("m19", 0, None, "prusa").contains(&"M190".to_string()));
+        let by_description = labels("heat", 0, None, "prusa");
+        assert!(by_description.contains(&"heat-wait".to_string()));
+        assert!(by_description.contains(&"M140".to_string()));
+        assert!(!labels("zzz", 0, None, "prusa").contains(&"G1".to_string()));
+    }
+
+    #[test]
+    fn test_resolve_documentation() {
+        let registry = registry("prusa");
+        let context = CompletionContext {
+            flavor: &registry,
+            machine: None,
+            filament: None,
+        };
+        let list = complete(
+            "M104",
+            &ParsedDocument::parse("M104"),
+            Position::new(0, 4),
+            &context,
+        );
+        assert!(list.is_incomplete);
+
+        let item = list
+            .items
+            .into_iter()
+            .find(|item| item.label == "M104")
+            .expect("M104 item");
+        assert!(item.documentation.is_none());
+        assert_eq!(item.sort_text.as_deref(), Some("0M104"));
+
+        let Some(Documentation::MarkupContent(markup)) = resolve(item, &registry).documentation
+        else {
+            panic!("expected documentation");
+        };
+        assert!(markup.value.contains("**Parameters:**"));
+
+        // Items carry their flavor, so they resolve against it
+        let klipper = registry.snapshot(Some("klipper"));
+        let context = CompletionContext {
+            flavor: &klipper,
+            machine: None,
+            filament: None,
+        };
+        let item = complete(
+            "SET_HEATER_T",
+            &ParsedDocument::parse("SET_HEATER_T"),
+            Position::new(0, 12),
+            &context,
+        )
+        .items
+        .into_iter()
+        .find(|item| item.label == "SET_HEATER_TEMPERATURE")
+        .expect("SET_HEATER_TEMPERATURE item");
+        let flavor = resolve_flavor(&item);
+        assert_eq!(flavor.as_deref(), Some("klipper"));
+        let resolved = resolve(item, &registry.snapshot(flavor.as_deref()));
+        assert!(resolved.documentation.is_some());
+    }
+
+    #[test]
+    fn test_snippet_preview() {
+        assert_eq!(
+            snippet_preview("M104 S${1:215}\nM109 S${1}\n$0"),
+            "M104 S215\nM109 S\n"
+        );
+    }
+
+    #[test]
+    fn test_detect_filament() {
+        let content = "G28\n; filament_type = PETG;PLA\n";
+        assert_eq!(detect_filament(content).as_deref(), Some("PETG"));
+        assert_eq!(detect_filament("; filament_types = x\n"), None);
+    }
+}
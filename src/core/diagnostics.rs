@@ -1,19 +1,701 @@
 //! Diagnostic Provider
 //!
-//! Clean diagnostic generation.
+//! Schedules validation of open documents and publishes the results to a
+//! [`DiagnosticSink`]. The language server publishes through its client;
+//! tests can collect diagnostics directly.
 
-/// Diagnostic provider (placeholder)
-#[derive(Debug)]
-pub struct DiagnosticProvider;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
 
-impl DiagnosticProvider {
-    pub fn new() -> Self {
-        Self
+use lsp_types::{
+    DiagnosticSeverity, DiagnosticTag, DocumentDiagnosticReportKind, FullDocumentDiagnosticReport,
+    NumberOrString, Position, Range, UnchangedDocumentDiagnosticReport, Url,
+};
+use tokio::sync::Mutex;
+
+use crate::core::document::{DocumentConfig, DocumentManager};
+use crate::flavor::FlavorRegistry;
+use crate::machine::MachineRegistry;
+use crate::parser::ParsedDocument;
+use crate::validation::engine::{
+    Diagnostic, Generation, Severity, Tag, ValidationOptions, validate_parsed_with,
+};
+
+/// Quiet period after an edit before diagnostics are recomputed
+pub const DIAGNOSTICS_DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Destination of published diagnostics
+pub trait DiagnosticSink: Clone + Send + Sync + 'static {
+    fn publish(
+        &self,
+        uri: Url,
+        diagnostics: Vec<lsp_types::Diagnostic>,
+        version: Option<i32>,
+    ) -> impl Future<Output = ()> + Send;
+}
+
+/// Validates documents and publishes their diagnostics
+///
+/// Cloning is cheap and shares all state, so scheduled runs can hold a copy.
+#[derive(Clone)]
+pub struct DiagnosticProvider<S> {
+    sink: S,
+    documents: Arc<Mutex<DocumentManager>>,
+    flavor_registry: Arc<Mutex<FlavorRegistry>>,
+    machine_registry: Arc<Mutex<MachineRegistry>>,
+    /// Validation generation counter of each document
+    generations: Arc<Mutex<HashMap<Url, Arc<AtomicU64>>>>,
+    debounce: Duration,
+    /// Whether diagnostics are pushed; off when the client pulls them
+    push: Arc<AtomicBool>,
+    /// Bumped when flavors or settings change, invalidating result IDs
+    epoch: Arc<AtomicU64>,
+    /// Last result ID of each file pulled from disk
+    files: Arc<Mutex<HashMap<Url, CachedFile>>>,
+}
+
+/// Modification time and size of a file on disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl FileStamp {
+    pub fn new(metadata: &std::fs::Metadata) -> Self {
+        Self {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+        }
+    }
+}
+
+/// Result ID handed out for a file as of one stamp and epoch
+struct CachedFile {
+    stamp: FileStamp,
+    epoch: u64,
+    result_id: String,
+}
+
+/// What validating a document needs, copied out of the document manager
+struct Snapshot {
+    content: Arc<str>,
+    parsed: Arc<ParsedDocument>,
+    config: DocumentConfig,
+    version: i32,
+}
+
+impl<S: DiagnosticSink> DiagnosticProvider<S> {
+    pub fn new(
+        sink: S,
+        documents: Arc<Mutex<DocumentManager>>,
+        flavor_registry: Arc<Mutex<FlavorRegistry>>,
+        machine_registry: Arc<Mutex<MachineRegistry>>,
+    ) -> Self {
+        Self {
+            sink,
+            documents,
+            flavor_registry,
+            machine_registry,
+            generations: Arc::new(Mutex::new(HashMap::new())),
+            debounce: DIAGNOSTICS_DEBOUNCE,
+            push: Arc::new(AtomicBool::new(true)),
+            epoch: Arc::new(AtomicU64::new(0)),
+            files: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Switch between pushing diagnostics and letting the client pull them
+    pub fn set_push_enabled(&self, enabled: bool) {
+        self.push.store(enabled, Ordering::SeqCst);
+    }
+
+    pub fn push_enabled(&self) -> bool {
+        self.push.load(Ordering::SeqCst)
+    }
+
+    /// Make every result ID handed out so far stale
+    ///
+    /// Called when something outside the documents, like a flavor or the
+    /// settings, may have changed their diagnostics.
+    pub fn invalidate(&self) {
+        self.epoch.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Use a different quiet period for [`schedule`](Self::schedule)
+    pub fn with_debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    /// Start a new validation generation, making earlier runs stale
+    async fn next_generation(&self, uri: &Url) -> Generation {
+        let counter = Arc::clone(
+            self.generations
+                .lock()
+                .await
+                .entry(uri.clone())
+                .or_default(),
+        );
+        Generation::next(&counter)
+    }
+
+    /// Validate a document and publish its diagnostics
+    ///
+    /// Results are dropped if a newer run for the same document has started
+    /// or the document changed meanwhile. Does nothing if the client pulls
+    /// diagnostics instead.
+    pub async fn publish(&self, uri: Url) {
+        if !self.push_enabled() {
+            return;
+        }
+        let generation = self.next_generation(&uri).await;
+
+        let Some(snapshot) = self.snapshot(&uri).await else {
+            return;
+        };
+        let version = snapshot.version;
+        let Some(diagnostics) = self.validate(snapshot, Some(generation.clone())).await else {
+            return;
+        };
+
+        // Results for an edited or closed document are out of date
+        let current_version = self.documents.lock().await.get(&uri).map(|d| d.version);
+        if !generation.is_current() || current_version != Some(version) {
+            return;
+        }
+
+        self.sink.publish(uri, diagnostics, Some(version)).await;
+    }
+
+    /// Answer a diagnostic pull for an open document
+    ///
+    /// Returns `Unchanged` if `previous_result_id` still matches the document
+    /// and its configuration, and `None` if the document is not open.
+    pub async fn pull(
+        &self,
+        uri: &Url,
+        previous_result_id: Option<&str>,
+    ) -> Option<(DocumentDiagnosticReportKind, i32)> {
+        let snapshot = self.snapshot(uri).await?;
+        let version = snapshot.version;
+        Some((self.report(snapshot, previous_result_id).await, version))
+    }
+
+    /// Answer a diagnostic pull for a file that is not open
+    pub async fn pull_content(
+        &self,
+        content: String,
+        config: DocumentConfig,
+        previous_result_id: Option<&str>,
+    ) -> DocumentDiagnosticReportKind {
+        let parsed = Arc::new(ParsedDocument::parse(&content));
+        let snapshot = Snapshot {
+            content: content.into(),
+            parsed,
+            config,
+            version: 0,
+        };
+        self.report(snapshot, previous_result_id).await
+    }
+
+    /// Answer a diagnostic pull for a file that is not open from its stamp
+    ///
+    /// Returns `Unchanged` if the client already has the report computed for
+    /// the file as of `stamp`, and `None` if the file has to be read again.
+    pub async fn pull_cached(
+        &self,
+        uri: &Url,
+        stamp: FileStamp,
+        previous_result_id: Option<&str>,
+    ) -> Option<DocumentDiagnosticReportKind> {
+        let epoch = self.epoch.load(Ordering::SeqCst);
+        let files = self.files.lock().await;
+        let cached = files.get(uri)?;
+        if cached.stamp != stamp
+            || cached.epoch != epoch
+            || previous_result_id != Some(cached.result_id.as_str())
+        {
+            return None;
+        }
+        Some(
+            UnchangedDocumentDiagnosticReport {
+                result_id: cached.result_id.clone(),
+            }
+            .into(),
+        )
+    }
+
+    /// Answer a diagnostic pull for a file read from disk, remembering its
+    /// result ID for [`pull_cached`](Self::pull_cached)
+    pub async fn pull_file(
+        &self,
+        uri: Url,
+        stamp: FileStamp,
+        content: String,
+        config: DocumentConfig,
+        previous_result_id: Option<&str>,
+    ) -> DocumentDiagnosticReportKind {
+        let epoch = self.epoch.load(Ordering::SeqCst);
+        let report = self.pull_content(content, config, previous_result_id).await;
+        let result_id = match &report {
+            DocumentDiagnosticReportKind::Full(full) => full.result_id.clone(),
+            DocumentDiagnosticReportKind::Unchanged(unchanged) => Some(unchanged.result_id.clone()),
+        };
+        if let Some(result_id) = result_id {
+            let cached = CachedFile {
+                stamp,
+                epoch,
+                result_id,
+            };
+            self.files.lock().await.insert(uri, cached);
+        }
+        report
+    }
+
+    /// Forget the result IDs of files other than `uris`
+    pub async fn retain_files(&self, uris: &HashSet<Url>) {
+        self.files.lock().await.retain(|uri, _| uris.contains(uri));
+    }
+
+    async fn report(
+        &self,
+        snapshot: Snapshot,
+        previous_result_id: Option<&str>,
+    ) -> DocumentDiagnosticReportKind {
+        let epoch = self.epoch.load(Ordering::SeqCst);
+        let result_id = result_id(&snapshot.content, &snapshot.config, epoch);
+        if previous_result_id == Some(result_id.as_str()) {
+            return UnchangedDocumentDiagnosticReport { result_id }.into();
+        }
+
+        let items = self.validate(snapshot, None).await.unwrap_or_default();
+        FullDocumentDiagnosticReport {
+            result_id: Some(result_id),
+            items,
+        }
+        .into()
+    }
+
+    /// Copy what validating an open document needs, without its lock
+    async fn snapshot(&self, uri: &Url) -> Option<Snapshot> {
+        let docs = self.documents.lock().await;
+        let doc_state = docs.get(uri)?;
+        Some(Snapshot {
+            content: Arc::clone(&doc_state.content),
+            parsed: Arc::clone(&doc_state.parsed),
+            config: doc_state.config.clone(),
+            version: doc_state.version,
+        })
+    }
+
+    /// Validate a snapshot and convert the results to LSP diagnostics
+    ///
+    /// Validation runs on a blocking thread against a snapshot of the flavor,
+    /// without holding any lock. Returns `None` if the run was cancelled.
+    /// Files excluded by the project configuration get no diagnostics.
+    async fn validate(
+        &self,
+        snapshot: Snapshot,
+        generation: Option<Generation>,
+    ) -> Option<Vec<lsp_types::Diagnostic>> {
+        let Snapshot {
+            content,
+            parsed,
+            config,
+            ..
+        } = snapshot;
+        if !config.included {
+            return Some(Vec::new());
+        }
+
+        let flavor = self
+            .flavor_registry
+            .lock()
+            .await
+            .snapshot(config.flavor_name.as_deref());
+        let machine = match config.machine_name.as_deref() {
+            Some(name) => self
+                .machine_registry
+                .lock()
+                .await
+                .get_profile(name)
+                .cloned(),
+            None => None,
+        };
+
+        let validation = tokio::task::spawn_blocking(move || {
+            let options = ValidationOptions {
+                machine: machine.as_ref(),
+                severities: Some(&config.settings.rules),
+                generation: generation.as_ref(),
+            };
+            let result = validate_parsed_with(&content, &parsed, &flavor, &options);
+            let cancelled = generation.is_some_and(|generation| !generation.is_current());
+            (content, result, cancelled)
+        });
+        let (content, validation_result, cancelled) = match validation.await {
+            Ok(run) => run,
+            Err(e) => {
+                log::warn!("Validation failed: {}", e);
+                return None;
+            }
+        };
+        if cancelled {
+            return None;
+        }
+
+        let lines: Vec<&str> = content.lines().collect();
+        let diagnostics = validation_result
+            .diagnostics
+            .into_iter()
+            .map(|diagnostic| {
+                let line = lines.get(diagnostic.line - 1).copied().unwrap_or("");
+                to_lsp_diagnostic(diagnostic, line)
+            })
+            .collect();
+        Some(diagnostics)
+    }
+
+    /// Cancel pending runs for a document and clear its diagnostics
+    ///
+    /// Used when a document is closed; the empty list carries no version.
+    pub async fn clear(&self, uri: Url) {
+        self.next_generation(&uri).await;
+        self.generations.lock().await.remove(&uri);
+        if self.push_enabled() {
+            self.sink.publish(uri, Vec::new(), None).await;
+        }
+    }
+
+    /// Publish diagnostics once edits have paused for the debounce period
+    pub async fn schedule(&self, uri: Url) {
+        if !self.push_enabled() {
+            return;
+        }
+        let generation = self.next_generation(&uri).await;
+
+        let provider = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(provider.debounce).await;
+            if generation.is_current() {
+                provider.publish(uri).await;
+            }
+        });
+    }
+}
+
+/// Identify the diagnostics of a text under a configuration
+///
+/// The same text and configuration always give the same ID, so a client
+/// pulling again after unrelated activity gets an `Unchanged` report.
+fn result_id(content: &str, config: &DocumentConfig, epoch: u64) -> String {
+    let mut hasher = DefaultHasher::new();
+    epoch.hash(&mut hasher);
+    content.hash(&mut hasher);
+    format!("{:?}", config).hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// Convert a validation diagnostic on `line` to an LSP diagnostic
+pub fn to_lsp_diagnostic(diagnostic: Diagnostic, line: &str) -> lsp_types::Diagnostic {
+    let severity = match diagnostic.severity {
+        Severity::Error => DiagnosticSeverity::ERROR,
+        Severity::Warning => DiagnosticSeverity::WARNING,
+        Severity::Info => DiagnosticSeverity::INFORMATION,
+    };
+
+    // Point at the span if there is one, otherwise at the whole line
+    let line_num = (diagnostic.line - 1) as u32;
+    let (start, end) = match &diagnostic.span {
+        Some(span) => (span.start, span.end),
+        None => (0, line.len()),
+    };
+
+    let tags: Vec<DiagnosticTag> = diagnostic
+        .tags
+        .iter()
+        .map(|tag| match tag {
+            Tag::Deprecated => DiagnosticTag::DEPRECATED,
+        })
+        .collect();
+
+    lsp_types::Diagnostic::new(
+        Range::new(
+            Position::new(line_num, utf16_column(line, start)),
+            Position::new(line_num, utf16_column(line, end)),
+        ),
+        Some(severity),
+        Some(NumberOrString::String(diagnostic.rule.code().to_string())),
+        Some("gcode-ls".to_string()),
+        diagnostic.message,
+        None,
+        if tags.is_empty() { None } else { Some(tags) },
+    )
+}
+
+/// Byte offset of a UTF-16 column in `line`, clamped to the line
+pub(crate) fn byte_column(line: &str, column: u32) -> usize {
+    let mut utf16 = 0;
+    for (idx, c) in line.char_indices() {
+        if utf16 >= column as usize {
+            return idx;
+        }
+        utf16 += c.len_utf16();
     }
+    line.len()
+}
+
+/// Convert a byte offset in a line to a UTF-16 column
+pub(crate) fn utf16_column(line: &str, byte_offset: usize) -> u32 {
+    line.get(..byte_offset)
+        .unwrap_or(line)
+        .encode_utf16()
+        .count() as u32
 }
 
-impl Default for DiagnosticProvider {
-    fn default() -> Self {
-        Self::new()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Published = (Url, Vec<lsp_types::Diagnostic>, Option<i32>);
+
+    /// Sink collecting everything published
+    #[derive(Clone, Default)]
+    struct RecordingSink(Arc<std::sync::Mutex<Vec<Published>>>);
+
+    impl DiagnosticSink for RecordingSink {
+        async fn publish(
+            &self,
+            uri: Url,
+            diagnostics: Vec<lsp_types::Diagnostic>,
+            version: Option<i32>,
+        ) {
+            self.0.lock().unwrap().push((uri, diagnostics, version));
+        }
+    }
+
+    fn provider(sink: &RecordingSink) -> DiagnosticProvider<RecordingSink> {
+        let mut flavors = FlavorRegistry::new();
+        flavors.add_embedded_prusa_flavor();
+        flavors.set_active_flavor("prusa");
+
+        DiagnosticProvider::new(
+            sink.clone(),
+            Arc::new(Mutex::new(DocumentManager::new())),
+            Arc::new(Mutex::new(flavors)),
+            Arc::new(Mutex::new(MachineRegistry::new())),
+        )
+        .with_debounce(Duration::from_millis(20))
+    }
+
+    #[tokio::test]
+    async fn test_publish_open_document() {
+        let sink = RecordingSink::default();
+        let provider = provider(&sink);
+        let uri = Url::parse("file:///part.gcode").unwrap();
+        provider
+            .documents
+            .lock()
+            .await
+            .open(uri.clone(), "gcode", 3, "G28\nG999 X10\n".into());
+
+        provider.publish(uri.clone()).await;
+        provider
+            .publish(Url::parse("file:///closed.gcode").unwrap())
+            .await;
+
+        let published = sink.0.lock().unwrap();
+        assert_eq!(published.len(), 1);
+        let (published_uri, diagnostics, version) = &published[0];
+        assert_eq!(published_uri, &uri);
+        assert_eq!(*version, Some(3));
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].range.start.line, 1);
+        assert_eq!(
+            diagnostics[0].code,
+            Some(NumberOrString::String("unknown-command".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_schedule_debounces_edits() {
+        let sink = RecordingSink::default();
+        let provider = provider(&sink);
+        let uri = Url::parse("file:///part.gcode").unwrap();
+        provider
+            .documents
+            .lock()
+            .await
+            .open(uri.clone(), "gcode", 1, "G28\n".into());
+
+        for _ in 0..3 {
+            provider.schedule(uri.clone()).await;
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        assert_eq!(sink.0.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_clear_cancels_scheduled_run() {
+        let sink = RecordingSink::default();
+        let provider = provider(&sink);
+        let uri = Url::parse("file:///part.gcode").unwrap();
+        provider
+            .documents
+            .lock()
+            .await
+            .open(uri.clone(), "gcode", 1, "G999\n".into());
+
+        provider.schedule(uri.clone()).await;
+        provider.documents.lock().await.close(&uri);
+        provider.clear(uri.clone()).await;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let published = sink.0.lock().unwrap();
+        assert_eq!(published.len(), 1);
+        assert_eq!(published[0], (uri, Vec::new(), None));
+    }
+
+    #[tokio::test]
+    async fn test_pull_unchanged_document() {
+        let sink = RecordingSink::default();
+        let provider = provider(&sink);
+        provider.set_push_enabled(false);
+        let uri = Url::parse("file:///part.gcode").unwrap();
+        provider
+            .documents
+            .lock()
+            .await
+            .open(uri.clone(), "gcode", 2, "G999\n".into());
+
+        let (report, version) = provider.pull(&uri, None).await.unwrap();
+        assert_eq!(version, 2);
+        let DocumentDiagnosticReportKind::Full(full) = report else {
+            panic!("expected a full report");
+        };
+        assert_eq!(full.items.len(), 1);
+        let result_id = full.result_id.unwrap();
+
+        let (report, _) = provider.pull(&uri, Some(&result_id)).await.unwrap();
+        assert!(matches!(report, DocumentDiagnosticReportKind::Unchanged(_)));
+
+        provider.invalidate();
+        let (report, _) = provider.pull(&uri, Some(&result_id)).await.unwrap();
+        assert!(matches!(report, DocumentDiagnosticReportKind::Full(_)));
+
+        let report = provider
+            .pull_content("G28\n".into(), DocumentConfig::default(), Some(&result_id))
+            .await;
+        assert!(matches!(report, DocumentDiagnosticReportKind::Full(_)));
+        assert!(
+            provider
+                .pull(&Url::parse("file:///closed.gcode").unwrap(), None)
+                .await
+                .is_none()
+        );
+
+        provider.publish(uri.clone()).await;
+        provider.schedule(uri.clone()).await;
+        provider.clear(uri).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(sink.0.lock().unwrap().is_empty(), "nothing is pushed");
+    }
+
+    #[tokio::test]
+    async fn test_pull_cached_file() {
+        let sink = RecordingSink::default();
+        let provider = provider(&sink);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("part.gcode");
+        std::fs::write(&path, "G999\n").unwrap();
+        let uri = Url::from_file_path(&path).unwrap();
+        let stamp = FileStamp::new(&std::fs::metadata(&path).unwrap());
+
+        assert!(provider.pull_cached(&uri, stamp, None).await.is_none());
+        let report = provider
+            .pull_file(
+                uri.clone(),
+                stamp,
+                "G999\n".into(),
+                DocumentConfig::default(),
+                None,
+            )
+            .await;
+        let DocumentDiagnosticReportKind::Full(full) = report else {
+            panic!("expected a full report");
+        };
+        let result_id = full.result_id.unwrap();
+
+        let report = provider.pull_cached(&uri, stamp, Some(&result_id)).await;
+        assert!(matches!(
+            report,
+            Some(DocumentDiagnosticReportKind::Unchanged(_))
+        ));
+
+        // A rewritten file or new settings need the file read again
+        std::fs::write(&path, "G28\nG999\n").unwrap();
+        let rewritten = FileStamp::new(&std::fs::metadata(&path).unwrap());
+        assert!(
+            provider
+                .pull_cached(&uri, rewritten, Some(&result_id))
+                .await
+                .is_none()
+        );
+        provider.invalidate();
+        assert!(
+            provider
+                .pull_cached(&uri, stamp, Some(&result_id))
+                .await
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_result_id_ignores_override_order() {
+        let config = |rules: &[&str]| {
+            let mut config = DocumentConfig::default();
+            for rule in rules {
+                config.settings.rules.parse_assignment(rule).unwrap();
+            }
+            config
+        };
+        let rules = [
+            "cold-extrusion=off",
+            "unknown-command=info",
+            "thumbnail=warning",
+        ];
+        let reversed = [
+            "thumbnail=warning",
+            "unknown-command=info",
+            "cold-extrusion=off",
+        ];
+
+        assert_eq!(
+            result_id("G28\n", &config(&rules), 0),
+            result_id("G28\n", &config(&reversed), 0)
+        );
+    }
+
+    #[test]
+    fn test_utf16_range() {
+        let diagnostic = Diagnostic {
+            line: 1,
+            message: "message".to_string(),
+            severity: Severity::Error,
+            tags: Vec::new(),
+            rule: crate::validation::Rule::ArcGeometry,
+            span: Some(9..11),
+        };
+
+        let lsp = to_lsp_diagnostic(diagnostic, "G2 é I5 R3");
+        assert_eq!(lsp.range.start.character, 8);
+        assert_eq!(lsp.range.end.character, 10);
     }
 }
@@ -1,19 +1,446 @@
 //! Document Management
 //!
-//! Clean document state management.
+//! Open documents, their text, parsed lines, versions and the configuration
+//! selected for them. Independent of the LSP transport, so the lifecycle can
+//! be driven and tested without a client.
 
-/// Document manager (placeholder)
-#[derive(Debug)]
-pub struct DocumentManager;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+use lsp_types::{Position, TextDocumentContentChangeEvent, Url};
+
+use crate::config::Settings;
+use crate::core::files::GCODE_EXTENSIONS;
+use crate::flavor::registry::modeline_values;
+use crate::machine::Simulation;
+use crate::parser::ParsedDocument;
+
+/// Language identifiers of documents the server handles
+pub const GCODE_LANGUAGE_IDS: &[&str] = &["gcode", "ngc"];
+
+/// Settings, flavor and machine profile selected for a document
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocumentConfig {
+    /// Detected from modeline, configured flavor or default
+    pub flavor_name: Option<String>,
+    /// Detected from modeline or default machine profile
+    pub machine_name: Option<String>,
+    /// Resolved from CLI, project file and client settings
+    pub settings: Settings,
+    /// Selected by the project's file patterns
+    pub included: bool,
+}
+
+impl Default for DocumentConfig {
+    fn default() -> Self {
+        Self {
+            flavor_name: None,
+            machine_name: None,
+            settings: Settings::default(),
+            included: true,
+        }
+    }
+}
+
+/// State for each open document
+#[derive(Debug, Clone)]
+pub struct DocumentState {
+    pub language_id: String,
+    /// Version sent by the client with the last open or change
+    pub version: i32,
+    /// Shared with background validation runs
+    pub content: Arc<str>,
+    /// Parsed lines of `content`, updated incrementally
+    pub parsed: Arc<ParsedDocument>,
+    pub config: DocumentConfig,
+    /// Machine states, simulated on first use after each change; shared
+    /// with snapshots so a simulation run off the lock is cached here too
+    simulation: Arc<OnceLock<Arc<Simulation>>>,
+    /// Checkpoints from before the last change that it left valid
+    simulation_seed: Option<Arc<Simulation>>,
+}
+
+impl DocumentState {
+    pub fn new(language_id: &str, version: i32, content: String) -> Self {
+        let parsed = ParsedDocument::parse(&content);
+        Self {
+            language_id: language_id.to_string(),
+            version,
+            content: content.into(),
+            parsed: Arc::new(parsed),
+            config: DocumentConfig::default(),
+            simulation: Arc::default(),
+            simulation_seed: None,
+        }
+    }
+
+    /// Apply the edits of a `textDocument/didChange` notification in order
+    ///
+    /// A ranged edit re-parses only the lines it touched; a full-text change
+    /// re-parses the lines that differ from the previous text.
+    pub fn apply_changes(&mut self, changes: Vec<TextDocumentContentChangeEvent>) {
+        let mut content = self.content.to_string();
+        let parsed = Arc::make_mut(&mut self.parsed);
+        let mut first_changed = parsed.len();
+        for change in changes {
+            let changed = match change.range {
+                Some(range) => {
+                    let start = byte_offset(&content, range.start);
+                    let end = byte_offset(&content, range.end).max(start);
+                    let line = content[..start].matches('\n').count();
+                    let line_start = content[..start].rfind('\n').map_or(0, |idx| idx + 1);
+                    let removed = content[start..end].matches('\n').count();
+                    let added = change.text.matches('\n').count();
+
+                    content.replace_range(start..end, &change.text);
+                    parsed.splice(
+                        line..line + removed + 1,
+                        content[line_start..].lines().take(added + 1),
+                    )
+                }
+                None => {
+                    let changed = parsed.update(&content, &change.text);
+                    content = change.text;
+                    changed
+                }
+            };
+            first_changed = first_changed.min(changed.start);
+        }
+        self.content = content.into();
+        self.invalidate_simulation(first_changed);
+    }
+
+    /// Replace the whole text, re-parsing only the lines that differ
+    pub fn set_content(&mut self, content: String) {
+        let changed = Arc::make_mut(&mut self.parsed).update(&self.content, &content);
+        self.content = content.into();
+        self.invalidate_simulation(changed.start);
+    }
+
+    /// Drop the machine states from `line` on, keeping those before it
+    fn invalidate_simulation(&mut self, line: usize) {
+        let seed = match self.simulation.get() {
+            Some(simulation) => Some(simulation.truncate(line)),
+            None => self.simulation_seed.take().map(|seed| seed.truncate(line)),
+        };
+        self.simulation = Arc::default();
+        self.simulation_seed = seed.map(Arc::new);
+    }
+
+    /// Machine states of the document, simulating the lines not yet covered
+    ///
+    /// May replay the whole document; handlers use [`Self::snapshot`] to run
+    /// it without holding the documents lock.
+    pub fn simulation(&self) -> Arc<Simulation> {
+        simulate(
+            &self.simulation,
+            self.simulation_seed.as_deref(),
+            &self.content,
+            &self.parsed,
+        )
+    }
+
+    /// Flavors and machine profiles named by the document's modelines
+    ///
+    /// Edits that leave these alone keep the document's configuration.
+    pub fn modelines(&self) -> [Vec<String>; 2] {
+        [
+            modeline_values(&self.content, "gcode_flavor"),
+            modeline_values(&self.content, "gcode_machine"),
+        ]
+    }
+
+    /// Copy out what requests need, sharing the text and the simulation
+    pub fn snapshot(&self) -> DocumentSnapshot {
+        DocumentSnapshot {
+            content: Arc::clone(&self.content),
+            parsed: Arc::clone(&self.parsed),
+            config: self.config.clone(),
+            simulation: Arc::clone(&self.simulation),
+            simulation_seed: self.simulation_seed.clone(),
+        }
+    }
+}
+
+/// A document as of one version, usable after the documents lock is released
+#[derive(Debug, Clone)]
+pub struct DocumentSnapshot {
+    pub content: Arc<str>,
+    pub parsed: Arc<ParsedDocument>,
+    pub config: DocumentConfig,
+    simulation: Arc<OnceLock<Arc<Simulation>>>,
+    simulation_seed: Option<Arc<Simulation>>,
+}
+
+impl DocumentSnapshot {
+    /// Machine states of the document, simulating the lines not yet covered
+    pub fn simulation(&self) -> Arc<Simulation> {
+        simulate(
+            &self.simulation,
+            self.simulation_seed.as_deref(),
+            &self.content,
+            &self.parsed,
+        )
+    }
+}
+
+fn simulate(
+    cell: &OnceLock<Arc<Simulation>>,
+    seed: Option<&Simulation>,
+    content: &str,
+    parsed: &ParsedDocument,
+) -> Arc<Simulation> {
+    let simulation = cell.get_or_init(|| {
+        let seed = seed.cloned().unwrap_or_default();
+        Arc::new(seed.resume(content, parsed))
+    });
+    Arc::clone(simulation)
+}
+
+/// The set of open documents
+#[derive(Debug, Default)]
+pub struct DocumentManager {
+    documents: HashMap<Url, DocumentState>,
+}
 
 impl DocumentManager {
     pub fn new() -> Self {
-        Self
+        Self::default()
+    }
+
+    /// Check whether a document is G-code, by language ID or file extension
+    pub fn accepts(uri: &Url, language_id: &str) -> bool {
+        GCODE_LANGUAGE_IDS
+            .iter()
+            .any(|id| id.eq_ignore_ascii_case(language_id))
+            || Path::new(uri.path())
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| {
+                    GCODE_EXTENSIONS
+                        .iter()
+                        .any(|known| known.eq_ignore_ascii_case(ext))
+                })
+    }
+
+    /// Start tracking a document
+    ///
+    /// Returns `false`, and ignores the document, if it is not G-code.
+    pub fn open(&mut self, uri: Url, language_id: &str, version: i32, text: String) -> bool {
+        if !Self::accepts(&uri, language_id) {
+            log::debug!("Ignoring {} with language '{}'", uri, language_id);
+            return false;
+        }
+        self.documents
+            .insert(uri, DocumentState::new(language_id, version, text));
+        true
+    }
+
+    /// Apply edits to an open document
+    ///
+    /// Returns the updated document, or `None` if it is not open.
+    pub fn change(
+        &mut self,
+        uri: &Url,
+        version: i32,
+        changes: Vec<TextDocumentContentChangeEvent>,
+    ) -> Option<&DocumentState> {
+        let document = self.documents.get_mut(uri)?;
+        document.apply_changes(changes);
+        document.version = version;
+        Some(document)
+    }
+
+    /// Record a save, taking the saved text if the client sent it
+    pub fn save(&mut self, uri: &Url, text: Option<String>) -> Option<&DocumentState> {
+        let document = self.documents.get_mut(uri)?;
+        if let Some(text) = text
+            && *text != *document.content
+        {
+            document.set_content(text);
+        }
+        Some(document)
+    }
+
+    /// Stop tracking a document, returning its last state
+    pub fn close(&mut self, uri: &Url) -> Option<DocumentState> {
+        self.documents.remove(uri)
+    }
+
+    /// Set the configuration selected for a document
+    pub fn configure(&mut self, uri: &Url, config: DocumentConfig) {
+        if let Some(document) = self.documents.get_mut(uri) {
+            document.config = config;
+        }
+    }
+
+    pub fn get(&self, uri: &Url) -> Option<&DocumentState> {
+        self.documents.get(uri)
+    }
+
+    pub fn contains(&self, uri: &Url) -> bool {
+        self.documents.contains_key(uri)
+    }
+
+    /// URIs of all open documents
+    pub fn uris(&self) -> Vec<Url> {
+        self.documents.keys().cloned().collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Url, &DocumentState)> {
+        self.documents.iter()
     }
 }
 
-impl Default for DocumentManager {
-    fn default() -> Self {
-        Self::new()
+/// Convert an LSP position (UTF-16 columns) to a byte offset in `content`
+///
+/// Positions past the end of a line or of the document are clamped.
+fn byte_offset(content: &str, position: Position) -> usize {
+    let mut line_start = 0;
+    for _ in 0..position.line {
+        match content[line_start..].find('\n') {
+            Some(idx) => line_start += idx + 1,
+            None => return content.len(),
+        }
+    }
+
+    let rest = &content[line_start..];
+    let line = &rest[..rest.find('\n').unwrap_or(rest.len())];
+    let line = line.strip_suffix('\r').unwrap_or(line);
+
+    let mut column = 0;
+    for (idx, c) in line.char_indices() {
+        if column >= position.character as usize {
+            return line_start + idx;
+        }
+        column += c.len_utf16();
+    }
+    line_start + line.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::Range;
+
+    fn uri(path: &str) -> Url {
+        Url::parse(&format!("file:///project/{}", path)).unwrap()
+    }
+
+    fn edit(start: (u32, u32), end: (u32, u32), text: &str) -> TextDocumentContentChangeEvent {
+        TextDocumentContentChangeEvent {
+            range: Some(Range::new(
+                Position::new(start.0, start.1),
+                Position::new(end.0, end.1),
+            )),
+            range_length: None,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_apply_incremental_changes() {
+        let mut state = DocumentState::new("gcode", 1, "G28\nG1 X10 ; é\r\nM104 S200\n".into());
+
+        state.apply_changes(vec![
+            edit((1, 4), (1, 6), "25"),
+            edit((1, 13), (1, 99), " moved"),
+            edit((2, 0), (3, 0), ""),
+            edit((9, 0), (9, 0), "M84\n"),
+        ]);
+
+        assert_eq!(&*state.content, "G28\nG1 X25 ; é moved\r\nM84\n");
+        assert_eq!(*state.parsed, ParsedDocument::parse(&state.content));
+
+        // Joining and splitting lines, and edits at the end of the text
+        state.apply_changes(vec![
+            edit((0, 3), (1, 0), " "),
+            edit((0, 3), (0, 4), "\nG1 Y5\nG1 Z1\n"),
+            edit((4, 0), (5, 0), ""),
+            edit((3, 0), (3, 99), "M84 ; off"),
+        ]);
+        assert_eq!(&*state.content, "G28\nG1 Y5\nG1 Z1\nM84 ; off\r\n");
+        assert_eq!(*state.parsed, ParsedDocument::parse(&state.content));
+    }
+
+    #[test]
+    fn test_apply_full_change() {
+        let mut state = DocumentState::new("gcode", 1, "G28\n".into());
+        let shared = Arc::clone(&state.parsed);
+
+        state.apply_changes(vec![TextDocumentContentChangeEvent {
+            range: None,
+            range_length: None,
+            text: "G28\nG1 X10\n".to_string(),
+        }]);
+
+        assert_eq!(*state.parsed, ParsedDocument::parse("G28\nG1 X10\n"));
+        assert_eq!(shared.len(), 1, "snapshots are not modified");
+    }
+
+    #[test]
+    fn test_simulation_follows_edits() {
+        let mut state = DocumentState::new("gcode", 1, "G28\nG91\nG1 X10\n".into());
+        let parsed = Arc::clone(&state.parsed);
+        let before = state.simulation().state_before(&state.content, &parsed, 3);
+        assert_eq!(before.state.position.x, Some(10.0));
+
+        state.apply_changes(vec![edit((2, 4), (2, 6), "25")]);
+        let after = state
+            .simulation()
+            .state_before(&state.content, &state.parsed, 3);
+        assert_eq!(after.state.position.x, Some(25.0));
+        assert_eq!(after.origins.position, Some(2));
+    }
+
+    #[test]
+    fn test_snapshot_shares_simulation() {
+        let mut state = DocumentState::new("gcode", 1, "G28\nG1 X10\n".into());
+        let snapshot = state.snapshot();
+
+        // Simulated off the document, cached for it
+        let simulation = snapshot.simulation();
+        assert!(Arc::ptr_eq(&simulation, &state.simulation()));
+
+        // An edit leaves the snapshot on its own version
+        state.apply_changes(vec![edit((1, 4), (1, 6), "25")]);
+        assert!(!Arc::ptr_eq(&simulation, &state.simulation()));
+        let before = snapshot
+            .simulation()
+            .state_before(&snapshot.content, &snapshot.parsed, 2);
+        assert_eq!(before.state.position.x, Some(10.0));
+    }
+
+    #[test]
+    fn test_document_lifecycle() {
+        let mut manager = DocumentManager::new();
+        let part = uri("part.gcode");
+
+        assert!(manager.open(part.clone(), "gcode", 1, "G28\n".into()));
+        assert!(!manager.open(uri("notes.md"), "markdown", 1, "# notes".into()));
+        assert!(manager.open(uri("part.GCO"), "plaintext", 1, String::new()));
+        assert_eq!(manager.uris().len(), 2);
+
+        let document = manager
+            .change(&part, 2, vec![edit((1, 0), (1, 0), "G1 X10\n")])
+            .unwrap();
+        assert_eq!(document.version, 2);
+        assert_eq!(&*document.content, "G28\nG1 X10\n");
+
+        let config = DocumentConfig {
+            flavor_name: Some("marlin".to_string()),
+            ..Default::default()
+        };
+        manager.configure(&part, config.clone());
+        assert_eq!(manager.get(&part).unwrap().config, config);
+
+        let document = manager.save(&part, Some("M84\n".into())).unwrap();
+        assert_eq!(document.parsed.len(), 1);
+        assert_eq!(document.version, 2);
+
+        assert!(manager.close(&part).is_some());
+        assert!(!manager.contains(&part));
+        assert!(manager.change(&part, 3, Vec::new()).is_none());
     }
 }
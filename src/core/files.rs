@@ -0,0 +1,128 @@
+//! Workspace Files
+//!
+//! Finding G-code files on disk, shared by `gcode-ls check` and the
+//! server's workspace index and workspace diagnostics.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+
+/// File extensions searched for in directories
+pub const GCODE_EXTENSIONS: &[&str] = &["gcode", "gco", "g"];
+
+/// Directories never searched, besides hidden ones
+pub const SKIPPED_DIRECTORIES: &[&str] = &["node_modules", "target"];
+
+/// Expand files, directories and glob patterns into a list of files
+///
+/// Directories are searched recursively for G-code files. Each file is
+/// listed once, in the order it was first found.
+pub fn collect_files(patterns: &[String]) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+
+    for pattern in patterns {
+        let path = Path::new(pattern);
+        if path.is_dir() {
+            collect_directory(path, &mut files)?;
+        } else if path.exists() {
+            files.push(path.to_path_buf());
+        } else if pattern.contains(['*', '?', '[']) {
+            let matches: Vec<PathBuf> = glob::glob(pattern)
+                .with_context(|| format!("Invalid pattern '{}'", pattern))?
+                .filter_map(|entry| entry.ok())
+                .filter(|path| path.is_file())
+                .collect();
+            if matches.is_empty() {
+                bail!("No files match '{}'", pattern);
+            }
+            files.extend(matches);
+        } else {
+            bail!("No such file or directory: {}", pattern);
+        }
+    }
+
+    let mut seen = HashSet::new();
+    files.retain(|path| seen.insert(path.clone()));
+    Ok(files)
+}
+
+fn collect_directory(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let mut entries: Vec<std::fs::DirEntry> = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read directory {:?}", dir))?
+        .filter_map(|entry| entry.ok())
+        .collect();
+    entries.sort_by_key(|entry| entry.path());
+
+    for entry in entries {
+        let path = entry.path();
+        // Not following symlinks, a link to a parent directory cannot loop
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            if !is_skipped_directory(&entry.file_name().to_string_lossy()) {
+                collect_directory(&path, files)?;
+            }
+        } else if (file_type.is_file() || path.is_file())
+            && path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| GCODE_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+        {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Hidden directories (`.git`, `.cache`, ...) and build output
+fn is_skipped_directory(name: &str) -> bool {
+    name.starts_with('.') || SKIPPED_DIRECTORIES.contains(&name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collect_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        std::fs::write(dir.path().join("a.gcode"), "").unwrap();
+        std::fs::write(dir.path().join("sub").join("b.GCO"), "").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "").unwrap();
+
+        let root = dir.path().to_string_lossy().to_string();
+        let files = collect_files(&[root.clone(), format!("{}/*.gcode", root)]).unwrap();
+        assert_eq!(
+            files,
+            vec![
+                dir.path().join("a.gcode"),
+                dir.path().join("sub").join("b.GCO")
+            ]
+        );
+
+        assert!(collect_files(&[format!("{}/*.nc", root)]).is_err());
+        assert!(collect_files(&[format!("{}/missing.gcode", root)]).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_collect_files_skips_links_and_hidden() {
+        let dir = tempfile::tempdir().unwrap();
+        for sub in ["sub", ".git", "node_modules", "target"] {
+            std::fs::create_dir(dir.path().join(sub)).unwrap();
+            std::fs::write(dir.path().join(sub).join("part.gcode"), "").unwrap();
+        }
+        std::os::unix::fs::symlink("..", dir.path().join("loop")).unwrap();
+        std::os::unix::fs::symlink("..", dir.path().join("sub").join("up")).unwrap();
+
+        let root = dir.path().to_string_lossy().to_string();
+        assert_eq!(
+            collect_files(&[root]).unwrap(),
+            vec![dir.path().join("sub").join("part.gcode")]
+        );
+    }
+}
@@ -0,0 +1,27 @@
+//! Number Formatting
+//!
+//! Numbers as shown in hints and hovers and written into edits.
+
+/// Format a number with at most three decimals and no trailing zeros
+pub(crate) fn format_number(value: f64) -> String {
+    let text = format!("{:.3}", value);
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    if text == "-0" {
+        "0".to_string()
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_number() {
+        assert_eq!(format_number(10.0), "10");
+        assert_eq!(format_number(0.2), "0.2");
+        assert_eq!(format_number(1.23456), "1.235");
+        assert_eq!(format_number(-0.0001), "0");
+    }
+}
@@ -0,0 +1,247 @@
+//! Document Highlight
+//!
+//! Lines related to the element under the cursor: every use of a tool, the
+//! other occurrences of a command or of one of its parameters, and the
+//! commands that set the modal state a command runs in.
+
+use std::ops::Range as ByteRange;
+
+use lsp_types::{DocumentHighlight, DocumentHighlightKind, Position, Range};
+
+use crate::core::diagnostics::{byte_column, utf16_column};
+use crate::machine::TrackedState;
+use crate::machine::simulation::ModalOrigins;
+use crate::parser::{Command, ParsedDocument, ParsedLine};
+
+/// Highlights for the element at `position`
+///
+/// `before` is the state before the cursor's line, e.g. from the document's
+/// simulation; its origins point at the lines that set each modal value.
+pub fn document_highlights(
+    content: &str,
+    parsed: &ParsedDocument,
+    position: Position,
+    before: &TrackedState,
+) -> Vec<DocumentHighlight> {
+    let line_idx = position.line as usize;
+    let Some(ParsedLine::Command(command)) = parsed.get(line_idx) else {
+        return Vec::new();
+    };
+    let line = content.lines().nth(line_idx).unwrap_or("");
+    let column = byte_column(line, position.character);
+    let on = |span: &ByteRange<usize>| span.start <= column && column <= span.end;
+
+    let name = command.name.to_uppercase();
+    let parameter = command.parameters.iter().find(|param| on(&param.span));
+
+    if let Some(tool) = match parameter {
+        Some(param) if param.letter.eq_ignore_ascii_case(&'T') => param.value.parse().ok(),
+        Some(_) => None,
+        None if on(&command.span) => tool_number(&name),
+        None => None,
+    } {
+        return tool_highlights(content, parsed, tool);
+    }
+
+    if let Some(param) = parameter {
+        // The same parameter wherever the command is used, e.g. every `G92 E`
+        let letter = param.letter.to_ascii_uppercase();
+        return each_command(content, parsed, &name)
+            .filter_map(|(idx, text, command)| {
+                let param = command
+                    .parameters
+                    .iter()
+                    .find(|param| param.letter.to_ascii_uppercase() == letter)?;
+                Some(highlight(
+                    idx,
+                    text,
+                    &param.span,
+                    DocumentHighlightKind::TEXT,
+                ))
+            })
+            .collect();
+    }
+
+    if !on(&command.span) {
+        return Vec::new();
+    }
+
+    let setters = modal_setters(command, &before.origins);
+    let mut highlights: Vec<DocumentHighlight> = each_command(content, parsed, &name)
+        .filter(|(idx, _, _)| !setters.contains(idx))
+        .map(|(idx, text, command)| {
+            highlight(idx, text, &command.span, DocumentHighlightKind::TEXT)
+        })
+        .collect();
+    for idx in setters {
+        if let Some(ParsedLine::Command(setter)) = parsed.get(idx) {
+            let text = content.lines().nth(idx).unwrap_or("");
+            highlights.push(highlight(
+                idx,
+                text,
+                &setter.span,
+                DocumentHighlightKind::WRITE,
+            ));
+        }
+    }
+    highlights.sort_by_key(|highlight| highlight.range.start);
+    highlights
+}
+
+/// Tool selections `T<n>` and parameters `T<n>` of other commands
+fn tool_highlights(content: &str, parsed: &ParsedDocument, tool: u32) -> Vec<DocumentHighlight> {
+    let mut highlights = Vec::new();
+    for (idx, (text, line)) in content.lines().zip(parsed.iter()).enumerate() {
+        let ParsedLine::Command(command) = line else {
+            continue;
+        };
+        if tool_number(&command.name.to_uppercase()) == Some(tool) {
+            highlights.push(highlight(
+                idx,
+                text,
+                &command.span,
+                DocumentHighlightKind::WRITE,
+            ));
+        }
+        highlights.extend(
+            command
+                .parameters
+                .iter()
+                .filter(|param| param.letter.eq_ignore_ascii_case(&'T'))
+                .filter(|param| param.value.parse() == Ok(tool))
+                .map(|param| highlight(idx, text, &param.span, DocumentHighlightKind::READ)),
+        );
+    }
+    highlights
+}
+
+/// Lines holding a command named `name`, with their text
+fn each_command<'a>(
+    content: &'a str,
+    parsed: &'a ParsedDocument,
+    name: &'a str,
+) -> impl Iterator<Item = (usize, &'a str, &'a Command<'static>)> {
+    content
+        .lines()
+        .zip(parsed.iter())
+        .enumerate()
+        .filter_map(move |(idx, (text, line))| match line {
+            ParsedLine::Command(command) if command.name.eq_ignore_ascii_case(name) => {
+                Some((idx, text, command))
+            }
+            _ => None,
+        })
+}
+
+/// Lines that set the modal values a command depends on
+fn modal_setters(command: &Command, origins: &ModalOrigins) -> Vec<usize> {
+    let has = |letter: char| {
+        command
+            .parameters
+            .iter()
+            .any(|param| param.letter.eq_ignore_ascii_case(&letter))
+    };
+    let name = command.name.to_uppercase();
+
+    let mut setters = Vec::new();
+    if matches!(name.as_str(), "G0" | "G1" | "G2" | "G3") {
+        setters.extend(origins.positioning);
+        setters.extend(origins.units);
+        if !has('F') {
+            setters.extend(origins.feedrate);
+        }
+        if has('E') {
+            setters.extend(origins.extruder_positioning);
+        }
+        if matches!(name.as_str(), "G2" | "G3") {
+            setters.extend(origins.plane);
+        }
+    }
+    setters.sort_unstable();
+    setters.dedup();
+    setters
+}
+
+/// Tool selected by a `T<n>` command
+fn tool_number(name: &str) -> Option<u32> {
+    name.strip_prefix('T')?.parse().ok()
+}
+
+fn highlight(
+    line: usize,
+    text: &str,
+    span: &ByteRange<usize>,
+    kind: DocumentHighlightKind,
+) -> DocumentHighlight {
+    DocumentHighlight {
+        range: Range::new(
+            Position::new(line as u32, utf16_column(text, span.start)),
+            Position::new(line as u32, utf16_column(text, span.end)),
+        ),
+        kind: Some(kind),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::Simulation;
+
+    fn highlights(content: &str, line: u32, character: u32) -> Vec<(u32, u32, u32)> {
+        let parsed = ParsedDocument::parse(content);
+        let before =
+            Simulation::run(content, &parsed).state_before(content, &parsed, line as usize);
+        document_highlights(content, &parsed, Position::new(line, character), &before)
+            .into_iter()
+            .map(|highlight| {
+                (
+                    highlight.range.start.line,
+                    highlight.range.start.character,
+                    highlight.range.end.character,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_tool_uses() {
+        let content = "T1\nM104 S200 T1\nM104 S200 T0\nT0\nM109 T1 S210\n";
+        let expected = vec![(0, 0, 2), (1, 10, 12), (4, 5, 7)];
+
+        assert_eq!(highlights(content, 0, 1), expected);
+        assert_eq!(highlights(content, 1, 11), expected);
+    }
+
+    #[test]
+    fn test_same_parameter() {
+        let content = "G92 E0\nG1 X1 E1\nG92 X0 E0\nG92 X5\n";
+        assert_eq!(highlights(content, 0, 4), vec![(0, 4, 6), (2, 7, 9)]);
+    }
+
+    #[test]
+    fn test_command_and_modal_setters() {
+        let content = "G21\nG91\nG1 X1 F600\nM83\nG1 X1 E1\n";
+        let highlights = parsed_kinds(content, 4, 0);
+
+        assert_eq!(
+            highlights,
+            vec![
+                (0, DocumentHighlightKind::WRITE),
+                (1, DocumentHighlightKind::WRITE),
+                (2, DocumentHighlightKind::WRITE),
+                (3, DocumentHighlightKind::WRITE),
+                (4, DocumentHighlightKind::TEXT),
+            ]
+        );
+    }
+
+    fn parsed_kinds(content: &str, line: u32, character: u32) -> Vec<(u32, DocumentHighlightKind)> {
+        let parsed = ParsedDocument::parse(content);
+        let before =
+            Simulation::run(content, &parsed).state_before(content, &parsed, line as usize);
+        document_highlights(content, &parsed, Position::new(line, character), &before)
+            .into_iter()
+            .map(|highlight| (highlight.range.start.line, highlight.kind.unwrap()))
+            .collect()
+    }
+}
@@ -0,0 +1,472 @@
+//! Hover
+//!
+//! Describes the element under the cursor: a command, one of its parameters
+//! with the validity of its value, or a comment a slicer or this server
+//! gives meaning to. Every line also shows the machine state before and
+//! after it, and the line that last set each modal value.
+
+use std::ops::Range;
+
+use lsp_types::{Hover, HoverContents, MarkupContent, MarkupKind, Position};
+
+use crate::core::diagnostics::{byte_column, utf16_column};
+use crate::core::format::format_number;
+use crate::core::inlay_hints::position_label;
+use crate::flavor::registry::FlavorRegistry;
+use crate::flavor::schema::{CommandDef, ParameterDef};
+use crate::machine::TrackedState;
+use crate::machine::state::{Heater, MachineState, Plane, PositioningMode, Units};
+use crate::parser::{Command, Comment, Parameter, ParsedLine};
+
+/// Comments with a meaning to slicers, firmware or this server, by prefix
+const SPECIAL_COMMENTS: &[(&str, &str)] = &[
+    (
+        "TYPE:",
+        "Feature type of the moves that follow (PrusaSlicer, Cura)",
+    ),
+    ("LAYER:", "Start of a layer, numbered from zero (Cura)"),
+    ("LAYER_CHANGE", "Start of a new layer (PrusaSlicer)"),
+    ("LAYER_COUNT:", "Number of layers in the print (Cura)"),
+    ("Z:", "Height of the new layer in mm (PrusaSlicer)"),
+    (
+        "HEIGHT:",
+        "Layer height of the moves that follow in mm (PrusaSlicer)",
+    ),
+    (
+        "WIDTH:",
+        "Extrusion width of the moves that follow in mm (PrusaSlicer)",
+    ),
+    ("MESH:", "Object the moves that follow belong to (Cura)"),
+    ("TIME:", "Estimated print time in seconds (Cura)"),
+    (
+        "TIME_ELAPSED:",
+        "Print time elapsed at this point in seconds (Cura)",
+    ),
+    ("FLAVOR:", "Firmware flavor the file was sliced for (Cura)"),
+    ("Filament used:", "Filament the print uses (Cura)"),
+    ("WIPE_START", "Start of a wipe move (PrusaSlicer)"),
+    ("WIPE_END", "End of a wipe move (PrusaSlicer)"),
+    (
+        "thumbnail begin",
+        "Start of an embedded base64 preview image",
+    ),
+    ("thumbnail end", "End of an embedded preview image"),
+    (
+        "gcode-ls:",
+        "Suppresses gcode-ls diagnostics on this line or the next one",
+    ),
+];
+
+/// Hover for `position` on the line `line`
+///
+/// `before` is the machine state before the line, e.g. from the document's
+/// simulation.
+pub fn hover(
+    line: &str,
+    parsed: &ParsedLine,
+    flavor: &FlavorRegistry,
+    position: Position,
+    before: &TrackedState,
+) -> Hover {
+    let mut after = before.clone();
+    if let ParsedLine::Command(command) = parsed {
+        after.apply(command, position.line as usize);
+    }
+    let state = state_table(before, &after);
+
+    let offset = byte_column(line, position.character);
+    let (value, range) = match element_hover(parsed, flavor, offset) {
+        Some((text, span)) => (
+            format!("{}\n\n---\n\n{}", text, state),
+            Some(lsp_types::Range::new(
+                Position::new(position.line, utf16_column(line, span.start)),
+                Position::new(position.line, utf16_column(line, span.end)),
+            )),
+        ),
+        None => (state, None),
+    };
+
+    Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range,
+    }
+}
+
+/// Text and byte span of the command, parameter or comment at `offset`
+fn element_hover(
+    parsed: &ParsedLine,
+    flavor: &FlavorRegistry,
+    offset: usize,
+) -> Option<(String, Range<usize>)> {
+    let contains = |span: &Range<usize>| span.start <= offset && offset <= span.end;
+
+    let element = match parsed {
+        ParsedLine::Command(command) => {
+            let definition = flavor.get_command(&command.name.to_uppercase());
+            if contains(&command.span) {
+                (command_hover(command, definition?), command.span.clone())
+            } else if let Some(param) = command.parameters.iter().find(|p| contains(&p.span)) {
+                (
+                    parameter_hover(command, param, definition?),
+                    param.span.clone(),
+                )
+            } else {
+                let comment = command.comment.as_ref().filter(|c| contains(&c.span))?;
+                (comment_hover(comment)?, comment.span.clone())
+            }
+        }
+        ParsedLine::Comment(comment) if contains(&comment.span) => {
+            (comment_hover(comment)?, comment.span.clone())
+        }
+        _ => return None,
+    };
+    Some(element)
+}
+
+/// Label, description of a state and the line that set it
+type StateRow = (&'static str, fn(&MachineState) -> String, Option<usize>);
+
+/// Table of the machine state before and after a line
+fn state_table(before: &TrackedState, after: &TrackedState) -> String {
+    let origins = &after.origins;
+    let rows: [StateRow; 10] = [
+        (
+            "Position",
+            |s| position_label(s).unwrap_or_else(|| "unknown".to_string()),
+            origins.position,
+        ),
+        (
+            "Positioning",
+            |s| positioning(s.positioning, "G90", "G91"),
+            origins.positioning,
+        ),
+        (
+            "Extruder",
+            |s| positioning(s.extruder_positioning, "M82", "M83"),
+            origins.extruder_positioning,
+        ),
+        (
+            "Units",
+            |s| match s.units {
+                Units::Millimeters => "G21 millimeters".to_string(),
+                Units::Inches => "G20 inches".to_string(),
+            },
+            origins.units,
+        ),
+        (
+            "Plane",
+            |s| match s.plane {
+                Plane::XY => "G17 XY".to_string(),
+                Plane::XZ => "G18 XZ".to_string(),
+                Plane::YZ => "G19 YZ".to_string(),
+            },
+            origins.plane,
+        ),
+        ("Tool", |s| format!("T{}", s.tool), origins.tool),
+        ("Hotend", |s| heater(s.hotend()), origins.hotend),
+        ("Bed", |s| heater(s.bed), origins.bed),
+        (
+            "Fan",
+            |s| match s.fan {
+                Some(speed) => format!("{}%", format_number(speed * 100.0)),
+                None => "unknown".to_string(),
+            },
+            origins.fan,
+        ),
+        (
+            "Feedrate",
+            |s| match s.feedrate {
+                Some(feedrate) => format!("{} mm/s", format_number(feedrate / 60.0)),
+                None => "unknown".to_string(),
+            },
+            origins.feedrate,
+        ),
+    ];
+
+    let mut table =
+        String::from("**Machine state**\n\n| | Before | After | Set on |\n|---|---|---|---|");
+    for (label, describe, origin) in rows {
+        let (old, new) = (describe(&before.state), describe(&after.state));
+        let new = if old == new {
+            new
+        } else {
+            format!("**{}**", new)
+        };
+        let origin = origin.map_or_else(|| "-".to_string(), |line| format!("line {}", line + 1));
+        table.push_str(&format!("\n| {} | {} | {} | {} |", label, old, new, origin));
+    }
+    table
+}
+
+fn positioning(mode: PositioningMode, absolute: &str, relative: &str) -> String {
+    match mode {
+        PositioningMode::Absolute => format!("{} absolute", absolute),
+        PositioningMode::Relative => format!("{} relative", relative),
+    }
+}
+
+fn heater(heater: Option<Heater>) -> String {
+    match heater {
+        Some(heater) if heater.is_on() => format!(
+            "{} °C{}",
+            format_number(heater.target),
+            if heater.waited { ", reached" } else { "" }
+        ),
+        Some(_) => "off".to_string(),
+        None => "unknown".to_string(),
+    }
+}
+
+/// Description, deprecation and parameters of a command
+fn command_hover(command: &Command, definition: &CommandDef) -> String {
+    // Always show rich information: prefer long description, fallback to short
+    let desc = definition
+        .description_long
+        .clone()
+        .or_else(|| definition.description_short.clone())
+        .unwrap_or_else(|| "No description".to_string());
+
+    let mut hover_text = format!("**{}**\n\n{}", command.name.to_uppercase(), desc);
+
+    if definition.deprecated {
+        hover_text.push_str("\n\n**Deprecated**");
+        if let Some(replacement) = &definition.replaced_by {
+            hover_text.push_str(&format!(": use `{}` instead", replacement));
+        }
+    }
+
+    if let Some(parameters) = &definition.parameters
+        && !parameters.is_empty()
+    {
+        hover_text.push_str("\n\n**Parameters:**");
+        for param in parameters {
+            hover_text.push_str(&format!(
+                "\n- `{}`: {} ({:?}{})",
+                param.name,
+                param.description,
+                param.param_type,
+                if param.required {
+                    ", required"
+                } else {
+                    ", optional"
+                }
+            ));
+        }
+    }
+
+    hover_text
+}
+
+/// Definition of a parameter and whether its value is valid
+fn parameter_hover(command: &Command, param: &Parameter, definition: &CommandDef) -> String {
+    let command_name = command.name.to_uppercase();
+    let letter = param.letter.to_ascii_uppercase();
+    let Some(param_def) = definition.find_parameter(&letter.to_string()) else {
+        return format!(
+            "**{}** on {}\n\nNot a parameter of {}",
+            letter, command_name, command_name
+        );
+    };
+
+    let mut hover_text = format!(
+        "**{}** on {}\n\n{}\n\n{}",
+        letter,
+        command_name,
+        param_def.description,
+        parameter_summary(param_def)
+    );
+
+    if param.value.is_empty() {
+        hover_text.push_str("\n\nNo value given");
+    } else {
+        match param_def.validate(&param.value) {
+            Ok(()) => hover_text.push_str(&format!("\n\nValue `{}` is valid", param.value)),
+            Err(e) => hover_text.push_str(&format!("\n\n**Invalid:** {}", e)),
+        }
+    }
+
+    hover_text
+}
+
+/// Type, requirement, range and allowed values of a parameter
+fn parameter_summary(param_def: &ParameterDef) -> String {
+    let mut parts = vec![
+        format!("{:?}", param_def.param_type).to_lowercase(),
+        if param_def.required {
+            "required".to_string()
+        } else {
+            "optional".to_string()
+        },
+    ];
+
+    if let Some(constraints) = &param_def.constraints {
+        match (constraints.min_value, constraints.max_value) {
+            (Some(min), Some(max)) => parts.push(format!("range {} to {}", min, max)),
+            (Some(min), None) => parts.push(format!("at least {}", min)),
+            (None, Some(max)) => parts.push(format!("at most {}", max)),
+            (None, None) => {}
+        }
+        if let Some(values) = &constraints.enum_values {
+            let values: Vec<String> = values.iter().map(|v| format!("`{}`", v)).collect();
+            parts.push(format!("one of {}", values.join(", ")));
+        }
+    }
+    if let Some(unit) = param_def.unit() {
+        parts.push(format!("in {}", unit));
+    }
+
+    parts.join(" · ")
+}
+
+/// Meaning of a slicer, modeline or suppression comment
+fn comment_hover(comment: &Comment) -> Option<String> {
+    let text = comment.text.trim();
+
+    for key in ["gcode_flavor", "gcode_machine"] {
+        if let Some((_, value)) = text.split_once(&format!("{}=", key)) {
+            let what = if key == "gcode_flavor" {
+                "flavor"
+            } else {
+                "machine profile"
+            };
+            let value = value.split_whitespace().next().unwrap_or("");
+            return Some(format!(
+                "**Modeline**\n\nSelects the {} `{}` for this document",
+                what, value
+            ));
+        }
+    }
+
+    let (prefix, description) = SPECIAL_COMMENTS
+        .iter()
+        .filter(|(prefix, _)| text.starts_with(prefix))
+        .max_by_key(|(prefix, _)| prefix.len())?;
+
+    let mut hover_text = format!("**`;{}`**\n\n{}", prefix, description);
+    let value = text[prefix.len()..].trim();
+    if !value.is_empty() && prefix.ends_with(':') {
+        hover_text.push_str(&format!("\n\nValue: `{}`", value));
+    }
+    Some(hover_text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::Simulation;
+    use crate::parser::{ParsedDocument, parse_line};
+
+    fn prusa() -> FlavorRegistry {
+        let mut flavor = FlavorRegistry::new();
+        flavor.add_embedded_prusa_flavor();
+        flavor.set_active_flavor("prusa");
+        flavor
+    }
+
+    fn hover_text(line: &str, column: u32) -> String {
+        let before = TrackedState::default();
+        let hover = hover(
+            line,
+            &parse_line(line),
+            &prusa(),
+            Position::new(0, column),
+            &before,
+        );
+        let HoverContents::Markup(markup) = hover.contents else {
+            panic!("expected markup");
+        };
+        markup.value
+    }
+
+    #[test]
+    fn test_command_hover() {
+        let text = hover_text("M104 S215", 2);
+        assert!(text.starts_with("**M104**"));
+        assert!(text.contains("**Parameters:**"));
+    }
+
+    #[test]
+    fn test_parameter_hover() {
+        let text = hover_text("M104 S215", 6);
+        assert!(text.starts_with("**S** on M104"));
+        assert!(text.contains("Target temperature in Celsius"));
+        assert!(text.contains("int · required · in °C"));
+        assert!(text.contains("Value `215` is valid"));
+
+        let text = hover_text("M104 Sabc", 6);
+        assert!(text.contains("**Invalid:**"));
+
+        let text = hover_text("M104 Q1", 6);
+        assert!(text.contains("Not a parameter of M104"));
+    }
+
+    #[test]
+    fn test_comment_hover() {
+        let text = hover_text(";TYPE:External perimeter", 3);
+        assert!(text.starts_with("**`;TYPE:`**"));
+        assert!(text.contains("Value: `External perimeter`"));
+
+        let text = hover_text("G1 X1 ;LAYER:12", 10);
+        assert!(text.contains("Cura"));
+        assert!(text.contains("`12`"));
+
+        let text = hover_text("; gcode_flavor=marlin", 4);
+        assert!(text.contains("flavor `marlin`"));
+
+        assert!(hover_text("; just a note", 4).starts_with("**Machine state**"));
+    }
+
+    #[test]
+    fn test_hover_range() {
+        let line = "G1 é X10";
+        let before = TrackedState::default();
+        let range = hover(
+            line,
+            &parse_line(line),
+            &prusa(),
+            Position::new(4, 6),
+            &before,
+        )
+        .range
+        .unwrap();
+        assert_eq!(range.start, Position::new(4, 5));
+        assert_eq!(range.end, Position::new(4, 8));
+
+        let unknown = hover(
+            line,
+            &parse_line(line),
+            &FlavorRegistry::new(),
+            Position::new(4, 6),
+            &before,
+        );
+        assert!(unknown.range.is_none(), "commands without a definition");
+    }
+
+    #[test]
+    fn test_state_table() {
+        let content = "G28\nM83\nM104 S215\nG1 X5 F1200\n";
+        let parsed = ParsedDocument::parse(content);
+        let before = Simulation::run(content, &parsed).state_before(content, &parsed, 3);
+        let line = "G1 X5 F1200";
+        let hover = hover(
+            line,
+            &parse_line(line),
+            &prusa(),
+            Position::new(3, 0),
+            &before,
+        );
+        let HoverContents::Markup(markup) = hover.contents else {
+            panic!("expected markup");
+        };
+
+        let text = markup.value;
+        assert!(text.contains("| Position | X0 Y0 Z0 mm | **X5 Y0 Z0 mm** | line 4 |"));
+        assert!(text.contains("| Extruder | M83 relative | M83 relative | line 2 |"));
+        assert!(text.contains("| Hotend | 215 °C | 215 °C | line 3 |"));
+        assert!(text.contains("| Feedrate | unknown | **20 mm/s** | line 4 |"));
+        assert!(text.contains("| Units | G21 millimeters | G21 millimeters | - |"));
+    }
+}
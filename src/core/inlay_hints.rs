@@ -0,0 +1,240 @@
+//! Inlay Hints
+//!
+//! State the machine is left in after each line: the absolute position in
+//! the units in effect, the feedrate and the extruded length of moves, plus
+//! the unit after each parameter whose flavor description names one.
+
+use std::ops::Range;
+
+use lsp_types::{InlayHint, InlayHintLabel, InlayHintTooltip, Position};
+
+use crate::core::diagnostics::utf16_column;
+use crate::core::format::format_number;
+use crate::flavor::registry::FlavorRegistry;
+use crate::machine::MachineState;
+use crate::machine::state::{PositioningMode, Units};
+use crate::parser::{Command, ParsedDocument, ParsedLine};
+
+/// Commands that move or set the position
+const POSITION_COMMANDS: &[&str] = &["G0", "G1", "G2", "G3", "G28", "G92"];
+
+/// Commands that move at the feedrate and may extrude
+const MOVE_COMMANDS: &[&str] = &["G0", "G1", "G2", "G3"];
+
+/// Inlay hints for the zero-based `lines` of a document
+///
+/// `state` is the machine state before the first line of the range, e.g.
+/// from the document's simulation.
+pub fn inlay_hints(
+    content: &str,
+    parsed: &ParsedDocument,
+    flavor: &FlavorRegistry,
+    lines: Range<usize>,
+    mut state: MachineState,
+) -> Vec<InlayHint> {
+    let mut hints = Vec::new();
+
+    let numbered = content.lines().zip(parsed.iter()).enumerate();
+    for (idx, (text, line)) in numbered.take(lines.end).skip(lines.start) {
+        // Klipper heater commands get no hints but change the state
+        if state.apply_extended_command(text) {
+            continue;
+        }
+        let ParsedLine::Command(command) = line else {
+            continue;
+        };
+
+        let before = state.clone();
+        state.apply(command);
+
+        let line_number = idx as u32;
+        hints.extend(parameter_hints(text, command, flavor, line_number));
+        hints.extend(state_hint(text, command, &before, &state, line_number));
+    }
+
+    hints
+}
+
+/// Units after parameters, e.g. `°C` after `S215` on M104
+fn parameter_hints(
+    text: &str,
+    command: &Command,
+    flavor: &FlavorRegistry,
+    line: u32,
+) -> Vec<InlayHint> {
+    let Some(definition) = flavor.get_command(&command.name.to_uppercase()) else {
+        return Vec::new();
+    };
+
+    command
+        .parameters
+        .iter()
+        .filter(|param| !param.value.is_empty())
+        .filter_map(|param| {
+            let unit = definition
+                .find_parameter(&param.letter.to_string())?
+                .unit()?;
+            Some(hint(
+                Position::new(line, utf16_column(text, param.span.end)),
+                unit.to_string(),
+                None,
+            ))
+        })
+        .collect()
+}
+
+/// Position, feedrate and extrusion after a move, at the end of the line
+fn state_hint(
+    text: &str,
+    command: &Command,
+    before: &MachineState,
+    after: &MachineState,
+    line: u32,
+) -> Option<InlayHint> {
+    let name = command.name.to_uppercase();
+    if !POSITION_COMMANDS.contains(&name.as_str()) {
+        return None;
+    }
+
+    let mut parts = Vec::new();
+    parts.extend(position_label(after));
+    if MOVE_COMMANDS.contains(&name.as_str()) {
+        if let Some(feedrate) = after.feedrate {
+            parts.push(format!("{} mm/s", format_number(feedrate / 60.0)));
+        }
+        if let Some(length) = extruded_length(command, before, after)
+            && length != 0.0
+        {
+            let verb = if length > 0.0 { "extrudes" } else { "retracts" };
+            parts.push(format!("{} {} mm", verb, format_number(length.abs())));
+        }
+    }
+    if parts.is_empty() {
+        return None;
+    }
+
+    let tooltip = format!(
+        "Machine state after line {}: {}",
+        line + 1,
+        after.active_modes().join(" ")
+    );
+    Some(hint(
+        Position::new(line, utf16_column(text, text.len())),
+        parts.join(" · "),
+        Some(tooltip),
+    ))
+}
+
+/// Known axis positions in the units in effect, e.g. `X10 Y5 Z0.2 mm`
+pub(crate) fn position_label(state: &MachineState) -> Option<String> {
+    let (scale, unit) = match state.units {
+        Units::Millimeters => (1.0, "mm"),
+        Units::Inches => (25.4, "in"),
+    };
+    let axes: Vec<String> = ['X', 'Y', 'Z', 'E']
+        .into_iter()
+        .filter_map(|axis| {
+            let value = state.position.axis(axis)?;
+            Some(format!("{}{}", axis, format_number(value / scale)))
+        })
+        .collect();
+
+    if axes.is_empty() {
+        None
+    } else {
+        Some(format!("{} {}", axes.join(" "), unit))
+    }
+}
+
+/// Filament pushed by a move in millimeters, negative for retractions
+fn extruded_length(command: &Command, before: &MachineState, after: &MachineState) -> Option<f64> {
+    let param = command
+        .parameters
+        .iter()
+        .find(|p| p.letter.eq_ignore_ascii_case(&'E'))?;
+
+    match (before.position.e, after.position.e) {
+        (Some(start), Some(end)) => Some(end - start),
+        // Relative lengths are known even before the extruder is reset
+        _ if after.extruder_positioning == PositioningMode::Relative => param
+            .value
+            .parse()
+            .ok()
+            .map(|value| after.to_millimeters(value)),
+        _ => None,
+    }
+}
+
+fn hint(position: Position, label: String, tooltip: Option<String>) -> InlayHint {
+    InlayHint {
+        position,
+        label: InlayHintLabel::String(label),
+        kind: None,
+        text_edits: None,
+        tooltip: tooltip.map(InlayHintTooltip::String),
+        padding_left: Some(true),
+        padding_right: None,
+        data: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::Simulation;
+
+    fn labels(content: &str, lines: Range<usize>) -> Vec<(u32, u32, String)> {
+        let mut flavor = FlavorRegistry::new();
+        flavor.add_embedded_prusa_flavor();
+        flavor.set_active_flavor("prusa");
+
+        let parsed = ParsedDocument::parse(content);
+        let state = Simulation::run(content, &parsed).state_before(content, &parsed, lines.start);
+        inlay_hints(content, &parsed, &flavor, lines, state.state)
+            .into_iter()
+            .map(|hint| {
+                let InlayHintLabel::String(label) = hint.label else {
+                    panic!("expected a string label");
+                };
+                (hint.position.line, hint.position.character, label)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_relative_moves_resolved() {
+        let content = "G28\nG91\nM83\nG1 X10 Y5 E0.5 F1800\nG1 X-2.5 E-0.8\n";
+        let hints = labels(content, 3..5);
+
+        assert_eq!(
+            hints,
+            vec![
+                (
+                    3,
+                    20,
+                    "X10 Y5 Z0 mm · 30 mm/s · extrudes 0.5 mm".to_string()
+                ),
+                (
+                    4,
+                    14,
+                    "X7.5 Y5 Z0 mm · 30 mm/s · retracts 0.8 mm".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_units_in_effect() {
+        let content = "G20\nG28\nG1 X1.5 F60\n";
+        let hints = labels(content, 0..3);
+
+        assert_eq!(hints[0], (1, 3, "X0 Y0 Z0 in".to_string()));
+        assert_eq!(hints[1], (2, 11, "X1.5 Y0 Z0 in · 25.4 mm/s".to_string()));
+    }
+
+    #[test]
+    fn test_parameter_units() {
+        let hints = labels("M104 S215 ; é\nM140 S60\n", 0..1);
+        assert_eq!(hints, vec![(0, 9, "°C".to_string())]);
+    }
+}
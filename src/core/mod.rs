@@ -1,9 +1,20 @@
 //! Core Business Logic
 //!
-//! Document management and core LSP functionality.
+//! Document management, workspace files, diagnostics, completion, hover,
+//! highlights, colors, inlay hints, thumbnails and workspace symbols,
+//! independent of the LSP transport.
 
+pub mod colors;
+pub mod completion;
 pub mod diagnostics;
 pub mod document;
+pub mod files;
+pub mod format;
+pub mod highlight;
+pub mod hover;
+pub mod inlay_hints;
+pub mod thumbnails;
+pub mod workspace_symbols;
 
-pub use diagnostics::DiagnosticProvider;
-pub use document::DocumentManager;
+pub use diagnostics::{DiagnosticProvider, DiagnosticSink, FileStamp};
+pub use document::{DocumentConfig, DocumentManager, DocumentSnapshot, DocumentState};
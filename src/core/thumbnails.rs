@@ -0,0 +1,204 @@
+//! Thumbnails
+//!
+//! Embedded slicer thumbnails: folding their blocks, previewing them in
+//! hover, and exporting the decoded image through a code lens.
+
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use lsp_types::{
+    CodeLens, Command, FoldingRange, FoldingRangeKind, Hover, HoverContents, MarkupContent,
+    MarkupKind, Position, Range, Url,
+};
+
+use crate::core::diagnostics::utf16_column;
+use crate::parser::thumbnail::{ThumbnailBlock, ThumbnailFormat, thumbnail_blocks};
+
+/// Command run by the export code lens, with the document URI and the line
+/// of the block's `begin` comment as arguments
+pub const EXPORT_COMMAND: &str = "gcode-ls.exportThumbnail";
+
+/// Fold each thumbnail block into its `begin` line
+pub fn folding_ranges(content: &str) -> Vec<FoldingRange> {
+    thumbnail_blocks(content.lines())
+        .into_iter()
+        .filter(|block| block.end > block.begin)
+        .map(|block| FoldingRange {
+            start_line: block.begin as u32,
+            start_character: None,
+            end_line: block.end as u32,
+            end_character: None,
+            kind: Some(FoldingRangeKind::Comment),
+            collapsed_text: Some(format!("thumbnail {}", block.label())),
+        })
+        .collect()
+}
+
+/// Preview the thumbnail whose block holds `position`
+pub fn thumbnail_hover(content: &str, position: Position) -> Option<Hover> {
+    let line = position.line as usize;
+    let block = thumbnail_blocks(content.lines())
+        .into_iter()
+        .find(|block| block.lines().contains(&line))?;
+
+    let last = content.lines().nth(block.end).unwrap_or("");
+    let mut text = format!("**Thumbnail** {}", block.label());
+    match block.format {
+        // Hover renders Markdown, whose images are shown by a browser engine
+        ThumbnailFormat::Png | ThumbnailFormat::Jpg if block.decode().is_ok() => {
+            text.push_str(&format!(
+                "\n\n![thumbnail](data:{};base64,{})",
+                block.format.mime_type(),
+                block.data
+            ));
+        }
+        ThumbnailFormat::Qoi => {
+            text.push_str("\n\nQOI images cannot be previewed; export it to view it.")
+        }
+        _ => text.push_str("\n\nThe image data is not valid base64."),
+    }
+
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: text,
+        }),
+        range: Some(Range::new(
+            Position::new(block.begin as u32, 0),
+            Position::new(block.end as u32, utf16_column(last, last.len())),
+        )),
+    })
+}
+
+/// An export lens above each closed thumbnail block
+pub fn code_lenses(content: &str, uri: &Url) -> Vec<CodeLens> {
+    thumbnail_blocks(content.lines())
+        .into_iter()
+        .filter(|block| block.closed)
+        .map(|block| {
+            let start = Position::new(block.begin as u32, 0);
+            CodeLens {
+                range: Range::new(start, start),
+                command: Some(Command {
+                    title: format!("Export {} thumbnail", block.label()),
+                    command: EXPORT_COMMAND.to_string(),
+                    arguments: Some(vec![uri.to_string().into(), block.begin.into()]),
+                }),
+                data: None,
+            }
+        })
+        .collect()
+}
+
+/// Write the thumbnail starting at line `begin` next to the document
+///
+/// The file is named after the document and the image size, e.g.
+/// `benchy.thumbnail-16x16.png`, with a counter such as
+/// `benchy.thumbnail-16x16-2.png` if that exists. Existing files are never
+/// overwritten. Returns the path written.
+pub fn export_thumbnail(content: &str, begin: usize, document: &Path) -> Result<PathBuf> {
+    let Some(block) = thumbnail_blocks(content.lines())
+        .into_iter()
+        .find(|block| block.begin == begin)
+    else {
+        bail!("No thumbnail starts at line {}", begin + 1);
+    };
+    let bytes = block.decode().context("Thumbnail is not valid base64")?;
+
+    for copy in 1.. {
+        let path = export_path(&block, document, copy);
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path);
+        match file {
+            Ok(mut file) => {
+                file.write_all(&bytes)
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+                return Ok(path);
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to create {}", path.display()));
+            }
+        }
+    }
+    unreachable!("every copy number was taken")
+}
+
+fn export_path(block: &ThumbnailBlock, document: &Path, copy: usize) -> PathBuf {
+    let stem = document
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "gcode".to_string());
+    let suffix = match copy {
+        1 => String::new(),
+        copy => format!("-{}", copy),
+    };
+    document.with_file_name(format!(
+        "{}.thumbnail-{}x{}{}.{}",
+        stem,
+        block.width,
+        block.height,
+        suffix,
+        block.format.extension()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENT: &str = "; thumbnail begin 2x1 8\n; iVBO\n; Rw0K\n; thumbnail end\n\
+                           ; thumbnail_QOI begin 4x4 4\n; cW9p\n; thumbnail_QOI end\nG28\n";
+
+    #[test]
+    fn test_folding_and_lenses() {
+        let folds = folding_ranges(CONTENT);
+        assert_eq!(folds.len(), 2);
+        assert_eq!((folds[0].start_line, folds[0].end_line), (0, 3));
+        assert_eq!(
+            folds[1].collapsed_text.as_deref(),
+            Some("thumbnail 4x4 QOI")
+        );
+
+        let uri = Url::parse("file:///tmp/benchy.gcode").unwrap();
+        let lenses = code_lenses(CONTENT, &uri);
+        let command = lenses[1].command.as_ref().unwrap();
+        assert_eq!(command.title, "Export 4x4 QOI thumbnail");
+        assert_eq!(
+            command.arguments,
+            Some(vec!["file:///tmp/benchy.gcode".into(), 4.into()])
+        );
+    }
+
+    #[test]
+    fn test_hover_preview() {
+        let Some(Hover {
+            contents: HoverContents::Markup(markup),
+            ..
+        }) = thumbnail_hover(CONTENT, Position::new(2, 3))
+        else {
+            panic!("Expected a hover");
+        };
+        assert!(markup.value.contains("(data:image/png;base64,iVBORw0K)"));
+        assert!(thumbnail_hover(CONTENT, Position::new(7, 0)).is_none());
+    }
+
+    #[test]
+    fn test_export() {
+        let dir = tempfile::tempdir().unwrap();
+        let document = dir.path().join("benchy.gcode");
+
+        let path = export_thumbnail(CONTENT, 4, &document).unwrap();
+        assert_eq!(path, dir.path().join("benchy.thumbnail-4x4.qoi"));
+        assert_eq!(std::fs::read(path).unwrap(), b"qoi");
+        assert!(export_thumbnail(CONTENT, 1, &document).is_err());
+
+        // An earlier export is kept
+        let path = export_thumbnail(CONTENT, 4, &document).unwrap();
+        assert_eq!(path, dir.path().join("benchy.thumbnail-4x4-2.qoi"));
+        assert!(dir.path().join("benchy.thumbnail-4x4.qoi").exists());
+    }
+}
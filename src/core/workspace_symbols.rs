@@ -0,0 +1,236 @@
+//! Workspace Symbols
+//!
+//! Landmarks of G-code files: layer markers, printed objects, tool changes,
+//! `;TYPE:` sections and subroutine labels. The index keeps them per file so
+//! `workspace/symbol` searches the workspace without reading it again.
+
+use std::collections::BTreeMap;
+
+use lsp_types::{Location, Position, Range, SymbolInformation, SymbolKind, Url};
+
+use crate::core::diagnostics::utf16_column;
+
+/// Most symbols answered for one query
+pub const MAX_RESULTS: usize = 1000;
+
+/// A named place in a file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Landmark {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub range: Range,
+    /// Layer the landmark is in, if the slicer marks layers
+    pub layer: Option<String>,
+}
+
+/// Landmarks of every indexed file, by URI
+#[derive(Debug, Default)]
+pub struct WorkspaceIndex {
+    files: BTreeMap<Url, Vec<Landmark>>,
+}
+
+impl WorkspaceIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Index a file, replacing what was known about it
+    pub fn update(&mut self, uri: Url, content: &str) {
+        self.insert(uri, landmarks(content));
+    }
+
+    /// Store landmarks found elsewhere, e.g. off the lock
+    pub fn insert(&mut self, uri: Url, landmarks: Vec<Landmark>) {
+        self.files.insert(uri, landmarks);
+    }
+
+    pub fn remove(&mut self, uri: &Url) {
+        self.files.remove(uri);
+    }
+
+    pub fn contains(&self, uri: &Url) -> bool {
+        self.files.contains_key(uri)
+    }
+
+    /// Landmarks whose name contains the query's characters in order,
+    /// ignoring case, by file and line
+    pub fn search(&self, query: &str) -> Vec<SymbolInformation> {
+        let query = query.to_lowercase();
+        self.files
+            .iter()
+            .flat_map(|(uri, landmarks)| landmarks.iter().map(move |landmark| (uri, landmark)))
+            .filter(|(_, landmark)| is_subsequence(&query, &landmark.name.to_lowercase()))
+            .take(MAX_RESULTS)
+            .map(|(uri, landmark)| {
+                #[allow(deprecated)]
+                SymbolInformation {
+                    name: landmark.name.clone(),
+                    kind: landmark.kind,
+                    tags: None,
+                    deprecated: None,
+                    location: Location::new(uri.clone(), landmark.range),
+                    container_name: landmark.layer.clone(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Find the landmarks of a file
+pub fn landmarks(content: &str) -> Vec<Landmark> {
+    let mut landmarks = Vec::new();
+    let mut layer: Option<String> = None;
+    // PrusaSlicer marks layers without numbering them
+    let mut layer_changes = 0;
+
+    for (idx, line) in content.lines().enumerate() {
+        let (code, comment) = match line.split_once(';') {
+            Some((code, comment)) => (code, Some(comment.trim())),
+            None => (line, None),
+        };
+
+        let found = match comment {
+            Some(comment) if code.trim().is_empty() => {
+                if let Some(number) = comment.strip_prefix("LAYER:") {
+                    Some((format!("Layer {}", number.trim()), SymbolKind::NAMESPACE))
+                } else if comment == "LAYER_CHANGE" {
+                    layer_changes += 1;
+                    Some((
+                        format!("Layer {}", layer_changes - 1),
+                        SymbolKind::NAMESPACE,
+                    ))
+                } else {
+                    comment
+                        .strip_prefix("TYPE:")
+                        .map(|feature| (feature.trim().to_string(), SymbolKind::ENUM_MEMBER))
+                }
+            }
+            _ => code_landmark(code),
+        };
+        let Some((name, kind)) = found else {
+            continue;
+        };
+        if name.is_empty() {
+            continue;
+        }
+
+        if kind == SymbolKind::NAMESPACE {
+            layer = Some(name.clone());
+        }
+        let start = line.len() - line.trim_start().len();
+        landmarks.push(Landmark {
+            range: Range::new(
+                Position::new(idx as u32, utf16_column(line, start)),
+                Position::new(idx as u32, utf16_column(line, line.trim_end().len())),
+            ),
+            layer: if kind == SymbolKind::NAMESPACE {
+                None
+            } else {
+                layer.clone()
+            },
+            name,
+            kind,
+        });
+    }
+
+    landmarks
+}
+
+/// Objects, tool changes and subroutines declared by a line's code
+fn code_landmark(code: &str) -> Option<(String, SymbolKind)> {
+    let mut words = code.split_whitespace();
+    let first = words.next()?.to_uppercase();
+
+    match first.as_str() {
+        // Marlin and Prusa label objects with `M486 A<name>`
+        "M486" => words
+            .find_map(|word| word.strip_prefix(['A', 'a']))
+            .map(|name| (unquote(name), SymbolKind::OBJECT)),
+        // Klipper defines them with `EXCLUDE_OBJECT_DEFINE NAME=<name>`
+        "EXCLUDE_OBJECT_DEFINE" => words
+            .find_map(|word| {
+                let (key, value) = word.split_once('=')?;
+                key.eq_ignore_ascii_case("NAME").then_some(value)
+            })
+            .map(|name| (unquote(name), SymbolKind::OBJECT)),
+        tool if tool.len() > 1
+            && tool.starts_with('T')
+            && tool[1..].chars().all(|c| c.is_ascii_digit()) =>
+        {
+            Some((tool.to_string(), SymbolKind::VARIABLE))
+        }
+        // LinuxCNC subroutines: `o<name> sub` or `o100 sub`
+        label
+            if label.starts_with('O')
+                && words
+                    .next()
+                    .is_some_and(|word| word.eq_ignore_ascii_case("sub")) =>
+        {
+            Some((
+                code.split_whitespace().next()?.to_string(),
+                SymbolKind::FUNCTION,
+            ))
+        }
+        _ => None,
+    }
+}
+
+fn unquote(name: &str) -> String {
+    name.trim_matches(['"', '\'']).to_string()
+}
+
+fn is_subsequence(query: &str, text: &str) -> bool {
+    let mut chars = text.chars();
+    query.chars().all(|c| chars.any(|t| t == c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(content: &str) -> Vec<(String, SymbolKind, Option<String>)> {
+        landmarks(content)
+            .into_iter()
+            .map(|landmark| (landmark.name, landmark.kind, landmark.layer))
+            .collect()
+    }
+
+    #[test]
+    fn test_landmarks() {
+        let content = "M486 S0 A\"Benchy\"\n;LAYER_CHANGE\n;Z:0.2\n;TYPE:Skirt\nG1 X1 ; T2\nT1\n\
+                       EXCLUDE_OBJECT_DEFINE NAME=cube_1 CENTER=10,10\no<purge> sub\n;LAYER:7\n";
+        let layer = Some("Layer 0".to_string());
+
+        assert_eq!(
+            names(content),
+            vec![
+                ("Benchy".to_string(), SymbolKind::OBJECT, None),
+                ("Layer 0".to_string(), SymbolKind::NAMESPACE, None),
+                ("Skirt".to_string(), SymbolKind::ENUM_MEMBER, layer.clone()),
+                ("T1".to_string(), SymbolKind::VARIABLE, layer.clone()),
+                ("cube_1".to_string(), SymbolKind::OBJECT, layer.clone()),
+                ("o<purge>".to_string(), SymbolKind::FUNCTION, layer),
+                ("Layer 7".to_string(), SymbolKind::NAMESPACE, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_search() {
+        let mut index = WorkspaceIndex::new();
+        let a = Url::parse("file:///a.gcode").unwrap();
+        let b = Url::parse("file:///b.gcode").unwrap();
+        index.update(a.clone(), "M486 S0 Abenchy\n");
+        index.update(b.clone(), "  M486 S1 ABench_2 ; é\n");
+
+        let found = index.search("BNCH");
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[1].location.uri, b);
+        assert_eq!(found[1].location.range.start, Position::new(0, 2));
+        assert_eq!(found[1].location.range.end, Position::new(0, 22));
+        assert!(index.search("cube").is_empty());
+
+        index.remove(&b);
+        assert_eq!(index.search("").len(), 1);
+    }
+}
@@ -2,14 +2,27 @@
 //!
 //! Simple in-memory registry - much cleaner than the complex async version.
 
-use super::schema::{CommandDef, Flavor};
+use super::schema::{CommandDef, Flavor, FlavorFile, SnippetDef, SnippetFile};
+use anyhow::{Context, Result};
 use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+/// File name suffix of flavor files
+pub const FLAVOR_FILE_SUFFIX: &str = ".gcode-flavor.toml";
+
+/// File name suffix of user snippet files
+pub const SNIPPET_FILE_SUFFIX: &str = ".gcode-snippets.toml";
 
 /// Simple in-memory flavor registry
+///
+/// Flavors and snippets are shared, so snapshots are cheap to take.
 #[derive(Debug, Clone)]
 pub struct FlavorRegistry {
-    flavors: HashMap<String, Flavor>,
+    flavors: HashMap<String, Arc<Flavor>>,
     active_flavor: Option<String>,
+    /// Snippets loaded from snippet directories, in load order
+    user_snippets: Arc<Vec<SnippetFile>>,
 }
 
 impl Default for FlavorRegistry {
@@ -23,12 +36,13 @@ impl FlavorRegistry {
         Self {
             flavors: HashMap::new(),
             active_flavor: None,
+            user_snippets: Arc::default(),
         }
     }
 
     /// Add a flavor to the registry
     pub fn add_flavor(&mut self, flavor: Flavor) {
-        self.flavors.insert(flavor.name.clone(), flavor);
+        self.flavors.insert(flavor.name.clone(), Arc::new(flavor));
     }
 
     /// Set the active flavor
@@ -46,6 +60,12 @@ impl FlavorRegistry {
         self.active_flavor
             .as_ref()
             .and_then(|name| self.flavors.get(name))
+            .map(|flavor| &**flavor)
+    }
+
+    /// Get a flavor by name
+    pub fn get_flavor(&self, name: &str) -> Option<&Flavor> {
+        self.flavors.get(name).map(|flavor| &**flavor)
     }
 
     /// List all available flavors
@@ -58,10 +78,31 @@ impl FlavorRegistry {
         self.get_active_flavor()?.commands.get(name)
     }
 
+    /// Snippets available with the active flavor
+    ///
+    /// User snippets for all flavors or for the active one come after the
+    /// flavor's own, and replace flavor snippets of the same name.
+    pub fn snippets(&self) -> Vec<&SnippetDef> {
+        let active = self.active_flavor.as_deref();
+        let user: Vec<&SnippetDef> = self
+            .user_snippets
+            .iter()
+            .filter(|file| file.flavor.is_none() || file.flavor.as_deref() == active)
+            .flat_map(|file| &file.snippets)
+            .collect();
+
+        let mut snippets: Vec<&SnippetDef> = self
+            .get_active_flavor()
+            .into_iter()
+            .flat_map(|flavor| &flavor.snippets)
+            .filter(|snippet| !user.iter().any(|u| u.name == snippet.name))
+            .collect();
+        snippets.extend(user);
+        snippets
+    }
+
     /// Add comprehensive embedded Prusa flavor with rich command definitions
     pub fn add_embedded_prusa_flavor(&mut self) {
-        use crate::flavor::schema::{Flavor, FlavorFile};
-
         // Load embedded TOML content
         let embedded_toml = include_str!("../../resources/flavors/prusa.gcode-flavor.toml");
 
@@ -99,6 +140,8 @@ impl FlavorRegistry {
                 ),
                 parameters: None,
                 constraints: None,
+                deprecated: false,
+                replaced_by: None,
             },
         );
 
@@ -110,6 +153,8 @@ impl FlavorRegistry {
                 description_long: Some("Linear move with extrusion".to_string()),
                 parameters: None,
                 constraints: None,
+                deprecated: false,
+                replaced_by: None,
             },
         );
 
@@ -118,58 +163,166 @@ impl FlavorRegistry {
             version: Some("minimal-fallback".to_string()),
             description: Some("Minimal fallback Prusa flavor".to_string()),
             commands,
+            snippets: Vec::new(),
         };
 
         self.add_flavor(flavor);
     }
 
+    /// Share one flavor with a new registry where it is active
+    ///
+    /// Falls back to the active flavor when `name` is `None` or unknown.
+    /// Used to validate without holding on to the shared registry.
+    pub fn snapshot(&self, name: Option<&str>) -> FlavorRegistry {
+        let flavor = name.and_then(|name| self.flavors.get(name)).or_else(|| {
+            self.active_flavor
+                .as_ref()
+                .and_then(|name| self.flavors.get(name))
+        });
+
+        let mut snapshot = FlavorRegistry::new();
+        snapshot.user_snippets = Arc::clone(&self.user_snippets);
+        if let Some(flavor) = flavor {
+            snapshot
+                .flavors
+                .insert(flavor.name.clone(), Arc::clone(flavor));
+            snapshot.active_flavor = Some(flavor.name.clone());
+        }
+        snapshot
+    }
+
+    /// Pick the flavor for a document
+    ///
+    /// A modeline wins, then the `configured` flavor if it exists, then the
+    /// active flavor.
+    pub fn select_flavor(&self, content: &str, configured: Option<&str>) -> Option<String> {
+        self.detect_modeline_flavor(content)
+            .or_else(|| {
+                configured
+                    .filter(|name| self.flavors.contains_key(*name))
+                    .map(String::from)
+            })
+            .or_else(|| self.active_flavor.clone())
+    }
+
+    /// Load a flavor from a TOML file
+    pub fn load_file(&mut self, path: &Path) -> Result<()> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read flavor {:?}", path))?;
+        let flavor_file: FlavorFile = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse flavor {:?}", path))?;
+        self.add_flavor(Flavor::from(flavor_file));
+        Ok(())
+    }
+
+    /// Load every `*.gcode-flavor.toml` file in a directory
+    ///
+    /// A missing directory is not an error. Invalid files are logged and skipped.
+    pub fn load_directory(&mut self, dir: &Path) -> usize {
+        load_matching_files(dir, FLAVOR_FILE_SUFFIX, |path| self.load_file(path))
+    }
+
+    /// Load user snippets from a TOML file
+    pub fn load_snippet_file(&mut self, path: &Path) -> Result<()> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read snippets {:?}", path))?;
+        let snippet_file: SnippetFile = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse snippets {:?}", path))?;
+        Arc::make_mut(&mut self.user_snippets).push(snippet_file);
+        Ok(())
+    }
+
+    /// Load every `*.gcode-snippets.toml` file in a directory
+    ///
+    /// A missing directory is not an error. Invalid files are logged and skipped.
+    pub fn load_snippet_directory(&mut self, dir: &Path) -> usize {
+        load_matching_files(dir, SNIPPET_FILE_SUFFIX, |path| {
+            self.load_snippet_file(path)
+        })
+    }
+
     /// Detect flavor from modeline in document content
     pub fn detect_modeline_flavor(&self, content: &str) -> Option<String> {
-        // Check first and last few lines for modeline
-        let lines: Vec<&str> = content.lines().collect();
-        let check_lines: Vec<&str> = if lines.len() <= 10 {
-            lines
-        } else {
-            // Check first 5 and last 5 lines
-            let mut check = Vec::new();
-            check.extend_from_slice(&lines[0..5]);
-            check.extend_from_slice(&lines[lines.len() - 5..]);
-            check
-        };
+        // Look for patterns like:
+        // ; vim: gcode_flavor=prusa
+        // ; gcode_flavor=prusa
+        // // gcode_flavor=prusa
+        modeline_values(content, "gcode_flavor")
+            .into_iter()
+            // Verify the flavor exists in registry
+            .find(|flavor_name| self.flavors.contains_key(flavor_name))
+    }
+}
 
-        for line in check_lines {
-            // Look for patterns like:
-            // ; vim: gcode_flavor=prusa
-            // ; gcode_flavor=prusa
-            // // gcode_flavor=prusa
-            if let Some(flavor_name) = extract_flavor_from_modeline(line) {
-                // Verify the flavor exists in registry
-                if self.flavors.contains_key(&flavor_name) {
-                    return Some(flavor_name);
-                }
-            }
+/// Load the files in `dir` whose name ends with `suffix`, counting successes
+fn load_matching_files(
+    dir: &Path,
+    suffix: &str,
+    mut load: impl FnMut(&Path) -> Result<()>,
+) -> usize {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+
+    let mut loaded = 0;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let matches = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.ends_with(suffix));
+        if !matches {
+            continue;
+        }
+
+        match load(&path) {
+            Ok(()) => loaded += 1,
+            Err(e) => log::warn!("{:#}", e),
         }
+    }
+
+    loaded
+}
 
-        None
+/// Number of lines at each end of a document searched for modelines
+const MODELINE_LINES: usize = 5;
+
+/// Collect `key=value` modeline values from the first and last lines of a document
+///
+/// Only those lines are read, so this is cheap on large documents.
+pub(crate) fn modeline_values(content: &str, key: &str) -> Vec<String> {
+    let mut check_lines: Vec<&str> = content.lines().take(2 * MODELINE_LINES + 1).collect();
+    if check_lines.len() > 2 * MODELINE_LINES {
+        check_lines.truncate(MODELINE_LINES);
+        let tail = content.lines().rev().take(MODELINE_LINES);
+        let start = check_lines.len();
+        check_lines.extend(tail);
+        check_lines[start..].reverse();
     }
+
+    check_lines
+        .into_iter()
+        .filter_map(|line| extract_modeline_value(line, key))
+        .collect()
 }
 
-/// Extract flavor name from a modeline string
-fn extract_flavor_from_modeline(line: &str) -> Option<String> {
-    // Simple pattern matching for gcode_flavor=name
-    if let Some(start) = line.find("gcode_flavor=") {
-        let flavor_part = &line[start + 13..]; // Skip "gcode_flavor="
-        let end = flavor_part
+/// Extract the value of `key=value` from a modeline string
+fn extract_modeline_value(line: &str, key: &str) -> Option<String> {
+    // Simple pattern matching for key=name
+    let pattern = format!("{}=", key);
+    if let Some(start) = line.find(&pattern) {
+        let value_part = &line[start + pattern.len()..];
+        let end = value_part
             .find(|c: char| c.is_whitespace() || c == ';' || c == '#')
-            .unwrap_or(flavor_part.len());
-        let flavor_name = &flavor_part[..end];
+            .unwrap_or(value_part.len());
+        let value = &value_part[..end];
 
-        if !flavor_name.is_empty()
-            && flavor_name
+        if !value.is_empty()
+            && value
                 .chars()
                 .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
         {
-            return Some(flavor_name.to_string());
+            return Some(value.to_string());
         }
     }
     None
@@ -198,6 +351,7 @@ mod tests {
                 description: None,
             },
             commands: vec![],
+            snippets: vec![],
         };
         let flavor = Flavor::from(file);
 
@@ -224,7 +378,10 @@ mod tests {
                 description_long: None,
                 parameters: None,
                 constraints: None,
+                deprecated: false,
+                replaced_by: None,
             }],
+            snippets: vec![],
         };
         let flavor = Flavor::from(file);
 
@@ -246,4 +403,81 @@ mod tests {
         assert!(!registry.set_active_flavor("nonexistent"));
         assert!(registry.get_command("G1").is_none());
     }
+
+    #[test]
+    fn test_bundled_flavor_files_parse() {
+        for content in [
+            include_str!("../../resources/flavors/prusa.gcode-flavor.toml"),
+            include_str!("../../resources/flavors/marlin.gcode-flavor.toml"),
+            include_str!("../../resources/flavors/klipper.gcode-flavor.toml"),
+        ] {
+            let file: FlavorFile = toml::from_str(content).expect("bundled flavor parses");
+            assert!(!file.commands.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_load_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("custom.gcode-flavor.toml"),
+            include_str!("../../resources/flavors/marlin.gcode-flavor.toml"),
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("broken.gcode-flavor.toml"), "[flavor").unwrap();
+        std::fs::write(dir.path().join("notes.toml"), "").unwrap();
+
+        let mut registry = FlavorRegistry::new();
+        assert_eq!(registry.load_directory(dir.path()), 1);
+        assert!(registry.set_active_flavor("marlin"));
+        assert_eq!(registry.load_directory(&dir.path().join("missing")), 0);
+    }
+
+    #[test]
+    fn test_modeline_values() {
+        // Only the first and last five lines are searched
+        let mut lines = ["G1 X1"; 11];
+        lines[0] = "; gcode_flavor=prusa";
+        lines[5] = "; gcode_flavor=middle";
+        lines[10] = "; gcode_flavor=marlin";
+        assert_eq!(
+            modeline_values(&lines.join("\n"), "gcode_flavor"),
+            vec!["prusa".to_string(), "marlin".to_string()]
+        );
+        assert_eq!(
+            modeline_values(&lines[..10].join("\n"), "gcode_flavor"),
+            vec!["prusa".to_string(), "middle".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_user_snippets() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("mine.gcode-snippets.toml"),
+            "[[snippets]]\nname = \"cool-down\"\nbody = \"M104 S0\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("klipper.gcode-snippets.toml"),
+            "flavor = \"klipper\"\n[[snippets]]\nname = \"flush\"\nbody = \"M400\"\n",
+        )
+        .unwrap();
+
+        let mut registry = FlavorRegistry::new();
+        registry.add_embedded_prusa_flavor();
+        registry.set_active_flavor("prusa");
+        assert_eq!(registry.load_snippet_directory(dir.path()), 2);
+
+        let snapshot = registry.snapshot(None);
+        let snippets = snapshot.snippets();
+        let cool_down: Vec<_> = snippets.iter().filter(|s| s.name == "cool-down").collect();
+        assert_eq!(cool_down.len(), 1);
+        assert_eq!(cool_down[0].body, "M104 S0");
+        assert!(!snippets.iter().any(|s| s.name == "flush"));
+        assert!(Arc::ptr_eq(
+            &snapshot.flavors["prusa"],
+            &registry.flavors["prusa"]
+        ));
+    }
 }
@@ -3,13 +3,34 @@
 //! Clean, simple types for flavor definitions - much simpler than the verbose legacy version.
 
 use serde::Deserialize;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 /// Root flavor file structure (matches TOML)
 #[derive(Debug, Clone, Deserialize, PartialEq)]
 pub struct FlavorFile {
     pub flavor: FlavorMeta,
     pub commands: Vec<CommandDef>,
+    #[serde(default)]
+    pub snippets: Vec<SnippetDef>,
+}
+
+/// User snippet file structure (`*.gcode-snippets.toml`)
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct SnippetFile {
+    /// Flavor the snippets apply to, all flavors if unset
+    pub flavor: Option<String>,
+    pub snippets: Vec<SnippetDef>,
+}
+
+/// A reusable G-code sequence offered by completion
+///
+/// The body uses LSP snippet syntax: `${1:215}` is the first placeholder with
+/// its default value, and `$0` is where the cursor ends up.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct SnippetDef {
+    pub name: String,
+    pub description: Option<String>,
+    pub body: String,
 }
 
 /// Flavor metadata
@@ -27,6 +48,7 @@ pub struct Flavor {
     pub version: Option<String>,
     pub description: Option<String>,
     pub commands: HashMap<String, CommandDef>,
+    pub snippets: Vec<SnippetDef>,
 }
 
 /// GCode command definition
@@ -37,6 +59,9 @@ pub struct CommandDef {
     pub description_long: Option<String>,
     pub parameters: Option<Vec<ParameterDef>>,
     pub constraints: Option<Vec<ParameterConstraint>>,
+    #[serde(default)]
+    pub deprecated: bool,
+    pub replaced_by: Option<String>,
 }
 
 /// Command parameter definition
@@ -77,6 +102,12 @@ pub struct ParameterConstraint {
     pub constraint_type: ConstraintType,
     pub parameters: Vec<String>,
     pub message: Option<String>,
+    /// Only evaluate the constraint when this condition holds
+    pub when: Option<ConstraintCondition>,
+    /// Comparison operator for `compare` constraints
+    pub operator: Option<ComparisonOperator>,
+    /// Right-hand side for `compare` constraints
+    pub than: Option<ConstraintOperand>,
 }
 
 /// Types of parameter constraints
@@ -86,6 +117,122 @@ pub enum ConstraintType {
     RequireAnyOf,
     RequireAllOf,
     MutuallyExclusive,
+    /// All parameters are required when the `when` condition holds
+    RequiresIf,
+    /// None of the parameters are allowed when the `when` condition holds
+    ForbiddenIf,
+    /// The first parameter must compare to `than` using `operator`
+    Compare,
+}
+
+/// Condition on the command parameters or on the modal state
+///
+/// All fields that are set must hold for the condition to be true.
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+pub struct ConstraintCondition {
+    /// Parameter that must be present
+    pub parameter: Option<String>,
+    /// Comparison applied to the parameter value (presence only if unset)
+    pub operator: Option<ComparisonOperator>,
+    /// Value the parameter is compared to
+    pub value: Option<f64>,
+    /// Modal command that must be in effect (e.g. "G17")
+    pub modal: Option<String>,
+}
+
+/// Numeric comparison operators
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ComparisonOperator {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+/// Right-hand side of a parameter comparison
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum ConstraintOperand {
+    /// Literal number
+    Value(f64),
+    /// Another parameter of the same command
+    Parameter(String),
+    /// Named setting from the context (e.g. "extruder_count")
+    Setting { setting: String },
+}
+
+/// Document state visible to constraint evaluation
+///
+/// Conditions that depend on missing state are treated as undecidable and
+/// the constraint is skipped rather than reported.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConstraintContext {
+    /// Modal commands in effect; empty when modal state is not tracked
+    pub modes: HashSet<String>,
+    /// Named numeric settings
+    pub settings: HashMap<String, f64>,
+}
+
+impl ComparisonOperator {
+    /// Apply the operator to two values
+    pub fn apply(self, left: f64, right: f64) -> bool {
+        match self {
+            Self::Lt => left < right,
+            Self::Le => left <= right,
+            Self::Gt => left > right,
+            Self::Ge => left >= right,
+            Self::Eq => left == right,
+            Self::Ne => left != right,
+        }
+    }
+
+    /// Symbol used in messages
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Gt => ">",
+            Self::Ge => ">=",
+            Self::Eq => "==",
+            Self::Ne => "!=",
+        }
+    }
+}
+
+impl ConstraintCondition {
+    /// Evaluate the condition, or `None` if it cannot be decided
+    fn evaluate(
+        &self,
+        cmd_parameters: &[(String, String)],
+        context: &ConstraintContext,
+    ) -> Option<bool> {
+        if let Some(name) = &self.parameter {
+            let Some(value) = parameter_value(cmd_parameters, name) else {
+                return Some(false);
+            };
+
+            if let (Some(operator), Some(expected)) = (self.operator, self.value) {
+                let actual: f64 = value.parse().ok()?;
+                if !operator.apply(actual, expected) {
+                    return Some(false);
+                }
+            }
+        }
+
+        if let Some(mode) = &self.modal {
+            if context.modes.is_empty() {
+                return None;
+            }
+            if !context.modes.contains(&mode.to_uppercase()) {
+                return Some(false);
+            }
+        }
+
+        Some(true)
+    }
 }
 
 impl From<FlavorFile> for Flavor {
@@ -102,6 +249,7 @@ impl From<FlavorFile> for Flavor {
             version: file.flavor.version,
             description: file.flavor.description,
             commands,
+            snippets: file.snippets,
         }
     }
 }
@@ -123,19 +271,53 @@ impl CommandDef {
             .unwrap_or_default()
     }
 
+    /// Describe a deprecated command and its replacement, if any
+    pub fn deprecation_message(&self) -> Option<String> {
+        if !self.deprecated {
+            return None;
+        }
+
+        Some(match &self.replaced_by {
+            Some(replacement) => format!(
+                "Command '{}' is deprecated, use '{}' instead",
+                self.name, replacement
+            ),
+            None => format!("Command '{}' is deprecated", self.name),
+        })
+    }
+
     /// Validate parameter constraints for a command
     pub fn validate_constraints(&self, cmd_parameters: &[String]) -> Vec<String> {
+        let cmd_parameters: Vec<(String, String)> = cmd_parameters
+            .iter()
+            .map(|name| (name.clone(), String::new()))
+            .collect();
+
+        self.validate_constraints_in_context(&cmd_parameters, &ConstraintContext::default())
+    }
+
+    /// Validate parameter constraints given parameter values and document state
+    ///
+    /// `cmd_parameters` holds `(name, raw value)` pairs.
+    pub fn validate_constraints_in_context(
+        &self,
+        cmd_parameters: &[(String, String)],
+        context: &ConstraintContext,
+    ) -> Vec<String> {
         let mut errors = Vec::new();
+        let has_param = |param_name: &String| parameter_value(cmd_parameters, param_name).is_some();
 
         if let Some(constraints) = &self.constraints {
             for constraint in constraints {
+                if let Some(condition) = &constraint.when
+                    && condition.evaluate(cmd_parameters, context) != Some(true)
+                {
+                    continue;
+                }
+
                 match constraint.constraint_type {
                     ConstraintType::RequireAnyOf => {
-                        let has_any = constraint.parameters.iter().any(|param_name| {
-                            cmd_parameters
-                                .iter()
-                                .any(|p| p.to_uppercase() == *param_name)
-                        });
+                        let has_any = constraint.parameters.iter().any(has_param);
 
                         if !has_any {
                             let message = constraint.message.as_deref().unwrap_or(
@@ -148,15 +330,11 @@ impl CommandDef {
                             ));
                         }
                     }
-                    ConstraintType::RequireAllOf => {
+                    ConstraintType::RequireAllOf | ConstraintType::RequiresIf => {
                         let missing: Vec<_> = constraint
                             .parameters
                             .iter()
-                            .filter(|param_name| {
-                                !cmd_parameters
-                                    .iter()
-                                    .any(|p| p.to_uppercase() == **param_name)
-                            })
+                            .filter(|param_name| !has_param(param_name))
                             .collect();
 
                         if !missing.is_empty() {
@@ -177,11 +355,7 @@ impl CommandDef {
                         let present_params: Vec<_> = constraint
                             .parameters
                             .iter()
-                            .filter(|param_name| {
-                                cmd_parameters
-                                    .iter()
-                                    .any(|p| p.to_uppercase() == **param_name)
-                            })
+                            .filter(|param_name| has_param(param_name))
                             .collect();
 
                         if present_params.len() > 1 {
@@ -194,6 +368,30 @@ impl CommandDef {
                             errors.push(format!("{}: {}", message, present_names.join(", ")));
                         }
                     }
+                    ConstraintType::ForbiddenIf => {
+                        let present_params: Vec<_> = constraint
+                            .parameters
+                            .iter()
+                            .filter(|param_name| has_param(param_name))
+                            .collect();
+
+                        if !present_params.is_empty() {
+                            let message = constraint
+                                .message
+                                .as_deref()
+                                .unwrap_or("Parameters are not allowed here");
+                            let present_names: Vec<&str> =
+                                present_params.iter().map(|s| s.as_str()).collect();
+                            errors.push(format!("{}: {}", message, present_names.join(", ")));
+                        }
+                    }
+                    ConstraintType::Compare => {
+                        if let Some(error) =
+                            evaluate_comparison(constraint, cmd_parameters, context)
+                        {
+                            errors.push(error);
+                        }
+                    }
                 }
             }
         }
@@ -202,6 +400,53 @@ impl CommandDef {
     }
 }
 
+/// Look up the raw value of a parameter by (case-insensitive) name
+fn parameter_value<'a>(cmd_parameters: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    cmd_parameters
+        .iter()
+        .find(|(param_name, _)| param_name.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+/// Evaluate a `compare` constraint, skipping it when either side is unknown
+fn evaluate_comparison(
+    constraint: &ParameterConstraint,
+    cmd_parameters: &[(String, String)],
+    context: &ConstraintContext,
+) -> Option<String> {
+    let operator = constraint.operator?;
+    let left_name = constraint.parameters.first()?;
+    let left: f64 = parameter_value(cmd_parameters, left_name)?.parse().ok()?;
+
+    let (right, right_desc) = match constraint.than.as_ref()? {
+        ConstraintOperand::Value(value) => (*value, value.to_string()),
+        ConstraintOperand::Parameter(name) => (
+            parameter_value(cmd_parameters, name)?.parse().ok()?,
+            name.clone(),
+        ),
+        ConstraintOperand::Setting { setting } => {
+            (*context.settings.get(setting)?, setting.clone())
+        }
+    };
+
+    if operator.apply(left, right) {
+        return None;
+    }
+
+    let message = constraint
+        .message
+        .as_deref()
+        .unwrap_or("Parameter comparison failed");
+    Some(format!(
+        "{}: expected {} {} {}, got {}",
+        message,
+        left_name,
+        operator.symbol(),
+        right_desc,
+        left
+    ))
+}
+
 impl ParameterDef {
     /// Check if parameter matches name (including aliases)
     pub fn matches_name(&self, name: &str) -> bool {
@@ -215,6 +460,26 @@ impl ParameterDef {
             .unwrap_or(false)
     }
 
+    /// Unit of the parameter's value, read from its description
+    ///
+    /// Flavor files state units in prose ("Target temperature in Celsius",
+    /// "Feed rate (mm/min)"), so this recognizes the common wordings.
+    pub fn unit(&self) -> Option<&'static str> {
+        let description = self.description.to_lowercase();
+        let units = [
+            ("celsius", "°C"),
+            ("mm/min", "mm/min"),
+            ("mm/s", "mm/s"),
+            ("milliseconds", "ms"),
+            ("seconds", "s"),
+            ("percent", "%"),
+        ];
+        units
+            .into_iter()
+            .find(|(word, _)| description.contains(word))
+            .map(|(_, unit)| unit)
+    }
+
     /// Validate parameter value
     pub fn validate(&self, value: &str) -> Result<(), String> {
         // Type validation and constraint checking
@@ -317,7 +582,10 @@ mod tests {
                 description_long: None,
                 parameters: None,
                 constraints: None,
+                deprecated: false,
+                replaced_by: None,
             }],
+            snippets: vec![],
         };
 
         let flavor = Flavor::from(file);
@@ -379,6 +647,8 @@ mod tests {
                 aliases: None,
             }]),
             constraints: None,
+            deprecated: false,
+            replaced_by: None,
         };
 
         assert!(cmd.find_parameter("X").is_some());
@@ -396,7 +666,12 @@ mod tests {
                 constraint_type: ConstraintType::RequireAnyOf,
                 parameters: vec!["X".to_string(), "Y".to_string(), "Z".to_string()],
                 message: Some("Movement requires at least one coordinate".to_string()),
+                when: None,
+                operator: None,
+                than: None,
             }]),
+            deprecated: false,
+            replaced_by: None,
         };
 
         // Test success - has X parameter
@@ -420,7 +695,12 @@ mod tests {
                 constraint_type: ConstraintType::RequireAllOf,
                 parameters: vec!["I".to_string(), "J".to_string()],
                 message: Some("Arc commands require both I and J".to_string()),
+                when: None,
+                operator: None,
+                than: None,
             }]),
+            deprecated: false,
+            replaced_by: None,
         };
 
         // Test success - has both I and J
@@ -445,7 +725,12 @@ mod tests {
                 constraint_type: ConstraintType::MutuallyExclusive,
                 parameters: vec!["ABS".to_string(), "REL".to_string()],
                 message: Some("Cannot specify both absolute and relative modes".to_string()),
+                when: None,
+                operator: None,
+                than: None,
             }]),
+            deprecated: false,
+            replaced_by: None,
         };
 
         // Test success - only one mode
@@ -470,13 +755,21 @@ mod tests {
                     constraint_type: ConstraintType::RequireAnyOf,
                     parameters: vec!["X".to_string(), "Y".to_string(), "Z".to_string()],
                     message: Some("Movement requires coordinate".to_string()),
+                    when: None,
+                    operator: None,
+                    than: None,
                 },
                 ParameterConstraint {
                     constraint_type: ConstraintType::MutuallyExclusive,
                     parameters: vec!["S".to_string(), "F".to_string()],
                     message: Some("Cannot specify both S and F".to_string()),
+                    when: None,
+                    operator: None,
+                    than: None,
                 },
             ]),
+            deprecated: false,
+            replaced_by: None,
         };
 
         // Test success - satisfies both constraints
@@ -487,4 +780,232 @@ mod tests {
         let errors = cmd.validate_constraints(&["S".to_string(), "F".to_string()]);
         assert_eq!(errors.len(), 2); // Both constraints fail
     }
+
+    #[test]
+    fn test_deprecated_command_from_toml() {
+        let toml = r#"
+            [flavor]
+            name = "test"
+
+            [[commands]]
+            name = "M999"
+            deprecated = true
+            replaced_by = "M998"
+
+            [[commands]]
+            name = "G1"
+        "#;
+
+        let flavor = Flavor::from(toml::from_str::<FlavorFile>(toml).unwrap());
+
+        let old = &flavor.commands["M999"];
+        assert!(old.deprecated);
+        assert_eq!(old.replaced_by.as_deref(), Some("M998"));
+        assert_eq!(
+            old.deprecation_message().as_deref(),
+            Some("Command 'M999' is deprecated, use 'M998' instead")
+        );
+
+        let current = &flavor.commands["G1"];
+        assert!(!current.deprecated);
+        assert!(current.deprecation_message().is_none());
+    }
+
+    fn constraint(
+        constraint_type: ConstraintType,
+        parameters: &[&str],
+        when: Option<ConstraintCondition>,
+    ) -> ParameterConstraint {
+        ParameterConstraint {
+            constraint_type,
+            parameters: parameters.iter().map(|p| p.to_string()).collect(),
+            message: None,
+            when,
+            operator: None,
+            than: None,
+        }
+    }
+
+    fn command_with(constraints: Vec<ParameterConstraint>) -> CommandDef {
+        CommandDef {
+            name: "M109".to_string(),
+            description_short: None,
+            description_long: None,
+            parameters: None,
+            constraints: Some(constraints),
+            deprecated: false,
+            replaced_by: None,
+        }
+    }
+
+    fn values(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_forbidden_if_constraint() {
+        // If R is present then S must not be
+        let cmd = command_with(vec![constraint(
+            ConstraintType::ForbiddenIf,
+            &["S"],
+            Some(ConstraintCondition {
+                parameter: Some("R".to_string()),
+                ..Default::default()
+            }),
+        )]);
+        let context = ConstraintContext::default();
+
+        let errors = cmd.validate_constraints_in_context(&values(&[("S", "200")]), &context);
+        assert!(errors.is_empty());
+
+        let errors =
+            cmd.validate_constraints_in_context(&values(&[("R", "200"), ("S", "200")]), &context);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("not allowed"));
+    }
+
+    #[test]
+    fn test_requires_if_constraint_with_value() {
+        // P is required when S is 0
+        let cmd = command_with(vec![constraint(
+            ConstraintType::RequiresIf,
+            &["P"],
+            Some(ConstraintCondition {
+                parameter: Some("S".to_string()),
+                operator: Some(ComparisonOperator::Eq),
+                value: Some(0.0),
+                modal: None,
+            }),
+        )]);
+        let context = ConstraintContext::default();
+
+        assert!(
+            cmd.validate_constraints_in_context(&values(&[("S", "1")]), &context)
+                .is_empty()
+        );
+        assert!(
+            cmd.validate_constraints_in_context(&values(&[("S", "0"), ("P", "5")]), &context)
+                .is_empty()
+        );
+
+        let errors = cmd.validate_constraints_in_context(&values(&[("S", "0")]), &context);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("missing P"));
+    }
+
+    #[test]
+    fn test_modal_condition() {
+        // K is not allowed in the XY plane
+        let cmd = command_with(vec![constraint(
+            ConstraintType::ForbiddenIf,
+            &["K"],
+            Some(ConstraintCondition {
+                modal: Some("G17".to_string()),
+                ..Default::default()
+            }),
+        )]);
+        let params = values(&[("X", "10"), ("K", "5")]);
+
+        let mut context = ConstraintContext::default();
+        assert!(
+            cmd.validate_constraints_in_context(&params, &context)
+                .is_empty(),
+            "Untracked modal state is undecidable"
+        );
+
+        context.modes.insert("G17".to_string());
+        assert_eq!(
+            cmd.validate_constraints_in_context(&params, &context).len(),
+            1
+        );
+
+        context.modes = HashSet::from(["G18".to_string()]);
+        assert!(
+            cmd.validate_constraints_in_context(&params, &context)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_compare_constraint() {
+        let mut below_max = constraint(ConstraintType::Compare, &["MINIMUM"], None);
+        below_max.operator = Some(ComparisonOperator::Le);
+        below_max.than = Some(ConstraintOperand::Parameter("MAXIMUM".to_string()));
+
+        let mut tool_index = constraint(ConstraintType::Compare, &["T"], None);
+        tool_index.operator = Some(ComparisonOperator::Lt);
+        tool_index.than = Some(ConstraintOperand::Setting {
+            setting: "extruder_count".to_string(),
+        });
+
+        let cmd = command_with(vec![below_max, tool_index]);
+        let mut context = ConstraintContext::default();
+
+        let errors = cmd.validate_constraints_in_context(
+            &values(&[("MINIMUM", "210"), ("MAXIMUM", "200"), ("T", "3")]),
+            &context,
+        );
+        assert_eq!(errors.len(), 1, "Unknown settings are skipped");
+        assert!(errors[0].contains("expected MINIMUM <= MAXIMUM, got 210"));
+
+        context.settings.insert("extruder_count".to_string(), 2.0);
+        let errors = cmd.validate_constraints_in_context(
+            &values(&[("MINIMUM", "190"), ("MAXIMUM", "200"), ("T", "3")]),
+            &context,
+        );
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("expected T < extruder_count, got 3"));
+    }
+
+    #[test]
+    fn test_conditional_constraints_from_toml() {
+        let toml = r#"
+            [flavor]
+            name = "test"
+
+            [[commands]]
+            name = "M109"
+
+            [[commands.constraints]]
+            type = "forbidden_if"
+            parameters = ["S"]
+            when = { parameter = "R" }
+
+            [[commands.constraints]]
+            type = "requires_if"
+            parameters = ["P"]
+            when = { parameter = "S", operator = "eq", value = 0 }
+
+            [[commands.constraints]]
+            type = "compare"
+            parameters = ["T"]
+            operator = "lt"
+            than = { setting = "extruder_count" }
+
+            [[commands.constraints]]
+            type = "compare"
+            parameters = ["S"]
+            operator = "ge"
+            than = 0
+        "#;
+
+        let flavor = Flavor::from(toml::from_str::<FlavorFile>(toml).unwrap());
+        let constraints = flavor.commands["M109"].constraints.as_ref().unwrap();
+
+        assert_eq!(constraints[0].constraint_type, ConstraintType::ForbiddenIf);
+        assert_eq!(
+            constraints[1].when.as_ref().unwrap().operator,
+            Some(ComparisonOperator::Eq)
+        );
+        assert_eq!(
+            constraints[2].than,
+            Some(ConstraintOperand::Setting {
+                setting: "extruder_count".to_string()
+            })
+        );
+        assert_eq!(constraints[3].than, Some(ConstraintOperand::Value(0.0)));
+    }
 }
@@ -1,13 +1,16 @@
 // New clean modules
+pub mod cli;
 pub mod config;
 pub mod core;
 pub mod flavor;
 pub mod lsp;
+pub mod machine;
 pub mod parser;
 pub mod validation;
 
 // Re-exports for clean public API
 pub use config::{Args, Config};
 pub use flavor::{Flavor, FlavorRegistry};
+pub use machine::{MachineProfile, MachineRegistry};
 pub use parser::{ParsedLine, parse_line};
 pub use validation::{Diagnostic, validate_document};
@@ -1,4 +1,5 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
 use std::sync::Arc;
 
 use tokio::sync::Mutex;
@@ -6,56 +7,308 @@ use tower_lsp::lsp_types::*;
 use tower_lsp::{Client, LanguageServer};
 
 use crate::Config;
+use crate::config::project::PROJECT_FILE_NAME;
+use crate::config::{ProjectConfig, Settings};
+use crate::core::files::{GCODE_EXTENSIONS, collect_files};
+use crate::core::thumbnails::EXPORT_COMMAND;
+use crate::core::workspace_symbols::WorkspaceIndex;
+use crate::core::{DiagnosticProvider, DiagnosticSink, DocumentManager, DocumentSnapshot};
 use crate::flavor::registry::FlavorRegistry;
-use crate::lsp::document::DocumentState;
 use crate::lsp::handlers::{
-    HandleCompletion, HandleDiagnostics, HandleDocumentSymbol, HandleHover,
+    HandleCodeAction, HandleColor, HandleCompletion, HandleConfiguration, HandleDocumentHighlight,
+    HandleDocumentSymbol, HandleDocuments, HandleHover, HandleInlayHint, HandlePullDiagnostics,
+    HandleThumbnail, HandleWorkspaceSymbol, settings_from_client,
 };
+use crate::machine::MachineRegistry;
 
 /// The main LSP backend that holds state and implements the Language Server Protocol
+///
+/// Cloning is cheap and shares all state, so background tasks can hold a copy.
+#[derive(Clone)]
 pub struct Backend {
     pub client: Client,
     pub flavor_registry: Arc<Mutex<FlavorRegistry>>,
-    pub documents: Arc<Mutex<HashMap<Url, DocumentState>>>,
+    pub machine_registry: Arc<Mutex<MachineRegistry>>,
+    pub documents: Arc<Mutex<DocumentManager>>,
+    pub diagnostics: DiagnosticProvider<Client>,
     pub config: Config,
+    /// Settings sent by the client
+    pub client_settings: Arc<Mutex<Settings>>,
+    /// Settings pulled from the client for each open document
+    pub scoped_settings: Arc<Mutex<HashMap<Url, Settings>>>,
+    /// Capabilities announced by the client in `initialize`
+    pub client_capabilities: Arc<Mutex<ClientCapabilities>>,
+    /// Flavor, snippet and machine directories already loaded into the registries
+    pub loaded_dirs: Arc<Mutex<HashSet<PathBuf>>>,
+    /// Workspace folders sent in `initialize`, scanned for workspace diagnostics
+    pub workspace_roots: Arc<Mutex<Vec<PathBuf>>>,
+    /// Landmarks of the workspace's G-code files, for workspace symbols
+    pub workspace_index: Arc<Mutex<WorkspaceIndex>>,
+}
+
+impl DiagnosticSink for Client {
+    async fn publish(&self, uri: Url, diagnostics: Vec<Diagnostic>, version: Option<i32>) {
+        self.publish_diagnostics(uri, diagnostics, version).await;
+    }
 }
 
 impl Backend {
-    pub fn new(client: Client, config: Config, flavor_registry: FlavorRegistry) -> Self {
+    pub fn new(
+        client: Client,
+        config: Config,
+        flavor_registry: FlavorRegistry,
+        machine_registry: MachineRegistry,
+    ) -> Self {
         let flavor_registry = Arc::new(Mutex::new(flavor_registry));
+        let machine_registry = Arc::new(Mutex::new(machine_registry));
+        let documents = Arc::new(Mutex::new(DocumentManager::new()));
+        let diagnostics = DiagnosticProvider::new(
+            client.clone(),
+            Arc::clone(&documents),
+            Arc::clone(&flavor_registry),
+            Arc::clone(&machine_registry),
+        );
 
         Self {
             client,
             flavor_registry,
-            documents: Arc::new(Mutex::new(HashMap::new())),
+            machine_registry,
+            documents,
+            diagnostics,
             config,
+            client_settings: Arc::new(Mutex::new(Settings::default())),
+            scoped_settings: Arc::new(Mutex::new(HashMap::new())),
+            client_capabilities: Arc::new(Mutex::new(ClientCapabilities::default())),
+            loaded_dirs: Arc::new(Mutex::new(HashSet::new())),
+            workspace_roots: Arc::new(Mutex::new(Vec::new())),
+            workspace_index: Arc::new(Mutex::new(WorkspaceIndex::new())),
+        }
+    }
+
+    /// Resolve and store the configuration of an open document
+    pub async fn configure_document(&self, uri: &Url) {
+        let Some(content) = self
+            .documents
+            .lock()
+            .await
+            .get(uri)
+            .map(|document| Arc::clone(&document.content))
+        else {
+            return;
+        };
+
+        let config = self.resolve_document_config(uri, &content).await;
+        self.documents.lock().await.configure(uri, config);
+    }
+
+    /// Load the flavor, snippet and machine directories named in a settings layer
+    ///
+    /// Each directory is only read the first time it is seen.
+    pub async fn load_settings_dirs(&self, settings: &Settings) {
+        let mut loaded_dirs = self.loaded_dirs.lock().await;
+
+        let flavor_dirs: Vec<&PathBuf> = settings
+            .flavor_dirs
+            .iter()
+            .filter(|dir| loaded_dirs.insert((*dir).clone()))
+            .collect();
+        if !flavor_dirs.is_empty() {
+            let mut flavor_registry = self.flavor_registry.lock().await;
+            for dir in flavor_dirs {
+                let count = flavor_registry.load_directory(dir);
+                log::info!("Loaded {} flavor(s) from {:?}", count, dir);
+            }
+        }
+
+        let snippet_dirs: Vec<&PathBuf> = settings
+            .snippet_dirs
+            .iter()
+            .filter(|dir| loaded_dirs.insert((*dir).clone()))
+            .collect();
+        if !snippet_dirs.is_empty() {
+            let mut flavor_registry = self.flavor_registry.lock().await;
+            for dir in snippet_dirs {
+                let count = flavor_registry.load_snippet_directory(dir);
+                log::info!("Loaded {} snippet file(s) from {:?}", count, dir);
+            }
+        }
+
+        let machine_dirs: Vec<&PathBuf> = settings
+            .machine_dirs
+            .iter()
+            .filter(|dir| loaded_dirs.insert((*dir).clone()))
+            .collect();
+        if !machine_dirs.is_empty() {
+            let mut machine_registry = self.machine_registry.lock().await;
+            for dir in machine_dirs {
+                let count = machine_registry.load_directory(dir);
+                log::info!("Loaded {} machine profile(s) from {:?}", count, dir);
+            }
+        }
+    }
+}
+
+impl Backend {
+    /// An open document, copied out so the documents lock is released
+    pub async fn document_snapshot(&self, uri: &Url) -> Option<DocumentSnapshot> {
+        self.documents
+            .lock()
+            .await
+            .get(uri)
+            .map(|doc| doc.snapshot())
+    }
+
+    /// G-code files in the workspace folders, open or not
+    pub async fn workspace_files(&self) -> Vec<PathBuf> {
+        let roots: Vec<String> = self
+            .workspace_roots
+            .lock()
+            .await
+            .iter()
+            .map(|root| root.to_string_lossy().into_owned())
+            .collect();
+        tokio::task::spawn_blocking(move || {
+            roots
+                .iter()
+                .filter_map(|root| match collect_files(std::slice::from_ref(root)) {
+                    Ok(files) => Some(files),
+                    Err(e) => {
+                        log::warn!("Failed to scan workspace folder {}: {}", root, e);
+                        None
+                    }
+                })
+                .flatten()
+                .collect::<Vec<_>>()
+        })
+        .await
+        .unwrap_or_default()
+    }
+}
+
+/// Characters that open completion
+///
+/// Command letters, plus `_` inside Klipper words such as `SET_FAN_SPEED`
+/// and `=` before their values. Clients complete words as they are typed,
+/// so parameter letters need no trigger.
+fn trigger_characters() -> Vec<String> {
+    ['G', 'M', 'T', '_', '='].map(|c| c.to_string()).to_vec()
+}
+
+/// Directories of the workspace folders sent in `initialize`
+///
+/// Clients may send the deprecated `rootUri` along with the folders, so
+/// roots equal to or inside another root are dropped; otherwise their files
+/// would be scanned twice.
+fn workspace_roots(params: &InitializeParams) -> Vec<PathBuf> {
+    #[allow(deprecated)]
+    let root_uri = params.root_uri.iter();
+
+    let mut candidates: Vec<PathBuf> = params
+        .workspace_folders
+        .iter()
+        .flatten()
+        .map(|folder| &folder.uri)
+        .chain(root_uri)
+        .filter_map(|uri| uri.to_file_path().ok())
+        .collect();
+    // Parents sort before the directories inside them
+    candidates.sort();
+
+    let mut roots: Vec<PathBuf> = Vec::new();
+    for root in candidates {
+        if !roots.iter().any(|kept| root.starts_with(kept)) {
+            roots.push(root);
         }
     }
+    roots
 }
 
 #[tower_lsp::async_trait]
 impl LanguageServer for Backend {
     async fn initialize(
         &self,
-        _: InitializeParams,
+        params: InitializeParams,
     ) -> tower_lsp::jsonrpc::Result<InitializeResult> {
+        *self.client_capabilities.lock().await = params.capabilities.clone();
+
+        if let Some(settings) = params
+            .initialization_options
+            .clone()
+            .and_then(settings_from_client)
+        {
+            self.load_settings_dirs(&settings).await;
+            *self.client_settings.lock().await = settings;
+        }
+
+        let roots = workspace_roots(&params);
+        let search = roots.clone();
+        let projects = tokio::task::spawn_blocking(move || {
+            search
+                .iter()
+                .filter_map(|root| ProjectConfig::discover(root))
+                .collect::<Vec<_>>()
+        })
+        .await
+        .unwrap_or_default();
+        for project in &projects {
+            self.load_settings_dirs(&project.settings).await;
+        }
+        *self.workspace_roots.lock().await = roots;
+
+        // Clients that pull diagnostics would otherwise get them twice
+        let pulls_diagnostics = params
+            .capabilities
+            .text_document
+            .as_ref()
+            .is_some_and(|text_document| text_document.diagnostic.is_some());
+        self.diagnostics.set_push_enabled(!pulls_diagnostics);
+
         Ok(InitializeResult {
             capabilities: ServerCapabilities {
                 hover_provider: Some(HoverProviderCapability::Simple(true)),
                 completion_provider: Some(CompletionOptions {
-                    resolve_provider: Some(false),
-                    trigger_characters: Some(vec![
-                        "G".to_string(),
-                        "M".to_string(),
-                        "T".to_string(),
-                    ]),
+                    resolve_provider: Some(true),
+                    trigger_characters: Some(trigger_characters()),
                     work_done_progress_options: Default::default(),
                     all_commit_characters: None,
                     completion_item: None,
                 }),
                 document_symbol_provider: Some(OneOf::Left(true)),
-                text_document_sync: Some(TextDocumentSyncCapability::Kind(
-                    TextDocumentSyncKind::FULL,
+                document_highlight_provider: Some(OneOf::Left(true)),
+                color_provider: Some(ColorProviderCapability::Simple(true)),
+                folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+                code_lens_provider: Some(CodeLensOptions {
+                    resolve_provider: Some(false),
+                }),
+                execute_command_provider: Some(ExecuteCommandOptions {
+                    commands: vec![EXPORT_COMMAND.to_string()],
+                    work_done_progress_options: Default::default(),
+                }),
+                workspace_symbol_provider: Some(OneOf::Left(true)),
+                inlay_hint_provider: Some(OneOf::Left(true)),
+                diagnostic_provider: Some(DiagnosticServerCapabilities::Options(
+                    DiagnosticOptions {
+                        identifier: Some("gcode-ls".to_string()),
+                        inter_file_dependencies: false,
+                        workspace_diagnostics: true,
+                        work_done_progress_options: Default::default(),
+                    },
+                )),
+                code_action_provider: Some(CodeActionProviderCapability::Options(
+                    CodeActionOptions {
+                        code_action_kinds: Some(vec![CodeActionKind::QUICKFIX]),
+                        ..Default::default()
+                    },
+                )),
+                text_document_sync: Some(TextDocumentSyncCapability::Options(
+                    TextDocumentSyncOptions {
+                        open_close: Some(true),
+                        change: Some(TextDocumentSyncKind::INCREMENTAL),
+                        save: Some(TextDocumentSyncSaveOptions::SaveOptions(SaveOptions {
+                            include_text: Some(false),
+                        })),
+                        ..Default::default()
+                    },
                 )),
                 ..Default::default()
             },
@@ -64,6 +317,61 @@ impl LanguageServer for Backend {
     }
 
     async fn initialized(&self, _: InitializedParams) {
+        let workspace = self.client_capabilities.lock().await.workspace.clone();
+
+        // Clients using the pull model only notify about changes we register for
+        let dynamic_registration = workspace
+            .as_ref()
+            .and_then(|workspace| workspace.did_change_configuration)
+            .and_then(|capability| capability.dynamic_registration)
+            .unwrap_or(false);
+        if dynamic_registration {
+            let registration = Registration {
+                id: "gcode-ls-configuration".to_string(),
+                method: "workspace/didChangeConfiguration".to_string(),
+                register_options: None,
+            };
+            if let Err(e) = self.client.register_capability(vec![registration]).await {
+                log::warn!("Failed to register for configuration changes: {}", e);
+            }
+        }
+
+        // Keep the symbol index in step with G-code files changed on disk, and
+        // the configuration with project files
+        let watches_files = workspace
+            .as_ref()
+            .and_then(|workspace| workspace.did_change_watched_files)
+            .and_then(|capability| capability.dynamic_registration)
+            .unwrap_or(false);
+        if watches_files {
+            let options = DidChangeWatchedFilesRegistrationOptions {
+                watchers: vec![
+                    FileSystemWatcher {
+                        glob_pattern: GlobPattern::String(format!(
+                            "**/*.{{{}}}",
+                            GCODE_EXTENSIONS.join(",")
+                        )),
+                        kind: None,
+                    },
+                    FileSystemWatcher {
+                        glob_pattern: GlobPattern::String(format!("**/{}", PROJECT_FILE_NAME)),
+                        kind: None,
+                    },
+                ],
+            };
+            let registration = Registration {
+                id: "gcode-ls-watched-files".to_string(),
+                method: "workspace/didChangeWatchedFiles".to_string(),
+                register_options: serde_json::to_value(options).ok(),
+            };
+            if let Err(e) = self.client.register_capability(vec![registration]).await {
+                log::warn!("Failed to register for file changes: {}", e);
+            }
+        }
+
+        let backend = self.clone();
+        tokio::spawn(async move { backend.index_workspace().await });
+
         self.client
             .log_message(MessageType::INFO, "gcode-language-server initialized")
             .await;
@@ -84,6 +392,66 @@ impl LanguageServer for Backend {
         self.handle_completion(params).await
     }
 
+    async fn completion_resolve(
+        &self,
+        item: CompletionItem,
+    ) -> tower_lsp::jsonrpc::Result<CompletionItem> {
+        self.handle_completion_resolve(item).await
+    }
+
+    async fn document_highlight(
+        &self,
+        params: DocumentHighlightParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<DocumentHighlight>>> {
+        self.handle_document_highlight(params).await
+    }
+
+    async fn document_color(
+        &self,
+        params: DocumentColorParams,
+    ) -> tower_lsp::jsonrpc::Result<Vec<ColorInformation>> {
+        self.handle_document_color(params).await
+    }
+
+    async fn color_presentation(
+        &self,
+        params: ColorPresentationParams,
+    ) -> tower_lsp::jsonrpc::Result<Vec<ColorPresentation>> {
+        self.handle_color_presentation(params).await
+    }
+
+    async fn folding_range(
+        &self,
+        params: FoldingRangeParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<FoldingRange>>> {
+        self.handle_folding_range(params).await
+    }
+
+    async fn code_lens(
+        &self,
+        params: CodeLensParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<CodeLens>>> {
+        self.handle_code_lens(params).await
+    }
+
+    async fn execute_command(
+        &self,
+        params: ExecuteCommandParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<serde_json::Value>> {
+        self.handle_execute_command(params).await
+    }
+
+    async fn symbol(
+        &self,
+        params: WorkspaceSymbolParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<SymbolInformation>>> {
+        self.handle_workspace_symbol(params).await
+    }
+
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        self.handle_did_change_watched_files(params).await
+    }
+
     async fn document_symbol(
         &self,
         params: DocumentSymbolParams,
@@ -91,34 +459,51 @@ impl LanguageServer for Backend {
         self.handle_document_symbol(params).await
     }
 
-    // Store opened documents for hover/diagnostics
-    async fn did_open(&self, params: DidOpenTextDocumentParams) {
-        let uri = params.text_document.uri.clone();
-        let content = params.text_document.text;
+    async fn code_action(
+        &self,
+        params: CodeActionParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<CodeActionResponse>> {
+        self.handle_code_action(params).await
+    }
+
+    async fn inlay_hint(
+        &self,
+        params: InlayHintParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<InlayHint>>> {
+        self.handle_inlay_hint(params).await
+    }
 
-        // Create document state with flavor detection
-        let doc_state = self.create_document_state(content).await;
+    async fn diagnostic(
+        &self,
+        params: DocumentDiagnosticParams,
+    ) -> tower_lsp::jsonrpc::Result<DocumentDiagnosticReportResult> {
+        self.handle_diagnostic(params).await
+    }
 
-        let mut docs = self.documents.lock().await;
-        docs.insert(uri.clone(), doc_state);
-        drop(docs); // Release the lock before calling publish_diagnostics
+    async fn workspace_diagnostic(
+        &self,
+        params: WorkspaceDiagnosticParams,
+    ) -> tower_lsp::jsonrpc::Result<WorkspaceDiagnosticReportResult> {
+        self.handle_workspace_diagnostic(params).await
+    }
 
-        // Publish diagnostics for the opened document
-        self.publish_diagnostics(uri).await;
+    async fn did_open(&self, params: DidOpenTextDocumentParams) {
+        self.handle_did_open(params).await
     }
 
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
-        let uri = params.text_document.uri.clone();
-        if let Some(change) = params.content_changes.into_iter().last() {
-            // Create new document state with updated content
-            let doc_state = self.create_document_state(change.text).await;
+        self.handle_did_change(params).await
+    }
 
-            let mut docs = self.documents.lock().await;
-            docs.insert(uri.clone(), doc_state);
-            drop(docs); // Release the lock before calling publish_diagnostics
+    async fn did_save(&self, params: DidSaveTextDocumentParams) {
+        self.handle_did_save(params).await
+    }
 
-            // Publish updated diagnostics
-            self.publish_diagnostics(uri).await;
-        }
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        self.handle_did_close(params).await
+    }
+
+    async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
+        self.handle_did_change_configuration(params).await
     }
 }
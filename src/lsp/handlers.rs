@@ -1,10 +1,28 @@
 use tower_lsp::jsonrpc::Result as LspResult;
 use tower_lsp::lsp_types::*;
 
-use crate::flavor::schema::ParameterType;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::config::project::PROJECT_FILE_NAME;
+use crate::config::{ProjectConfig, Settings};
+use crate::core::colors::{color_presentations, document_colors};
+use crate::core::completion::{
+    CompletionContext, complete, detect_filament, resolve, resolve_flavor,
+};
+use crate::core::diagnostics::utf16_column;
+use crate::core::highlight::document_highlights;
+use crate::core::hover::hover;
+use crate::core::inlay_hints::inlay_hints;
+use crate::core::thumbnails::{
+    EXPORT_COMMAND, code_lenses, export_thumbnail, folding_ranges, thumbnail_hover,
+};
+use crate::core::workspace_symbols::landmarks;
+use crate::core::{DocumentConfig, DocumentSnapshot, DocumentState, FileStamp};
+use crate::flavor::registry::FlavorRegistry;
 use crate::lsp::backend::Backend;
-use crate::lsp::document::DocumentState;
-use crate::validation::engine::validate_document;
+use crate::machine::TrackedState;
+use crate::parser::{ParsedLine, extended_command_span};
 
 /// Trait for handling hover requests
 #[tower_lsp::async_trait]
@@ -19,6 +37,7 @@ pub trait HandleCompletion {
         &self,
         params: CompletionParams,
     ) -> LspResult<Option<CompletionResponse>>;
+    async fn handle_completion_resolve(&self, item: CompletionItem) -> LspResult<CompletionItem>;
 }
 
 /// Trait for handling document symbols
@@ -30,326 +49,972 @@ pub trait HandleDocumentSymbol {
     ) -> LspResult<Option<DocumentSymbolResponse>>;
 }
 
-/// Trait for handling diagnostics
+/// Trait for handling code actions
 #[tower_lsp::async_trait]
-pub trait HandleDiagnostics {
-    async fn create_document_state(&self, content: String) -> DocumentState;
-    async fn publish_diagnostics(&self, uri: Url);
-    fn create_lsp_diagnostic(
+pub trait HandleCodeAction {
+    async fn handle_code_action(
         &self,
-        validation_diagnostic: crate::validation::engine::Diagnostic,
-    ) -> tower_lsp::lsp_types::Diagnostic;
+        params: CodeActionParams,
+    ) -> LspResult<Option<CodeActionResponse>>;
+}
+
+/// Trait for handling configuration changes
+#[tower_lsp::async_trait]
+pub trait HandleConfiguration {
+    async fn handle_did_change_configuration(&self, params: DidChangeConfigurationParams);
+    async fn pull_settings(&self, uri: &Url);
+    async fn revalidate_open_documents(&self);
+}
+
+/// Trait for handling the document lifecycle
+#[tower_lsp::async_trait]
+pub trait HandleDocuments {
+    async fn handle_did_open(&self, params: DidOpenTextDocumentParams);
+    async fn handle_did_change(&self, params: DidChangeTextDocumentParams);
+    async fn handle_did_save(&self, params: DidSaveTextDocumentParams);
+    async fn handle_did_close(&self, params: DidCloseTextDocumentParams);
+    async fn resolve_document_config(&self, uri: &Url, content: &str) -> DocumentConfig;
+}
+
+/// Trait for handling inlay hint requests
+#[tower_lsp::async_trait]
+pub trait HandleInlayHint {
+    async fn handle_inlay_hint(&self, params: InlayHintParams)
+    -> LspResult<Option<Vec<InlayHint>>>;
+}
+
+/// Trait for handling document highlights
+#[tower_lsp::async_trait]
+pub trait HandleDocumentHighlight {
+    async fn handle_document_highlight(
+        &self,
+        params: DocumentHighlightParams,
+    ) -> LspResult<Option<Vec<DocumentHighlight>>>;
+}
+
+/// Trait for handling color swatches and pickers
+#[tower_lsp::async_trait]
+pub trait HandleColor {
+    async fn handle_document_color(
+        &self,
+        params: DocumentColorParams,
+    ) -> LspResult<Vec<ColorInformation>>;
+    async fn handle_color_presentation(
+        &self,
+        params: ColorPresentationParams,
+    ) -> LspResult<Vec<ColorPresentation>>;
+}
+
+/// Trait for handling embedded thumbnails: folding, export lenses and the
+/// export command
+#[tower_lsp::async_trait]
+pub trait HandleThumbnail {
+    async fn handle_folding_range(
+        &self,
+        params: FoldingRangeParams,
+    ) -> LspResult<Option<Vec<FoldingRange>>>;
+    async fn handle_code_lens(&self, params: CodeLensParams) -> LspResult<Option<Vec<CodeLens>>>;
+    async fn handle_execute_command(
+        &self,
+        params: ExecuteCommandParams,
+    ) -> LspResult<Option<serde_json::Value>>;
+}
+
+/// Trait for handling workspace symbol search and the index behind it
+#[tower_lsp::async_trait]
+pub trait HandleWorkspaceSymbol {
+    async fn handle_workspace_symbol(
+        &self,
+        params: WorkspaceSymbolParams,
+    ) -> LspResult<Option<Vec<SymbolInformation>>>;
+    async fn handle_did_change_watched_files(&self, params: DidChangeWatchedFilesParams);
+    async fn index_workspace(&self);
+    async fn index_document(&self, uri: &Url);
+    async fn index_file(&self, uri: &Url);
+}
+
+/// Trait for handling diagnostic pulls
+#[tower_lsp::async_trait]
+pub trait HandlePullDiagnostics {
+    async fn handle_diagnostic(
+        &self,
+        params: DocumentDiagnosticParams,
+    ) -> LspResult<DocumentDiagnosticReportResult>;
+    async fn handle_workspace_diagnostic(
+        &self,
+        params: WorkspaceDiagnosticParams,
+    ) -> LspResult<WorkspaceDiagnosticReportResult>;
+    async fn pull_file(
+        &self,
+        uri: &Url,
+        previous: Option<&str>,
+    ) -> Option<DocumentDiagnosticReportKind>;
+    async fn refresh_diagnostics(&self);
 }
 
 #[tower_lsp::async_trait]
 impl HandleHover for Backend {
+    /// Describe the element under the cursor and the machine state at its line
     async fn handle_hover(&self, params: HoverParams) -> LspResult<Option<Hover>> {
         let tdpp = params.text_document_position_params;
         let uri = tdpp.text_document.uri;
         let pos = tdpp.position;
 
-        let docs = self.documents.lock().await;
-        let doc_state = match docs.get(&uri) {
-            Some(state) => state,
-            None => return Ok(None),
+        let Some(doc) = self.document_snapshot(&uri).await else {
+            return Ok(None);
         };
 
         let line_idx = pos.line as usize;
-        let line = doc_state.content.lines().nth(line_idx).unwrap_or("");
-        let char_idx = pos.character as usize;
-
-        // Find token under cursor (alphanumeric)
-        let mut start = char_idx;
-        while start > 0 {
-            let c = line.chars().nth(start - 1).unwrap_or(' ');
-            if c.is_alphanumeric() {
-                start -= 1;
-            } else {
-                break;
-            }
-        }
-        let mut end = char_idx;
-        while end < line.len() {
-            let c = line.chars().nth(end).unwrap_or(' ');
-            if c.is_alphanumeric() {
-                end += 1;
-            } else {
-                break;
-            }
+        let Some(parsed) = doc.parsed.get(line_idx) else {
+            return Ok(None);
+        };
+        if matches!(parsed, ParsedLine::Comment(_))
+            && let Some(preview) = thumbnail_hover(&doc.content, pos)
+        {
+            return Ok(Some(preview));
         }
+        let line = doc.content.lines().nth(line_idx).unwrap_or("");
+
+        // Cached per document, so only the lines since a checkpoint are replayed
+        let before = state_before(&doc, line_idx).await?;
 
-        if start >= end {
+        let flavor = self
+            .flavor_registry
+            .lock()
+            .await
+            .snapshot(doc.config.flavor_name.as_deref());
+        Ok(Some(hover(line, parsed, &flavor, pos, &before)))
+    }
+}
+
+#[tower_lsp::async_trait]
+impl HandleCompletion for Backend {
+    /// Complete commands, parameters and parameter values at the cursor
+    async fn handle_completion(
+        &self,
+        params: CompletionParams,
+    ) -> LspResult<Option<CompletionResponse>> {
+        let uri = params.text_document_position.text_document.uri;
+        let pos = params.text_document_position.position;
+
+        let Some(doc) = self.document_snapshot(&uri).await else {
             return Ok(None);
+        };
+        let config = doc.config;
+        let flavor = self
+            .flavor_registry
+            .lock()
+            .await
+            .snapshot(config.flavor_name.as_deref());
+        let machine = match config.machine_name.as_deref() {
+            Some(name) => self
+                .machine_registry
+                .lock()
+                .await
+                .get_profile(name)
+                .cloned(),
+            None => None,
+        };
+
+        // The slicer's record of the filament wins over the project default
+        let filament = detect_filament(&doc.content).or(config.settings.filament);
+        let context = CompletionContext {
+            flavor: &flavor,
+            machine: machine.as_ref(),
+            filament: filament.as_deref(),
+        };
+
+        let completions = complete(&doc.content, &doc.parsed, pos, &context);
+        if completions.items.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(CompletionResponse::List(completions)))
         }
+    }
 
-        let token: String = line.chars().skip(start).take(end - start).collect();
-        let token_up = token.to_uppercase();
+    /// Add the documentation left out of command items
+    async fn handle_completion_resolve(&self, item: CompletionItem) -> LspResult<CompletionItem> {
+        // The flavor the item came from, which may not be the active one
+        let flavor = self
+            .flavor_registry
+            .lock()
+            .await
+            .snapshot(resolve_flavor(&item).as_deref());
+        Ok(resolve(item, &flavor))
+    }
+}
 
-        // Use the new flavor registry instead of legacy cached commands
-        let registry = self.flavor_registry.lock().await;
-        if let Some(cmd) = registry.get_command(&token_up) {
-            // Always show rich information: prefer long description, fallback to short
-            let desc = cmd
-                .description_long
-                .clone()
-                .or_else(|| cmd.description_short.clone())
-                .unwrap_or_else(|| "No description".to_string());
+#[tower_lsp::async_trait]
+impl HandleDocuments for Backend {
+    /// Track an opened G-code document and publish its diagnostics
+    async fn handle_did_open(&self, params: DidOpenTextDocumentParams) {
+        let document = params.text_document;
+        let uri = document.uri;
+
+        let opened = self.documents.lock().await.open(
+            uri.clone(),
+            &document.language_id,
+            document.version,
+            document.text,
+        );
+        if !opened {
+            return;
+        }
 
-            // Enhance hover with parameter information
-            let mut hover_text = format!("**{}**\n\n{}", token_up, desc);
+        // Settings for the document's scope, if the client supports pulling them
+        self.pull_settings(&uri).await;
+        self.configure_document(&uri).await;
+        self.index_document(&uri).await;
 
-            if let Some(parameters) = &cmd.parameters
-                && !parameters.is_empty()
-            {
-                hover_text.push_str("\n\n**Parameters:**");
-                for param in parameters {
-                    hover_text.push_str(&format!(
-                        "\n- `{}`: {} ({:?}{})",
-                        param.name,
-                        param.description,
-                        param.param_type,
-                        if param.required {
-                            ", required"
-                        } else {
-                            ", optional"
-                        }
-                    ));
-                }
-            }
+        self.diagnostics.publish(uri).await;
+    }
+
+    /// Apply edits, re-parsing only the lines they touch
+    async fn handle_did_change(&self, params: DidChangeTextDocumentParams) {
+        let uri = params.text_document.uri;
 
-            let m = MarkupContent {
-                kind: MarkupKind::Markdown,
-                value: hover_text,
+        let modelines_changed = {
+            let mut docs = self.documents.lock().await;
+            let Some(before) = docs.get(&uri).map(DocumentState::modelines) else {
+                return;
             };
-            return Ok(Some(Hover {
-                contents: HoverContents::Markup(m),
-                range: None,
-            }));
+            match docs.change(&uri, params.text_document.version, params.content_changes) {
+                Some(document) => document.modelines() != before,
+                None => return,
+            }
+        };
+
+        // Only a modeline can select another flavor or machine while typing
+        if modelines_changed {
+            self.configure_document(&uri).await;
+        }
+
+        // Publish updated diagnostics once typing pauses
+        self.diagnostics.schedule(uri).await;
+    }
+
+    /// Re-read the configuration of a saved document and validate it now
+    ///
+    /// Saving is a natural point for project files to have changed, and for
+    /// diagnostics not to wait for the debounce period.
+    async fn handle_did_save(&self, params: DidSaveTextDocumentParams) {
+        let uri = params.text_document.uri;
+
+        let saved = self
+            .documents
+            .lock()
+            .await
+            .save(&uri, params.text)
+            .is_some();
+        if !saved {
+            return;
+        }
+
+        self.configure_document(&uri).await;
+        self.index_document(&uri).await;
+        self.diagnostics.publish(uri).await;
+    }
+
+    /// Forget a closed document and clear its diagnostics
+    async fn handle_did_close(&self, params: DidCloseTextDocumentParams) {
+        let uri = params.text_document.uri;
+
+        if self.documents.lock().await.close(&uri).is_none() {
+            return;
+        }
+        self.scoped_settings.lock().await.remove(&uri);
+        // Unsaved landmarks go; the file on disk, if any, is indexed again
+        self.index_file(&uri).await;
+
+        self.diagnostics.clear(uri).await;
+    }
+
+    /// Resolve the settings, flavor and machine profile of a document
+    async fn resolve_document_config(&self, uri: &Url, content: &str) -> DocumentConfig {
+        // Settings from the client, the nearest project file and the CLI
+        let path = uri.to_file_path().ok();
+        let project = match path.clone() {
+            Some(path) => tokio::task::spawn_blocking(move || ProjectConfig::discover(&path))
+                .await
+                .ok()
+                .flatten(),
+            None => None,
+        };
+        if let Some(project) = &project {
+            self.load_settings_dirs(&project.settings).await;
         }
+        let client_settings = match self.scoped_settings.lock().await.get(uri) {
+            Some(scoped) => scoped.clone(),
+            None => self.client_settings.lock().await.clone(),
+        };
+        let settings = self.config.resolve(&client_settings, project.as_ref());
+        let included = match (&project, &path) {
+            (Some(project), Some(path)) => project.includes(path),
+            _ => true,
+        };
 
-        Ok(None)
+        // Modeline first, then the configured flavor or profile, then the default
+        let flavor_name = self
+            .flavor_registry
+            .lock()
+            .await
+            .select_flavor(content, settings.flavor.as_deref());
+        let machine_name = self
+            .machine_registry
+            .lock()
+            .await
+            .select_profile(content, settings.machine.as_deref());
+
+        DocumentConfig {
+            flavor_name,
+            machine_name,
+            settings,
+            included,
+        }
     }
 }
 
 #[tower_lsp::async_trait]
-impl HandleCompletion for Backend {
-    async fn handle_completion(
+impl HandleInlayHint for Backend {
+    /// Show resolved positions, feedrates and parameter units in a range
+    async fn handle_inlay_hint(
         &self,
-        params: CompletionParams,
-    ) -> LspResult<Option<CompletionResponse>> {
-        let uri = params.text_document_position.text_document.uri;
-        let pos = params.text_document_position.position;
+        params: InlayHintParams,
+    ) -> LspResult<Option<Vec<InlayHint>>> {
+        let range = params.range;
+        let lines = range.start.line as usize..range.end.line as usize + 1;
 
-        let docs = self.documents.lock().await;
-        let doc_state = match docs.get(&uri) {
-            Some(state) => state,
-            None => return Ok(None),
+        let Some(doc) = self.document_snapshot(&params.text_document.uri).await else {
+            return Ok(None);
         };
+        let before = state_before(&doc, lines.start).await?;
+        let flavor = self
+            .flavor_registry
+            .lock()
+            .await
+            .snapshot(doc.config.flavor_name.as_deref());
+
+        Ok(Some(inlay_hints(
+            &doc.content,
+            &doc.parsed,
+            &flavor,
+            lines,
+            before.state,
+        )))
+    }
+}
 
-        let line_idx = pos.line as usize;
-        let line = doc_state.content.lines().nth(line_idx).unwrap_or("");
-        let char_idx = pos.character as usize;
-
-        // Parse the line to understand context
-        let words: Vec<&str> = line.split_whitespace().collect();
-        let mut completions = Vec::new();
-
-        // Get all commands from the flavor registry
-        let registry = self.flavor_registry.lock().await;
-        let active_flavor = match registry.get_active_flavor() {
-            Some(flavor) => flavor,
-            None => return Ok(None),
+#[tower_lsp::async_trait]
+impl HandleDocumentHighlight for Backend {
+    /// Light up the uses of a tool or command and the lines setting its modes
+    async fn handle_document_highlight(
+        &self,
+        params: DocumentHighlightParams,
+    ) -> LspResult<Option<Vec<DocumentHighlight>>> {
+        let tdpp = params.text_document_position_params;
+
+        let Some(doc) = self.document_snapshot(&tdpp.text_document.uri).await else {
+            return Ok(None);
         };
+        let before = state_before(&doc, tdpp.position.line as usize).await?;
 
-        // Determine if we're completing a command or parameters
-        let line_up_to_cursor = &line[..char_idx.min(line.len())];
-        let is_after_space = line_up_to_cursor.ends_with(' ');
+        let highlights = document_highlights(&doc.content, &doc.parsed, tdpp.position, &before);
+        Ok((!highlights.is_empty()).then_some(highlights))
+    }
+}
 
-        if words.is_empty() || (words.len() == 1 && !is_after_space) {
-            // Completing a command
-            let current_word = if words.is_empty() { "" } else { words[0] }.to_uppercase();
+#[tower_lsp::async_trait]
+impl HandleColor for Backend {
+    /// Colors set by `M150` and `SET_LED`
+    async fn handle_document_color(
+        &self,
+        params: DocumentColorParams,
+    ) -> LspResult<Vec<ColorInformation>> {
+        let Some(doc) = self.document_snapshot(&params.text_document.uri).await else {
+            return Ok(Vec::new());
+        };
+        Ok(document_colors(&doc.content))
+    }
 
-            for (command_name, command_def) in &active_flavor.commands {
-                if command_name.starts_with(&current_word) {
-                    // Use short description for completion detail (concise summary)
-                    let detail = command_def
-                        .description_short
-                        .clone()
-                        .unwrap_or_else(|| "G-code command".to_string());
+    /// Write a picked color back in the scale of the document's flavor
+    async fn handle_color_presentation(
+        &self,
+        params: ColorPresentationParams,
+    ) -> LspResult<Vec<ColorPresentation>> {
+        let Some(doc) = self.document_snapshot(&params.text_document.uri).await else {
+            return Ok(Vec::new());
+        };
+        let flavor = self
+            .flavor_registry
+            .lock()
+            .await
+            .snapshot(doc.config.flavor_name.as_deref());
+        Ok(color_presentations(
+            &doc.content,
+            params.range,
+            params.color,
+            &flavor,
+        ))
+    }
+}
 
-                    // Use long description for documentation (comprehensive info)
-                    let mut documentation = command_def
-                        .description_long
-                        .clone()
-                        .or_else(|| command_def.description_short.clone())
-                        .unwrap_or_else(|| "G-code command".to_string());
+#[tower_lsp::async_trait]
+impl HandleThumbnail for Backend {
+    /// Fold thumbnail blocks into their `begin` line
+    async fn handle_folding_range(
+        &self,
+        params: FoldingRangeParams,
+    ) -> LspResult<Option<Vec<FoldingRange>>> {
+        let doc = self.document_snapshot(&params.text_document.uri).await;
+        Ok(doc.map(|doc| folding_ranges(&doc.content)))
+    }
 
-                    // Add parameter information to documentation
-                    if let Some(parameters) = &command_def.parameters
-                        && !parameters.is_empty()
-                    {
-                        documentation.push_str("\n\n**Parameters:**");
-                        for param in parameters {
-                            documentation.push_str(&format!(
-                                "\n- `{}`: {} ({:?}{})",
-                                param.name,
-                                param.description,
-                                param.param_type,
-                                if param.required {
-                                    ", required"
-                                } else {
-                                    ", optional"
-                                }
-                            ));
-                        }
-                    }
+    /// Offer to export each thumbnail
+    async fn handle_code_lens(&self, params: CodeLensParams) -> LspResult<Option<Vec<CodeLens>>> {
+        let uri = params.text_document.uri;
+        let doc = self.document_snapshot(&uri).await;
+        Ok(doc.map(|doc| code_lenses(&doc.content, &uri)))
+    }
 
-                    completions.push(CompletionItem {
-                        label: command_name.clone(),
-                        kind: Some(CompletionItemKind::KEYWORD),
-                        detail: Some(detail),
-                        documentation: Some(Documentation::MarkupContent(MarkupContent {
-                            kind: MarkupKind::Markdown,
-                            value: documentation,
-                        })),
-                        ..Default::default()
-                    });
-                }
+    /// Export a thumbnail next to its document, as asked by a code lens
+    async fn handle_execute_command(
+        &self,
+        params: ExecuteCommandParams,
+    ) -> LspResult<Option<serde_json::Value>> {
+        if params.command != EXPORT_COMMAND {
+            return Err(tower_lsp::jsonrpc::Error::method_not_found());
+        }
+        let (uri, begin) = match params.arguments.as_slice() {
+            [uri, begin] => (
+                uri.as_str().and_then(|uri| Url::parse(uri).ok()),
+                begin.as_u64(),
+            ),
+            _ => (None, None),
+        };
+        let (Some(uri), Some(begin)) = (uri, begin) else {
+            return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                "{} expects a document URI and a line",
+                EXPORT_COMMAND
+            )));
+        };
+        let Ok(path) = uri.to_file_path() else {
+            return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                "Cannot export thumbnails of {}",
+                uri
+            )));
+        };
+
+        let Some(content) = self
+            .documents
+            .lock()
+            .await
+            .get(&uri)
+            .map(|doc_state| Arc::clone(&doc_state.content))
+        else {
+            return Ok(None);
+        };
+        let exported =
+            tokio::task::spawn_blocking(move || export_thumbnail(&content, begin as usize, &path))
+                .await
+                .unwrap_or_else(|e| Err(e.into()));
+        match exported {
+            Ok(file) => {
+                self.client
+                    .show_message(
+                        MessageType::INFO,
+                        format!("Thumbnail exported to {}", file.display()),
+                    )
+                    .await;
+                Ok(Some(serde_json::Value::String(file.display().to_string())))
+            }
+            Err(e) => {
+                self.client
+                    .show_message(MessageType::ERROR, format!("{:#}", e))
+                    .await;
+                Ok(None)
             }
-        } else if !words.is_empty() && is_after_space {
-            // Completing parameters for a command (cursor is after a space following the command)
-            let command_name = words[0].to_uppercase();
-            if let Some(command_def) = active_flavor.commands.get(&command_name)
-                && let Some(parameters) = &command_def.parameters
+        }
+    }
+}
+
+#[tower_lsp::async_trait]
+impl HandleWorkspaceSymbol for Backend {
+    /// Search the landmarks of every G-code file in the workspace
+    async fn handle_workspace_symbol(
+        &self,
+        params: WorkspaceSymbolParams,
+    ) -> LspResult<Option<Vec<SymbolInformation>>> {
+        Ok(Some(
+            self.workspace_index.lock().await.search(&params.query),
+        ))
+    }
+
+    /// Re-index files changed on disk and forget deleted ones
+    ///
+    /// Open documents are indexed from their text in the editor instead,
+    /// until they are closed. A changed project file reconfigures every
+    /// document.
+    async fn handle_did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        let mut project_changed = false;
+        for change in params.changes {
+            if change
+                .uri
+                .path()
+                .ends_with(&format!("/{}", PROJECT_FILE_NAME))
             {
-                // Parse existing parameters to avoid duplicates
-                let mut existing_params = std::collections::HashSet::new();
-                for word in &words[1..] {
-                    if let Some(param_name) = word.split(&['=', ':']).next() {
-                        existing_params.insert(param_name.to_uppercase());
-                    }
-                }
+                project_changed = true;
+                continue;
+            }
+            if self.documents.lock().await.get(&change.uri).is_some() {
+                continue;
+            }
+            self.index_file(&change.uri).await;
+        }
 
-                // Add completions for parameters not yet used
-                for param in parameters {
-                    let param_upper = param.name.to_uppercase();
-                    if !existing_params.contains(&param_upper) {
-                        completions.push(CompletionItem {
-                            label: param.name.clone(),
-                            kind: Some(CompletionItemKind::PROPERTY),
-                            detail: Some(format!("{:?}", param.param_type)),
-                            documentation: Some(Documentation::String(param.description.clone())),
-                            sort_text: Some(format!(
-                                "{}{}",
-                                if param.required { "0" } else { "1" },
-                                param.name
-                            )),
-                            insert_text: Some(match param.param_type {
-                                ParameterType::Float => format!("{}0.0", param.name),
-                                ParameterType::Int => format!("{}0", param.name),
-                                ParameterType::Bool => param.name.clone(),
-                                ParameterType::String => format!("{}\"\"", param.name),
-                            }),
-                            insert_text_format: Some(InsertTextFormat::SNIPPET),
-                            preselect: Some(param.required),
-                            filter_text: Some(param.name.clone()),
-                            ..Default::default()
-                        });
-                    }
-                }
+        if project_changed {
+            self.diagnostics.invalidate();
+            self.revalidate_open_documents().await;
+        }
+    }
+
+    /// Index every G-code file in the workspace folders
+    ///
+    /// Runs in the background after `initialized`; files are read and
+    /// scanned without holding the index lock.
+    async fn index_workspace(&self) {
+        let files = self.workspace_files().await;
+        let count = files.len();
+
+        for path in files {
+            let Ok(uri) = Url::from_file_path(&path) else {
+                continue;
+            };
+            let Ok(content) = tokio::fs::read_to_string(&path).await else {
+                continue;
+            };
+            let found = tokio::task::spawn_blocking(move || landmarks(&content))
+                .await
+                .unwrap_or_default();
+
+            // Open documents were indexed from their newer text
+            let mut index = self.workspace_index.lock().await;
+            if !index.contains(&uri) {
+                index.insert(uri, found);
             }
         }
+        log::info!("Indexed {} workspace file(s) for symbols", count);
+    }
 
-        if completions.is_empty() {
-            Ok(None)
-        } else {
-            Ok(Some(CompletionResponse::Array(completions)))
+    /// Index the text of an open document
+    async fn index_document(&self, uri: &Url) {
+        let Some(content) = self
+            .documents
+            .lock()
+            .await
+            .get(uri)
+            .map(|document| Arc::clone(&document.content))
+        else {
+            return;
+        };
+        let found = landmarks(&content);
+        self.workspace_index.lock().await.insert(uri.clone(), found);
+    }
+
+    /// Index a file from disk, or forget it if it cannot be read
+    async fn index_file(&self, uri: &Url) {
+        let content = match uri.to_file_path() {
+            Ok(path) => tokio::fs::read_to_string(path).await.ok(),
+            Err(()) => None,
+        };
+        match content {
+            Some(content) => {
+                let found = tokio::task::spawn_blocking(move || landmarks(&content))
+                    .await
+                    .unwrap_or_default();
+                self.workspace_index.lock().await.insert(uri.clone(), found);
+            }
+            None => self.workspace_index.lock().await.remove(uri),
         }
     }
 }
 
 #[tower_lsp::async_trait]
-impl HandleDiagnostics for Backend {
-    /// Create a new document state, detecting flavor and caching commands
-    async fn create_document_state(&self, content: String) -> DocumentState {
-        let mut flavor_registry = self.flavor_registry.lock().await;
-
-        // Try to detect flavor from modeline (highest priority)
-        let modeline_flavor = flavor_registry.detect_modeline_flavor(&content);
-
-        // Set the appropriate flavor
-        let flavor_name = if let Some(ref name) = modeline_flavor {
-            // Try to set the detected flavor
-            if flavor_registry.set_active_flavor(name) {
-                modeline_flavor.clone()
-            } else {
-                // Fallback if detected flavor doesn't exist
-                flavor_registry.get_active_flavor().map(|f| f.name.clone())
+impl HandlePullDiagnostics for Backend {
+    /// Report the diagnostics of one document
+    ///
+    /// Documents that are not open are read from disk; unreadable ones are
+    /// an error rather than an empty report.
+    async fn handle_diagnostic(
+        &self,
+        params: DocumentDiagnosticParams,
+    ) -> LspResult<DocumentDiagnosticReportResult> {
+        let uri = params.text_document.uri;
+        let previous = params.previous_result_id.as_deref();
+
+        let report = match self.diagnostics.pull(&uri, previous).await {
+            Some((report, _)) => report,
+            None => match self.pull_file(&uri, previous).await {
+                Some(report) => report,
+                // An empty report would claim the file is clean
+                None => {
+                    return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                        "Cannot read {}",
+                        uri
+                    )));
+                }
+            },
+        };
+
+        let report = match report {
+            DocumentDiagnosticReportKind::Full(full_document_diagnostic_report) => {
+                DocumentDiagnosticReport::Full(RelatedFullDocumentDiagnosticReport {
+                    related_documents: None,
+                    full_document_diagnostic_report,
+                })
+            }
+            DocumentDiagnosticReportKind::Unchanged(unchanged_document_diagnostic_report) => {
+                DocumentDiagnosticReport::Unchanged(RelatedUnchangedDocumentDiagnosticReport {
+                    related_documents: None,
+                    unchanged_document_diagnostic_report,
+                })
             }
-        } else {
-            // Use current active flavor or ensure we have one
-            flavor_registry.get_active_flavor().map(|f| f.name.clone())
         };
+        Ok(DocumentDiagnosticReportResult::Report(report))
+    }
 
-        DocumentState {
-            content,
-            flavor_name,
+    /// Report the diagnostics of every G-code file in the workspace folders
+    ///
+    /// Open documents are reported with their version and current text,
+    /// other files as they are on disk. Files unchanged since the client's
+    /// last report are not read again.
+    async fn handle_workspace_diagnostic(
+        &self,
+        params: WorkspaceDiagnosticParams,
+    ) -> LspResult<WorkspaceDiagnosticReportResult> {
+        let previous: HashMap<Url, String> = params
+            .previous_result_ids
+            .into_iter()
+            .map(|previous| (previous.uri, previous.value))
+            .collect();
+
+        let files = self.workspace_files().await;
+
+        let mut uris = self.documents.lock().await.uris();
+        uris.sort();
+        let mut seen: HashSet<Url> = uris.iter().cloned().collect();
+        uris.extend(
+            files
+                .into_iter()
+                .filter_map(|path| Url::from_file_path(path).ok())
+                .filter(|uri| seen.insert(uri.clone())),
+        );
+        self.diagnostics.retain_files(&seen).await;
+
+        let mut items = Vec::with_capacity(uris.len());
+        for uri in uris {
+            let previous = previous.get(&uri).map(String::as_str);
+            let (report, version) = match self.diagnostics.pull(&uri, previous).await {
+                Some((report, version)) => (report, Some(version as i64)),
+                None => match self.pull_file(&uri, previous).await {
+                    Some(report) => (report, None),
+                    None => continue,
+                },
+            };
+
+            items.push(match report {
+                DocumentDiagnosticReportKind::Full(full_document_diagnostic_report) => {
+                    WorkspaceDocumentDiagnosticReport::Full(WorkspaceFullDocumentDiagnosticReport {
+                        uri,
+                        version,
+                        full_document_diagnostic_report,
+                    })
+                }
+                DocumentDiagnosticReportKind::Unchanged(unchanged_document_diagnostic_report) => {
+                    WorkspaceDocumentDiagnosticReport::Unchanged(
+                        WorkspaceUnchangedDocumentDiagnosticReport {
+                            uri,
+                            version,
+                            unchanged_document_diagnostic_report,
+                        },
+                    )
+                }
+            });
         }
+
+        Ok(WorkspaceDiagnosticReportResult::Report(
+            WorkspaceDiagnosticReport { items },
+        ))
     }
 
-    /// Publish diagnostics for a document
-    async fn publish_diagnostics(&self, uri: Url) {
-        let docs = self.documents.lock().await;
-        let doc_state = match docs.get(&uri) {
-            Some(state) => state,
-            None => return,
-        };
+    /// Report the diagnostics of a file that is not open, as it is on disk
+    ///
+    /// The file is only read if it changed since its last report, or the
+    /// client doesn't have that report. Returns `None` if it can't be read.
+    async fn pull_file(
+        &self,
+        uri: &Url,
+        previous: Option<&str>,
+    ) -> Option<DocumentDiagnosticReportKind> {
+        let path = uri.to_file_path().ok()?;
+        let stamp = FileStamp::new(&tokio::fs::metadata(&path).await.ok()?);
+        if let Some(report) = self.diagnostics.pull_cached(uri, stamp, previous).await {
+            return Some(report);
+        }
 
-        let mut diagnostics = Vec::new();
+        let content = tokio::fs::read_to_string(&path).await.ok()?;
+        let config = self.resolve_document_config(uri, &content).await;
+        let report = self
+            .diagnostics
+            .pull_file(uri.clone(), stamp, content, config, previous)
+            .await;
+        Some(report)
+    }
 
-        // Use enhanced validation with parameter checking
-        let flavor_registry = self.flavor_registry.lock().await;
-        let validation_result = validate_document(&doc_state.content, &flavor_registry);
+    /// Ask a pulling client to fetch diagnostics again
+    async fn refresh_diagnostics(&self) {
+        self.diagnostics.invalidate();
+
+        let supported = self
+            .client_capabilities
+            .lock()
+            .await
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.diagnostic.as_ref())
+            .and_then(|diagnostic| diagnostic.refresh_support)
+            .unwrap_or(false);
+        if supported && let Err(e) = self.client.workspace_diagnostic_refresh().await {
+            log::warn!("Failed to refresh diagnostics: {}", e);
+        }
+    }
+}
 
-        // Convert validation results to LSP diagnostics
-        for validation_diagnostic in validation_result.diagnostics {
-            let lsp_diagnostic = self.create_lsp_diagnostic(validation_diagnostic);
-            diagnostics.push(lsp_diagnostic);
+/// Settings section the server reads from the client
+pub const SETTINGS_SECTION: &str = "gcode";
+
+/// Parse settings sent by the client
+///
+/// Accepts the settings object itself or an object wrapping it under the
+/// `gcode` section. Invalid settings are logged and ignored.
+pub fn settings_from_client(value: serde_json::Value) -> Option<Settings> {
+    let value = match value {
+        serde_json::Value::Null => return None,
+        serde_json::Value::Object(mut object) if object.contains_key(SETTINGS_SECTION) => {
+            object.remove(SETTINGS_SECTION)?
         }
+        value => value,
+    };
+
+    match serde_json::from_value(value) {
+        Ok(settings) => Some(settings),
+        Err(e) => {
+            log::warn!("Invalid client settings: {}", e);
+            None
+        }
+    }
+}
 
-        // Publish the diagnostics
-        self.client
-            .publish_diagnostics(uri, diagnostics, None)
-            .await;
+#[tower_lsp::async_trait]
+impl HandleConfiguration for Backend {
+    /// Apply new client settings and re-validate every open document
+    async fn handle_did_change_configuration(&self, params: DidChangeConfigurationParams) {
+        if let Some(settings) = settings_from_client(params.settings) {
+            self.load_settings_dirs(&settings).await;
+            *self.client_settings.lock().await = settings;
+        }
+
+        // Clients using the pull model send no settings, so ask again
+        let uris = self.documents.lock().await.uris();
+        for uri in &uris {
+            self.pull_settings(uri).await;
+        }
+
+        self.revalidate_open_documents().await;
+    }
+
+    /// Pull the settings for a document's scope with `workspace/configuration`
+    async fn pull_settings(&self, uri: &Url) {
+        let supported = self
+            .client_capabilities
+            .lock()
+            .await
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.configuration)
+            .unwrap_or(false);
+        if !supported {
+            return;
+        }
+
+        let item = ConfigurationItem {
+            scope_uri: Some(uri.clone()),
+            section: Some(SETTINGS_SECTION.to_string()),
+        };
+        let settings = match self.client.configuration(vec![item]).await {
+            Ok(values) => values.into_iter().next().and_then(settings_from_client),
+            Err(e) => {
+                log::warn!("Failed to pull configuration for {}: {}", uri, e);
+                None
+            }
+        };
+
+        if let Some(settings) = &settings {
+            self.load_settings_dirs(settings).await;
+        }
+
+        let mut scoped_settings = self.scoped_settings.lock().await;
+        match settings {
+            Some(settings) => scoped_settings.insert(uri.clone(), settings),
+            None => scoped_settings.remove(uri),
+        };
+    }
+
+    /// Reconfigure every open document and publish new diagnostics
+    ///
+    /// Clients pulling diagnostics are asked to pull again instead.
+    async fn revalidate_open_documents(&self) {
+        let uris = self.documents.lock().await.uris();
+        for uri in uris {
+            self.configure_document(&uri).await;
+            self.diagnostics.publish(uri).await;
+        }
+        if !self.diagnostics.push_enabled() {
+            self.refresh_diagnostics().await;
+        }
     }
+}
 
-    fn create_lsp_diagnostic(
+#[tower_lsp::async_trait]
+impl HandleCodeAction for Backend {
+    async fn handle_code_action(
         &self,
-        validation_diagnostic: crate::validation::engine::Diagnostic,
-    ) -> tower_lsp::lsp_types::Diagnostic {
-        use crate::validation::engine::Severity;
-
-        let severity = match validation_diagnostic.severity {
-            Severity::Error => DiagnosticSeverity::ERROR,
-            Severity::Warning => DiagnosticSeverity::WARNING,
-            Severity::Info => DiagnosticSeverity::INFORMATION,
+        params: CodeActionParams,
+    ) -> LspResult<Option<CodeActionResponse>> {
+        let uri = params.text_document.uri;
+
+        let Some(doc_state) = self.document_snapshot(&uri).await else {
+            return Ok(None);
         };
 
-        tower_lsp::lsp_types::Diagnostic::new(
-            Range::new(
-                Position::new((validation_diagnostic.line - 1) as u32, 0),
-                Position::new((validation_diagnostic.line - 1) as u32, 100), // Arbitrary end position
-            ),
-            Some(severity),
-            None,
-            Some("gcode-ls".to_string()),
-            validation_diagnostic.message,
-            None,
-            None,
-        )
+        // The document's own flavor, which its diagnostics came from
+        let flavor = self
+            .flavor_registry
+            .lock()
+            .await
+            .snapshot(doc_state.config.flavor_name.as_deref());
+
+        let first_line = params.range.start.line as usize;
+        let last_line = params.range.end.line as usize;
+
+        let mut actions = Vec::new();
+
+        for (line_idx, (line, parsed)) in doc_state
+            .content
+            .lines()
+            .zip(doc_state.parsed.iter())
+            .enumerate()
+            .skip(first_line)
+            .take(last_line.saturating_sub(first_line) + 1)
+        {
+            let Some((range, replacement)) =
+                deprecated_command_edit(line_idx, line, parsed, &flavor)
+            else {
+                continue;
+            };
+
+            // Link the quick fix to the deprecation diagnostics it resolves
+            let diagnostics: Vec<_> = params
+                .context
+                .diagnostics
+                .iter()
+                .filter(|d| {
+                    d.range.start.line == range.start.line
+                        && d.tags
+                            .as_ref()
+                            .is_some_and(|tags| tags.contains(&DiagnosticTag::DEPRECATED))
+                })
+                .cloned()
+                .collect();
+
+            let mut changes = std::collections::HashMap::new();
+            changes.insert(
+                uri.clone(),
+                vec![TextEdit {
+                    range,
+                    new_text: replacement.clone(),
+                }],
+            );
+
+            actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                title: format!("Replace with '{}'", replacement),
+                kind: Some(CodeActionKind::QUICKFIX),
+                diagnostics: if diagnostics.is_empty() {
+                    None
+                } else {
+                    Some(diagnostics)
+                },
+                edit: Some(WorkspaceEdit {
+                    changes: Some(changes),
+                    ..Default::default()
+                }),
+                is_preferred: Some(true),
+                ..Default::default()
+            }));
+        }
+
+        if actions.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(actions))
+        }
     }
 }
 
+/// State before `line`, simulated on a blocking thread
+///
+/// The first request after an edit may replay the whole document, which must
+/// neither hold the documents lock nor stall the runtime.
+async fn state_before(doc: &DocumentSnapshot, line: usize) -> LspResult<TrackedState> {
+    let doc = doc.clone();
+    tokio::task::spawn_blocking(move || {
+        doc.simulation()
+            .state_before(&doc.content, &doc.parsed, line)
+    })
+    .await
+    .map_err(|_| tower_lsp::jsonrpc::Error::internal_error())
+}
+
+/// Compute the edit replacing a deprecated command with its replacement
+///
+/// Returns the range of the command name on the line and the replacement text,
+/// or `None` if the line has no deprecated command with a known replacement.
+/// Klipper extended commands are read from the raw line.
+fn deprecated_command_edit(
+    line_idx: usize,
+    line: &str,
+    parsed: &ParsedLine,
+    registry: &FlavorRegistry,
+) -> Option<(Range, String)> {
+    let span = match (extended_command_span(line), parsed) {
+        (Some(span), _) => span,
+        (None, ParsedLine::Command(command)) => command.span.clone(),
+        (None, _) => return None,
+    };
+
+    let cmd_def = registry.get_command(&line[span.clone()].to_uppercase())?;
+    if !cmd_def.deprecated {
+        return None;
+    }
+    let replacement = cmd_def.replaced_by.clone()?;
+
+    let range = Range::new(
+        Position::new(line_idx as u32, utf16_column(line, span.start)),
+        Position::new(line_idx as u32, utf16_column(line, span.end)),
+    );
+
+    Some((range, replacement))
+}
+
 #[tower_lsp::async_trait]
 impl HandleDocumentSymbol for Backend {
     async fn handle_document_symbol(
@@ -358,24 +1023,29 @@ impl HandleDocumentSymbol for Backend {
     ) -> LspResult<Option<DocumentSymbolResponse>> {
         let uri = params.text_document.uri;
 
-        let docs = self.documents.lock().await;
-        let doc_state = match docs.get(&uri) {
-            Some(state) => state,
-            None => return Ok(None),
+        let Some(doc_state) = self.document_snapshot(&uri).await else {
+            return Ok(None);
         };
 
-        // Get flavor registry for enhanced symbol details
-        let flavor_registry = self.flavor_registry.lock().await;
+        // The document's own flavor, for enhanced symbol details
+        let flavor = self
+            .flavor_registry
+            .lock()
+            .await
+            .snapshot(doc_state.config.flavor_name.as_deref());
 
         let mut symbols = Vec::new();
 
-        for (line_idx, line) in doc_state.content.lines().enumerate() {
-            let parsed = crate::parser::parse_line(line);
-
-            if let crate::parser::ParsedLine::Command(command) = parsed {
+        for (line_idx, (line, parsed)) in doc_state
+            .content
+            .lines()
+            .zip(doc_state.parsed.iter())
+            .enumerate()
+        {
+            if let ParsedLine::Command(command) = parsed {
                 // Generate basic symbol name (command + first 3 parameters)
                 let symbol_name = if command.parameters.is_empty() {
-                    command.name.clone()
+                    command.name.to_string()
                 } else {
                     let params: Vec<String> = command
                         .parameters
@@ -387,7 +1057,7 @@ impl HandleDocumentSymbol for Backend {
                 };
 
                 // Enhanced symbol detail using flavor registry
-                let symbol_detail = flavor_registry.get_command(&command.name).map(|cmd_def| {
+                let symbol_detail = flavor.get_command(&command.name).map(|cmd_def| {
                     let mut detail = cmd_def
                         .description_short
                         .clone()
@@ -431,12 +1101,12 @@ impl HandleDocumentSymbol for Backend {
 
                 let range = Range::new(
                     Position::new(line_idx as u32, 0),
-                    Position::new(line_idx as u32, line.len() as u32),
+                    Position::new(line_idx as u32, utf16_column(line, line.len())),
                 );
 
                 let selection_range = Range::new(
-                    Position::new(line_idx as u32, 0),
-                    Position::new(line_idx as u32, command.name.len() as u32),
+                    Position::new(line_idx as u32, utf16_column(line, command.span.start)),
+                    Position::new(line_idx as u32, utf16_column(line, command.span.end)),
                 );
 
                 let symbol = DocumentSymbol {
@@ -461,25 +1131,19 @@ impl HandleDocumentSymbol for Backend {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::parser::{Command, Parameter, parse_line};
+    use crate::parser::{Command, ParsedLine, parse_line};
+
+    fn parse_command(line: &str) -> Command<'_> {
+        match parse_line(line) {
+            ParsedLine::Command(command) => command,
+            other => panic!("Expected command, got {:?}", other),
+        }
+    }
 
     #[test]
     fn test_symbol_name_generation() {
         // Test simple G-code with few parameters
-        let mut command = Command {
-            name: "G1".to_string(),
-            parameters: vec![
-                Parameter {
-                    letter: 'X',
-                    value: "10".to_string(),
-                },
-                Parameter {
-                    letter: 'Y',
-                    value: "20".to_string(),
-                },
-            ],
-            comment: None,
-        };
+        let mut command = parse_command("G1 X10 Y20");
 
         let name = format!(
             "{} {}",
@@ -496,28 +1160,7 @@ mod tests {
         assert_eq!(name, "G1 X10 Y20");
 
         // Test with many parameters - should limit to first 3
-        command.parameters = vec![
-            Parameter {
-                letter: 'X',
-                value: "10".to_string(),
-            },
-            Parameter {
-                letter: 'Y',
-                value: "20".to_string(),
-            },
-            Parameter {
-                letter: 'Z',
-                value: "0.3".to_string(),
-            },
-            Parameter {
-                letter: 'E',
-                value: "5.5".to_string(),
-            },
-            Parameter {
-                letter: 'F',
-                value: "1500".to_string(),
-            },
-        ];
+        command = parse_command("G1 X10 Y20 Z0.3 E5.5 F1500");
 
         let name = format!(
             "{} {}",
@@ -537,11 +1180,7 @@ mod tests {
     #[test]
     fn test_symbol_kind_mapping() {
         // Test G-code -> FUNCTION
-        let g_command = Command {
-            name: "G1".to_string(),
-            parameters: vec![],
-            comment: None,
-        };
+        let g_command = parse_command("G1");
         let kind = match g_command.name.chars().next().unwrap() {
             'G' => SymbolKind::FUNCTION,
             'M' => SymbolKind::PROPERTY,
@@ -551,11 +1190,7 @@ mod tests {
         assert_eq!(kind, SymbolKind::FUNCTION);
 
         // Test M-code -> PROPERTY
-        let m_command = Command {
-            name: "M104".to_string(),
-            parameters: vec![],
-            comment: None,
-        };
+        let m_command = parse_command("M104");
         let kind = match m_command.name.chars().next().unwrap() {
             'G' => SymbolKind::FUNCTION,
             'M' => SymbolKind::PROPERTY,
@@ -565,11 +1200,7 @@ mod tests {
         assert_eq!(kind, SymbolKind::PROPERTY);
 
         // Test T-code -> VARIABLE
-        let t_command = Command {
-            name: "T1".to_string(),
-            parameters: vec![],
-            comment: None,
-        };
+        let t_command = parse_command("T1");
         let kind = match t_command.name.chars().next().unwrap() {
             'G' => SymbolKind::FUNCTION,
             'M' => SymbolKind::PROPERTY,
@@ -594,7 +1225,7 @@ mod tests {
             let parsed = parse_line(input);
             if let crate::parser::ParsedLine::Command(cmd) = parsed {
                 let symbol_name = if cmd.parameters.is_empty() {
-                    cmd.name.clone()
+                    cmd.name.to_string()
                 } else {
                     format!(
                         "{} {}",
@@ -740,7 +1371,7 @@ mod tests {
             crate::parser::parse_line("G1 X10 Y20 Z0.3")
         {
             let symbol_name = if command.parameters.is_empty() {
-                command.name.clone()
+                command.name.to_string()
             } else {
                 let params: Vec<String> = command
                     .parameters
@@ -757,4 +1388,84 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_deprecated_command_edit() {
+        use crate::flavor::schema::{CommandDef, Flavor};
+
+        let mut commands = std::collections::HashMap::new();
+        commands.insert(
+            "M999".to_string(),
+            CommandDef {
+                name: "M999".to_string(),
+                description_short: None,
+                description_long: None,
+                parameters: None,
+                constraints: None,
+                deprecated: true,
+                replaced_by: Some("M998".to_string()),
+            },
+        );
+        let mut registry = FlavorRegistry::new();
+        registry.add_flavor(Flavor {
+            name: "test".to_string(),
+            version: None,
+            description: None,
+            commands,
+            snippets: Vec::new(),
+        });
+        registry.set_active_flavor("test");
+
+        let edit = |line_idx: usize, line: &str| {
+            deprecated_command_edit(line_idx, line, &parse_line(line), &registry)
+        };
+
+        let (range, replacement) = edit(4, "  M999 S1 ; reset").expect("quick fix");
+        assert_eq!(replacement, "M998");
+        assert_eq!(range, Range::new(Position::new(4, 2), Position::new(4, 6)));
+
+        // Columns count UTF-16 code units, not bytes
+        let (range, _) = edit(0, "é M999").expect("quick fix");
+        assert_eq!(range, Range::new(Position::new(0, 2), Position::new(0, 6)));
+
+        // Lines without a deprecated command have no fix
+        assert!(edit(0, "G1 X10").is_none());
+        assert!(edit(0, "; M999").is_none());
+    }
+
+    #[test]
+    fn test_deprecated_klipper_command_edit() {
+        use crate::flavor::schema::{Flavor, FlavorFile};
+
+        let klipper = include_str!("../../resources/flavors/klipper.gcode-flavor.toml");
+        let file: FlavorFile = toml::from_str(klipper).unwrap();
+        let mut registry = FlavorRegistry::new();
+        registry.add_flavor(Flavor::from(file));
+        registry.set_active_flavor("klipper");
+
+        let line = "  SYNC_STEPPER_TO_EXTRUDER STEPPER=belted EXTRUDER=extruder";
+        let (range, replacement) =
+            deprecated_command_edit(7, line, &parse_line(line), &registry).expect("quick fix");
+        assert_eq!(replacement, "SYNC_EXTRUDER_MOTION");
+        assert_eq!(range, Range::new(Position::new(7, 2), Position::new(7, 26)));
+
+        let line = "SYNC_EXTRUDER_MOTION EXTRUDER=belted MOTION_QUEUE=extruder";
+        assert!(deprecated_command_edit(0, line, &parse_line(line), &registry).is_none());
+    }
+
+    #[test]
+    fn test_settings_from_client() {
+        let wrapped = serde_json::json!({ "gcode": { "flavor": "marlin", "flavorDirs": ["/f"] } });
+        let settings = settings_from_client(wrapped).unwrap();
+        assert_eq!(settings.flavor.as_deref(), Some("marlin"));
+        assert_eq!(settings.flavor_dirs, vec![std::path::PathBuf::from("/f")]);
+
+        let bare =
+            serde_json::json!({ "machine": "prusa-mk4", "rules": { "cold-extrusion": "off" } });
+        let settings = settings_from_client(bare).unwrap();
+        assert_eq!(settings.machine.as_deref(), Some("prusa-mk4"));
+
+        assert!(settings_from_client(serde_json::Value::Null).is_none());
+        assert!(settings_from_client(serde_json::json!({ "flavor": 3 })).is_none());
+    }
 }
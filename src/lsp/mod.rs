@@ -3,7 +3,6 @@
 //! Clean LSP backend focused only on protocol handling.
 
 pub mod backend;
-pub mod document;
 pub mod handlers;
 pub mod server;
 
@@ -5,31 +5,22 @@ use tokio::io::{stdin, stdout};
 use tower_lsp::{LspService, Server};
 
 use crate::Config;
-use crate::flavor::registry::FlavorRegistry;
 use crate::lsp::backend::Backend;
 
 /// Start the LSP server
-pub async fn serve() -> Result<()> {
-    let config = Config::from_args_and_env()?;
-
-    // Initialize flavor registry with embedded Prusa flavor
-    let mut flavor_registry = FlavorRegistry::new();
-    flavor_registry.add_embedded_prusa_flavor();
-
-    // Set active flavor from config or default to "prusa"
-    let active_flavor = config
-        .get_effective_flavor()
-        .unwrap_or_else(|| "prusa".to_string());
-    flavor_registry.set_active_flavor(&active_flavor);
+pub async fn serve(config: Config) -> Result<()> {
+    let flavor_registry = config.flavor_registry();
+    let machine_registry = config.machine_registry();
 
     // Write embedded flavor to user's config directory for easy access
     if let Err(e) = write_embedded_flavor_to_disk() {
         log::warn!("Failed to write embedded flavor to disk: {}", e);
     }
 
-    let (service, socket) =
-        LspService::build(move |client| Backend::new(client, config.clone(), flavor_registry))
-            .finish();
+    let (service, socket) = LspService::build(move |client| {
+        Backend::new(client, config.clone(), flavor_registry, machine_registry)
+    })
+    .finish();
 
     Server::new(stdin(), stdout(), socket).serve(service).await;
 
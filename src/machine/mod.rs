@@ -0,0 +1,14 @@
+//! Machine Profiles
+//!
+//! Physical limits of a specific printer, and simulated machine state.
+//! Flavors describe the firmware; machine profiles describe the hardware.
+
+pub mod profile;
+pub mod registry;
+pub mod simulation;
+pub mod state;
+
+pub use profile::{Kinematics, MachineProfile};
+pub use registry::MachineRegistry;
+pub use simulation::{Simulation, TrackedState};
+pub use state::MachineState;
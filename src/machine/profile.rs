@@ -0,0 +1,232 @@
+//! Machine Profile Types
+//!
+//! Simple types for machine profile files (`*.gcode-machine.toml`).
+
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Machine profile (matches TOML)
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct MachineProfile {
+    pub machine: MachineMeta,
+    pub build_volume: BuildVolume,
+    pub axis_limits: Option<AxisLimits>,
+    pub temperatures: Option<TemperatureLimits>,
+}
+
+/// Machine metadata
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct MachineMeta {
+    pub name: String,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub kinematics: Kinematics,
+    #[serde(default = "default_extruder_count")]
+    pub extruder_count: u32,
+}
+
+/// Motion system of the machine
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Kinematics {
+    #[default]
+    Cartesian,
+    CoreXY,
+    Delta,
+}
+
+/// Printable volume in millimeters
+///
+/// Cartesian machines use `x` and `y` with the origin at the front-left
+/// corner of the bed. Delta machines use `radius` around a centered origin.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct BuildVolume {
+    pub x: Option<f64>,
+    pub y: Option<f64>,
+    pub z: f64,
+    pub radius: Option<f64>,
+}
+
+/// Explicit axis travel limits, overriding the build volume
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct AxisLimits {
+    pub x: Option<AxisRange>,
+    pub y: Option<AxisRange>,
+    pub z: Option<AxisRange>,
+}
+
+/// Travel range of a single axis in millimeters
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+pub struct AxisRange {
+    pub min: f64,
+    pub max: f64,
+}
+
+/// Heater temperature limits in Celsius
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct TemperatureLimits {
+    pub max_hotend: Option<f64>,
+    pub max_bed: Option<f64>,
+    /// Minimum hotend temperature for extrusion
+    pub min_extrude: Option<f64>,
+}
+
+fn default_extruder_count() -> u32 {
+    1
+}
+
+impl MachineProfile {
+    pub fn name(&self) -> &str {
+        &self.machine.name
+    }
+
+    /// Get the travel range of an axis ('X', 'Y' or 'Z')
+    pub fn axis_range(&self, axis: char) -> Option<AxisRange> {
+        let explicit = self.axis_limits.as_ref().and_then(|limits| match axis {
+            'X' => limits.x,
+            'Y' => limits.y,
+            'Z' => limits.z,
+            _ => None,
+        });
+        if explicit.is_some() {
+            return explicit;
+        }
+
+        let volume = &self.build_volume;
+        match (axis, self.machine.kinematics) {
+            ('Z', _) => Some(AxisRange {
+                min: 0.0,
+                max: volume.z,
+            }),
+            ('X' | 'Y', Kinematics::Delta) => volume.radius.map(|radius| AxisRange {
+                min: -radius,
+                max: radius,
+            }),
+            ('X', _) => volume.x.map(|max| AxisRange { min: 0.0, max }),
+            ('Y', _) => volume.y.map(|max| AxisRange { min: 0.0, max }),
+            _ => None,
+        }
+    }
+
+    /// Check that an XY point lies on a delta machine's circular bed
+    ///
+    /// Always true for other kinematics, where the axis ranges apply instead.
+    pub fn contains_xy(&self, x: f64, y: f64) -> bool {
+        match (self.machine.kinematics, self.build_volume.radius) {
+            (Kinematics::Delta, Some(radius)) => x.hypot(y) <= radius,
+            _ => true,
+        }
+    }
+
+    pub fn max_hotend_temperature(&self) -> Option<f64> {
+        self.temperatures.as_ref()?.max_hotend
+    }
+
+    pub fn max_bed_temperature(&self) -> Option<f64> {
+        self.temperatures.as_ref()?.max_bed
+    }
+
+    pub fn min_extrude_temperature(&self) -> Option<f64> {
+        self.temperatures.as_ref()?.min_extrude
+    }
+
+    /// Named settings available to flavor `compare` constraints
+    pub fn settings(&self) -> HashMap<String, f64> {
+        let mut settings = HashMap::new();
+        settings.insert(
+            "extruder_count".to_string(),
+            self.machine.extruder_count as f64,
+        );
+        settings.insert("build_volume_z".to_string(), self.build_volume.z);
+        if let Some(x) = self.build_volume.x {
+            settings.insert("build_volume_x".to_string(), x);
+        }
+        if let Some(y) = self.build_volume.y {
+            settings.insert("build_volume_y".to_string(), y);
+        }
+        if let Some(max) = self.max_hotend_temperature() {
+            settings.insert("max_hotend_temperature".to_string(), max);
+        }
+        if let Some(max) = self.max_bed_temperature() {
+            settings.insert("max_bed_temperature".to_string(), max);
+        }
+        settings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cartesian_profile() {
+        let profile: MachineProfile = toml::from_str(
+            r#"
+            [machine]
+            name = "test"
+            kinematics = "corexy"
+            extruder_count = 2
+
+            [build_volume]
+            x = 250
+            y = 210
+            z = 220
+
+            [axis_limits]
+            y = { min = -4, max = 212 }
+
+            [temperatures]
+            max_hotend = 290
+            max_bed = 120
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(profile.machine.kinematics, Kinematics::CoreXY);
+        assert_eq!(
+            profile.axis_range('X'),
+            Some(AxisRange {
+                min: 0.0,
+                max: 250.0
+            })
+        );
+        assert_eq!(
+            profile.axis_range('Y'),
+            Some(AxisRange {
+                min: -4.0,
+                max: 212.0
+            })
+        );
+        assert_eq!(profile.max_hotend_temperature(), Some(290.0));
+        assert_eq!(profile.settings()["extruder_count"], 2.0);
+        assert!(profile.contains_xy(1000.0, 1000.0));
+    }
+
+    #[test]
+    fn test_delta_profile_defaults() {
+        let profile: MachineProfile = toml::from_str(
+            r#"
+            [machine]
+            name = "delta"
+            kinematics = "delta"
+
+            [build_volume]
+            radius = 100
+            z = 300
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(profile.machine.extruder_count, 1);
+        assert_eq!(
+            profile.axis_range('X'),
+            Some(AxisRange {
+                min: -100.0,
+                max: 100.0
+            })
+        );
+        assert!(profile.contains_xy(60.0, 60.0));
+        assert!(!profile.contains_xy(80.0, 80.0));
+        assert_eq!(profile.max_bed_temperature(), None);
+    }
+}
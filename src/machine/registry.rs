@@ -0,0 +1,180 @@
+//! Machine Registry
+//!
+//! Simple in-memory registry of machine profiles.
+
+use super::profile::MachineProfile;
+use crate::flavor::registry::modeline_values;
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// File name suffix of machine profile files
+pub const MACHINE_FILE_SUFFIX: &str = ".gcode-machine.toml";
+
+/// Simple in-memory machine profile registry
+#[derive(Debug, Clone, Default)]
+pub struct MachineRegistry {
+    profiles: HashMap<String, MachineProfile>,
+    active_profile: Option<String>,
+}
+
+impl MachineRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a profile to the registry
+    pub fn add_profile(&mut self, profile: MachineProfile) {
+        self.profiles.insert(profile.machine.name.clone(), profile);
+    }
+
+    /// Set the default profile for documents without a modeline
+    pub fn set_active_profile(&mut self, name: &str) -> bool {
+        if self.profiles.contains_key(name) {
+            self.active_profile = Some(name.to_string());
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Get the default profile
+    pub fn get_active_profile(&self) -> Option<&MachineProfile> {
+        self.active_profile
+            .as_ref()
+            .and_then(|name| self.profiles.get(name))
+    }
+
+    /// Get a profile by name
+    pub fn get_profile(&self, name: &str) -> Option<&MachineProfile> {
+        self.profiles.get(name)
+    }
+
+    /// List all available profiles
+    pub fn list_profiles(&self) -> Vec<&str> {
+        self.profiles.keys().map(|s| s.as_str()).collect()
+    }
+
+    /// Pick the machine profile for a document
+    ///
+    /// A modeline wins, then the `configured` profile if it exists, then the
+    /// active profile.
+    pub fn select_profile(&self, content: &str, configured: Option<&str>) -> Option<String> {
+        self.detect_modeline_profile(content)
+            .or_else(|| {
+                configured
+                    .filter(|name| self.profiles.contains_key(*name))
+                    .map(String::from)
+            })
+            .or_else(|| self.active_profile.clone())
+    }
+
+    /// Load a machine profile from a TOML file
+    pub fn load_file(&mut self, path: &Path) -> Result<()> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read machine profile {:?}", path))?;
+        let profile: MachineProfile = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse machine profile {:?}", path))?;
+        self.add_profile(profile);
+        Ok(())
+    }
+
+    /// Load every `*.gcode-machine.toml` file in a directory
+    ///
+    /// A missing directory is not an error. Invalid files are logged and skipped.
+    pub fn load_directory(&mut self, dir: &Path) -> usize {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return 0;
+        };
+
+        let mut loaded = 0;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let is_profile = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.ends_with(MACHINE_FILE_SUFFIX));
+            if !is_profile {
+                continue;
+            }
+
+            match self.load_file(&path) {
+                Ok(()) => loaded += 1,
+                Err(e) => log::warn!("{:#}", e),
+            }
+        }
+
+        loaded
+    }
+
+    /// Add the machine profiles shipped with the server
+    pub fn add_embedded_profiles(&mut self) {
+        let embedded = [include_str!(
+            "../../resources/machines/prusa-mk4.gcode-machine.toml"
+        )];
+
+        for content in embedded {
+            match toml::from_str::<MachineProfile>(content) {
+                Ok(profile) => self.add_profile(profile),
+                Err(e) => log::warn!("Failed to parse embedded machine profile: {}", e),
+            }
+        }
+    }
+
+    /// Detect machine profile from modeline in document content
+    ///
+    /// Looks for `gcode_machine=name` in the first and last lines.
+    pub fn detect_modeline_profile(&self, content: &str) -> Option<String> {
+        modeline_values(content, "gcode_machine")
+            .into_iter()
+            .find(|name| self.profiles.contains_key(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_embedded_profiles() {
+        let mut registry = MachineRegistry::new();
+        registry.add_embedded_profiles();
+
+        assert!(registry.get_profile("prusa-mk4").is_some());
+        assert!(registry.get_active_profile().is_none());
+        assert!(registry.set_active_profile("prusa-mk4"));
+        assert!(!registry.set_active_profile("nonexistent"));
+    }
+
+    #[test]
+    fn test_detect_modeline_profile() {
+        let mut registry = MachineRegistry::new();
+        registry.add_embedded_profiles();
+
+        let content = "; gcode_flavor=prusa gcode_machine=prusa-mk4\nG28\n";
+        assert_eq!(
+            registry.detect_modeline_profile(content),
+            Some("prusa-mk4".to_string())
+        );
+        assert_eq!(
+            registry.detect_modeline_profile("; gcode_machine=unknown\nG28\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_load_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("tiny.gcode-machine.toml"),
+            "[machine]\nname = \"tiny\"\n\n[build_volume]\nx = 100\ny = 100\nz = 100\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("broken.gcode-machine.toml"), "not toml [").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "ignored").unwrap();
+
+        let mut registry = MachineRegistry::new();
+        assert_eq!(registry.load_directory(dir.path()), 1);
+        assert!(registry.get_profile("tiny").is_some());
+    }
+}
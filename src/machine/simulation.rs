@@ -0,0 +1,236 @@
+//! Document Simulation
+//!
+//! Machine state at any line of a document, and the line that last set each
+//! modal value. States are checkpointed every few hundred lines, so looking
+//! one up replays at most one interval, and an edit only invalidates the
+//! checkpoints after it.
+
+use crate::machine::MachineState;
+use crate::parser::{Command, ParsedDocument, ParsedLine};
+
+/// Lines between two checkpoints
+const CHECKPOINT_INTERVAL: usize = 256;
+
+/// Zero-based line that last set each modal value, `None` if never set
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ModalOrigins {
+    pub position: Option<usize>,
+    pub positioning: Option<usize>,
+    pub extruder_positioning: Option<usize>,
+    pub units: Option<usize>,
+    pub plane: Option<usize>,
+    pub tool: Option<usize>,
+    pub hotend: Option<usize>,
+    pub bed: Option<usize>,
+    pub fan: Option<usize>,
+    pub feedrate: Option<usize>,
+}
+
+/// Machine state together with where its modal values came from
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrackedState {
+    pub state: MachineState,
+    pub origins: ModalOrigins,
+}
+
+impl TrackedState {
+    /// Apply zero-based line `line`, given its text and its parse
+    ///
+    /// Klipper heater commands are read from the text, as in validation.
+    pub fn apply_line(&mut self, text: &str, parsed: &ParsedLine, line: usize) {
+        let (hotend, bed) = (self.state.hotend(), self.state.bed);
+        if self.state.apply_extended_command(text) {
+            if self.state.hotend() != hotend {
+                self.origins.hotend = Some(line);
+            }
+            if self.state.bed != bed {
+                self.origins.bed = Some(line);
+            }
+        } else if let ParsedLine::Command(cmd) = parsed {
+            self.apply(cmd, line);
+        }
+    }
+
+    /// Apply the command on zero-based line `line`
+    pub fn apply(&mut self, cmd: &Command, line: usize) {
+        self.state.apply(cmd);
+
+        let origins = &mut self.origins;
+        let has = |letter: char| {
+            cmd.parameters
+                .iter()
+                .any(|p| p.letter.eq_ignore_ascii_case(&letter))
+        };
+        match cmd.name.to_uppercase().as_str() {
+            "G0" | "G1" | "G2" | "G3" => {
+                origins.position = Some(line);
+                if has('F') {
+                    origins.feedrate = Some(line);
+                }
+            }
+            "G28" | "G92" => origins.position = Some(line),
+            "G90" | "G91" => {
+                origins.positioning = Some(line);
+                origins.extruder_positioning = Some(line);
+            }
+            "M82" | "M83" => origins.extruder_positioning = Some(line),
+            "G20" | "G21" => origins.units = Some(line),
+            "G17" | "G18" | "G19" => origins.plane = Some(line),
+            "M104" | "M109" => origins.hotend = Some(line),
+            "M140" | "M190" => origins.bed = Some(line),
+            "M106" | "M107" => origins.fan = Some(line),
+            name if name.starts_with('T') && name[1..].parse::<u32>().is_ok() => {
+                origins.tool = Some(line)
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Checkpointed machine states of a document
+#[derive(Debug, Clone, PartialEq)]
+pub struct Simulation {
+    /// `checkpoints[i]` is the state before line `i * CHECKPOINT_INTERVAL`
+    checkpoints: Vec<TrackedState>,
+}
+
+impl Default for Simulation {
+    fn default() -> Self {
+        Self {
+            checkpoints: vec![TrackedState::default()],
+        }
+    }
+}
+
+impl Simulation {
+    /// Simulate a whole document, `parsed` being the parse of `content`
+    pub fn run(content: &str, parsed: &ParsedDocument) -> Self {
+        Self::default().resume(content, parsed)
+    }
+
+    /// Continue a truncated simulation to the end of the document
+    pub fn resume(mut self, content: &str, parsed: &ParsedDocument) -> Self {
+        let start = (self.checkpoints.len() - 1) * CHECKPOINT_INTERVAL;
+        let mut state = self.checkpoints[self.checkpoints.len() - 1].clone();
+
+        let numbered = content.lines().zip(parsed.iter()).enumerate();
+        for (idx, (text, line)) in numbered.skip(start) {
+            if idx % CHECKPOINT_INTERVAL == 0 && idx / CHECKPOINT_INTERVAL == self.checkpoints.len()
+            {
+                self.checkpoints.push(state.clone());
+            }
+            state.apply_line(text, line, idx);
+        }
+        self
+    }
+
+    /// Keep only the checkpoints that do not depend on `line` or later lines
+    pub fn truncate(&self, line: usize) -> Self {
+        let keep = line / CHECKPOINT_INTERVAL + 1;
+        Self {
+            checkpoints: self.checkpoints[..keep.min(self.checkpoints.len())].to_vec(),
+        }
+    }
+
+    /// State before the zero-based `line`, replaying from the nearest checkpoint
+    pub fn state_before(
+        &self,
+        content: &str,
+        parsed: &ParsedDocument,
+        line: usize,
+    ) -> TrackedState {
+        let checkpoint = (line / CHECKPOINT_INTERVAL).min(self.checkpoints.len() - 1);
+        let start = checkpoint * CHECKPOINT_INTERVAL;
+
+        let mut state = self.checkpoints[checkpoint].clone();
+        let numbered = content.lines().zip(parsed.iter()).enumerate();
+        for (idx, (text, parsed_line)) in numbered.take(line).skip(start) {
+            state.apply_line(text, parsed_line, idx);
+        }
+        state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(lines: usize) -> (String, ParsedDocument) {
+        let content: String = (0..lines)
+            .map(|idx| match idx % 4 {
+                0 => "G91\n".to_string(),
+                1 => format!("G1 X1 F{}\n", idx),
+                2 => format!("M104 S{}\n", 200 + idx % 50),
+                _ => "G90\n".to_string(),
+            })
+            .collect();
+        let content = format!("G28\n{}", content);
+        let parsed = ParsedDocument::parse(&content);
+        (content, parsed)
+    }
+
+    fn replay(content: &str, parsed: &ParsedDocument, line: usize) -> TrackedState {
+        let mut state = TrackedState::default();
+        let numbered = content.lines().zip(parsed.iter()).enumerate();
+        for (idx, (text, parsed_line)) in numbered.take(line) {
+            state.apply_line(text, parsed_line, idx);
+        }
+        state
+    }
+
+    #[test]
+    fn test_state_before_matches_replay() {
+        let (content, parsed) = document(1000);
+        let simulation = Simulation::run(&content, &parsed);
+
+        for line in [0, 1, 255, 256, 257, 700, 1001, 2000] {
+            assert_eq!(
+                simulation.state_before(&content, &parsed, line),
+                replay(&content, &parsed, line)
+            );
+        }
+    }
+
+    #[test]
+    fn test_origins() {
+        let content = "G28\nG91\nM106 S255\nG1 X5 F600\nG1 X5\nT1\n";
+        let parsed = ParsedDocument::parse(content);
+        let state = Simulation::run(content, &parsed).state_before(content, &parsed, 6);
+
+        assert_eq!(state.origins.positioning, Some(1));
+        assert_eq!(state.origins.fan, Some(2));
+        assert_eq!(state.origins.feedrate, Some(3));
+        assert_eq!(state.origins.position, Some(4));
+        assert_eq!(state.origins.tool, Some(5));
+        assert_eq!(state.origins.units, None);
+        assert_eq!(state.state.position.x, Some(10.0));
+    }
+
+    #[test]
+    fn test_truncate_and_resume() {
+        let (content, parsed) = document(1000);
+        let simulation = Simulation::run(&content, &parsed);
+
+        let (edited, edited_parsed) = document(600);
+        let resumed = simulation.truncate(300).resume(&edited, &edited_parsed);
+        assert_eq!(resumed, Simulation::run(&edited, &edited_parsed));
+    }
+
+    #[test]
+    fn test_klipper_heater_commands() {
+        let content = "\
+SET_HEATER_TEMPERATURE HEATER=extruder TARGET=215
+SET_HEATER_TEMPERATURE HEATER=heater_bed TARGET=60
+TEMPERATURE_WAIT SENSOR=extruder MINIMUM=210
+G28
+";
+        let parsed = ParsedDocument::parse(content);
+        let state = Simulation::run(content, &parsed).state_before(content, &parsed, 4);
+
+        let hotend = state.state.hotend().unwrap();
+        assert_eq!((hotend.target, hotend.waited), (215.0, true));
+        assert_eq!(state.state.bed.unwrap().target, 60.0);
+        assert_eq!(state.origins.hotend, Some(2));
+        assert_eq!(state.origins.bed, Some(1));
+    }
+}
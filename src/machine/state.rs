@@ -0,0 +1,550 @@
+//! Simulated Machine State
+//!
+//! Tracks modal state and position through a document, one command at a time.
+//! Positions are stored in millimeters regardless of the active units.
+
+use crate::parser::Command;
+use std::collections::BTreeMap;
+
+/// Positioning mode of the axes or the extruder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PositioningMode {
+    Absolute,
+    Relative,
+}
+
+/// Units of coordinates in commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Units {
+    Millimeters,
+    Inches,
+}
+
+/// Arc plane selection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Plane {
+    XY,
+    XZ,
+    YZ,
+}
+
+/// Axis positions, `None` while unknown (e.g. before homing)
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Position {
+    pub x: Option<f64>,
+    pub y: Option<f64>,
+    pub z: Option<f64>,
+    pub e: Option<f64>,
+}
+
+impl Position {
+    /// Get an axis position by letter
+    pub fn axis(&self, axis: char) -> Option<f64> {
+        match axis.to_ascii_uppercase() {
+            'X' => self.x,
+            'Y' => self.y,
+            'Z' => self.z,
+            'E' => self.e,
+            _ => None,
+        }
+    }
+
+    fn axis_mut(&mut self, axis: char) -> Option<&mut Option<f64>> {
+        match axis.to_ascii_uppercase() {
+            'X' => Some(&mut self.x),
+            'Y' => Some(&mut self.y),
+            'Z' => Some(&mut self.z),
+            'E' => Some(&mut self.e),
+            _ => None,
+        }
+    }
+}
+
+/// Axes that have been homed (or given a reference with G92)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HomedAxes {
+    pub x: bool,
+    pub y: bool,
+    pub z: bool,
+}
+
+impl HomedAxes {
+    /// Check an axis by letter; non-homing axes are always considered homed
+    pub fn axis(&self, axis: char) -> bool {
+        match axis.to_ascii_uppercase() {
+            'X' => self.x,
+            'Y' => self.y,
+            'Z' => self.z,
+            _ => true,
+        }
+    }
+
+    fn set(&mut self, axis: char) {
+        match axis.to_ascii_uppercase() {
+            'X' => self.x = true,
+            'Y' => self.y = true,
+            'Z' => self.z = true,
+            _ => {}
+        }
+    }
+}
+
+/// Heater target temperature in Celsius
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Heater {
+    pub target: f64,
+    /// Whether a blocking wait (M109/M190) was issued for this target
+    pub waited: bool,
+}
+
+impl Heater {
+    /// Set a new target, keeping the wait only if the heater is already hot enough
+    fn set_target(current: Option<Heater>, target: f64, wait: bool) -> Heater {
+        let already_reached = current.is_some_and(|h| h.waited && target <= h.target);
+        Heater {
+            target,
+            waited: wait || already_reached,
+        }
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.target > 0.0
+    }
+}
+
+/// Machine state after executing a sequence of commands
+#[derive(Debug, Clone, PartialEq)]
+pub struct MachineState {
+    pub position: Position,
+    pub positioning: PositioningMode,
+    pub extruder_positioning: PositioningMode,
+    pub units: Units,
+    pub plane: Plane,
+    /// Feedrate of moves in millimeters per minute, `None` until set
+    pub feedrate: Option<f64>,
+    pub homed: HomedAxes,
+    /// Active tool (extruder) index
+    pub tool: u32,
+    /// Hotend heaters by tool index
+    pub hotends: BTreeMap<u32, Heater>,
+    pub bed: Option<Heater>,
+    /// Part cooling fan speed from 0 to 1, `None` until set
+    pub fan: Option<f64>,
+}
+
+impl Default for MachineState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MachineState {
+    /// Power-on state: absolute millimeters in the XY plane, position unknown
+    pub fn new() -> Self {
+        Self {
+            position: Position::default(),
+            positioning: PositioningMode::Absolute,
+            extruder_positioning: PositioningMode::Absolute,
+            units: Units::Millimeters,
+            plane: Plane::XY,
+            feedrate: None,
+            homed: HomedAxes::default(),
+            tool: 0,
+            hotends: BTreeMap::new(),
+            bed: None,
+            fan: None,
+        }
+    }
+
+    /// Heater of the active tool, if a temperature was ever set
+    pub fn hotend(&self) -> Option<Heater> {
+        self.hotends.get(&self.tool).copied()
+    }
+
+    /// Update the state with the effect of a command
+    pub fn apply(&mut self, cmd: &Command) {
+        match cmd.name.to_uppercase().as_str() {
+            "G0" | "G1" | "G2" | "G3" => self.apply_move(cmd),
+            "G28" => self.apply_home(cmd),
+            "G92" => self.apply_set_position(cmd),
+            "G90" => {
+                self.positioning = PositioningMode::Absolute;
+                self.extruder_positioning = PositioningMode::Absolute;
+            }
+            "G91" => {
+                self.positioning = PositioningMode::Relative;
+                self.extruder_positioning = PositioningMode::Relative;
+            }
+            "M82" => self.extruder_positioning = PositioningMode::Absolute,
+            "M83" => self.extruder_positioning = PositioningMode::Relative,
+            "G20" => self.units = Units::Inches,
+            "G21" => self.units = Units::Millimeters,
+            "G17" => self.plane = Plane::XY,
+            "G18" => self.plane = Plane::XZ,
+            "G19" => self.plane = Plane::YZ,
+            "M104" | "M109" => self.apply_hotend_temperature(cmd),
+            "M106" => {
+                let speed = cmd
+                    .parameters
+                    .iter()
+                    .find(|p| p.letter.eq_ignore_ascii_case(&'S'))
+                    .and_then(|p| p.value.parse::<f64>().ok())
+                    .unwrap_or(255.0);
+                self.fan = Some((speed / 255.0).clamp(0.0, 1.0));
+            }
+            "M107" => self.fan = Some(0.0),
+            "M140" | "M190" => {
+                if let Some(target) = temperature_target(cmd) {
+                    let wait = cmd.name.eq_ignore_ascii_case("M190");
+                    self.bed = Some(Heater::set_target(self.bed, target, wait));
+                }
+            }
+            name => {
+                if let Some(tool) = name.strip_prefix('T').and_then(|n| n.parse().ok()) {
+                    self.tool = tool;
+                }
+            }
+        }
+    }
+
+    /// Apply a Klipper heater command, read from the raw line
+    ///
+    /// The lexer only splits G/M/T codes, so `SET_HEATER_TEMPERATURE`,
+    /// `TEMPERATURE_WAIT` and `TURN_OFF_HEATERS` are recognised here.
+    /// Returns whether the line was one of them.
+    pub fn apply_extended_command(&mut self, line: &str) -> bool {
+        let code = line.split(';').next().unwrap_or("");
+        let mut words = code.split_whitespace();
+        let Some(name) = words.next() else {
+            return false;
+        };
+        let value = |key: &str| {
+            code.split_whitespace().skip(1).find_map(|word| {
+                let (k, v) = word.split_once('=')?;
+                k.eq_ignore_ascii_case(key).then_some(v)
+            })
+        };
+
+        match name.to_uppercase().as_str() {
+            "SET_HEATER_TEMPERATURE" => {
+                let target = value("TARGET")
+                    .and_then(|target| target.parse().ok())
+                    .unwrap_or(0.0);
+                match value("HEATER").and_then(KlipperHeater::parse) {
+                    Some(KlipperHeater::Hotend(tool)) => {
+                        let current = self.hotends.get(&tool).copied();
+                        self.hotends
+                            .insert(tool, Heater::set_target(current, target, false));
+                    }
+                    Some(KlipperHeater::Bed) => {
+                        self.bed = Some(Heater::set_target(self.bed, target, false));
+                    }
+                    None => {}
+                }
+            }
+            // Waiting on a sensor is M109/M190 for the target already set
+            "TEMPERATURE_WAIT" if value("MINIMUM").is_some() => {
+                let heater = match value("SENSOR").and_then(KlipperHeater::parse) {
+                    Some(KlipperHeater::Hotend(tool)) => self.hotends.get_mut(&tool),
+                    Some(KlipperHeater::Bed) => self.bed.as_mut(),
+                    None => None,
+                };
+                if let Some(heater) = heater {
+                    heater.waited = true;
+                }
+            }
+            "TEMPERATURE_WAIT" => {}
+            "TURN_OFF_HEATERS" => {
+                for heater in self.hotends.values_mut() {
+                    *heater = Heater::set_target(Some(*heater), 0.0, false);
+                }
+                if let Some(bed) = &mut self.bed {
+                    *bed = Heater::set_target(Some(*bed), 0.0, false);
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Modal commands currently in effect, one per modal group
+    pub fn active_modes(&self) -> [&'static str; 4] {
+        [
+            match self.plane {
+                Plane::XY => "G17",
+                Plane::XZ => "G18",
+                Plane::YZ => "G19",
+            },
+            match self.positioning {
+                PositioningMode::Absolute => "G90",
+                PositioningMode::Relative => "G91",
+            },
+            match self.units {
+                Units::Millimeters => "G21",
+                Units::Inches => "G20",
+            },
+            match self.extruder_positioning {
+                PositioningMode::Absolute => "M82",
+                PositioningMode::Relative => "M83",
+            },
+        ]
+    }
+
+    /// Convert a coordinate from the active units to millimeters
+    pub fn to_millimeters(&self, value: f64) -> f64 {
+        match self.units {
+            Units::Millimeters => value,
+            Units::Inches => value * 25.4,
+        }
+    }
+
+    fn apply_move(&mut self, cmd: &Command) {
+        for param in &cmd.parameters {
+            let axis = param.letter.to_ascii_uppercase();
+            let Ok(value) = param.value.parse::<f64>() else {
+                continue;
+            };
+            let mode = if axis == 'E' {
+                self.extruder_positioning
+            } else {
+                self.positioning
+            };
+            let value = self.to_millimeters(value);
+
+            if axis == 'F' {
+                self.feedrate = Some(value);
+            } else if let Some(slot) = self.position.axis_mut(axis) {
+                *slot = match mode {
+                    PositioningMode::Absolute => Some(value),
+                    PositioningMode::Relative => slot.map(|current| current + value),
+                };
+            }
+        }
+    }
+
+    /// Homing moves the given axes (all of X, Y and Z by default) to zero
+    fn apply_home(&mut self, cmd: &Command) {
+        let axes: Vec<char> = cmd
+            .parameters
+            .iter()
+            .map(|p| p.letter.to_ascii_uppercase())
+            .filter(|axis| matches!(axis, 'X' | 'Y' | 'Z'))
+            .collect();
+
+        let axes = if axes.is_empty() {
+            vec!['X', 'Y', 'Z']
+        } else {
+            axes
+        };
+
+        for axis in axes {
+            if let Some(slot) = self.position.axis_mut(axis) {
+                *slot = Some(0.0);
+            }
+            self.homed.set(axis);
+        }
+    }
+
+    fn apply_hotend_temperature(&mut self, cmd: &Command) {
+        let Some(target) = temperature_target(cmd) else {
+            return;
+        };
+
+        let tool = cmd
+            .parameters
+            .iter()
+            .find(|p| p.letter.eq_ignore_ascii_case(&'T'))
+            .and_then(|p| p.value.parse().ok())
+            .unwrap_or(self.tool);
+        let wait = cmd.name.eq_ignore_ascii_case("M109");
+
+        let current = self.hotends.get(&tool).copied();
+        self.hotends
+            .insert(tool, Heater::set_target(current, target, wait));
+    }
+
+    /// G92 sets the given axes (all axes by default) without moving
+    fn apply_set_position(&mut self, cmd: &Command) {
+        if cmd.parameters.is_empty() {
+            self.position = Position {
+                x: Some(0.0),
+                y: Some(0.0),
+                z: Some(0.0),
+                e: Some(0.0),
+            };
+            self.homed = HomedAxes {
+                x: true,
+                y: true,
+                z: true,
+            };
+            return;
+        }
+
+        for param in &cmd.parameters {
+            let Ok(value) = param.value.parse::<f64>() else {
+                continue;
+            };
+            let value = self.to_millimeters(value);
+            if let Some(slot) = self.position.axis_mut(param.letter) {
+                *slot = Some(value);
+            }
+            self.homed.set(param.letter);
+        }
+    }
+}
+
+/// A heater named in Klipper's configuration
+enum KlipperHeater {
+    Hotend(u32),
+    Bed,
+}
+
+impl KlipperHeater {
+    /// `extruder` is tool 0, `extruder1` tool 1, `heater_bed` the bed
+    fn parse(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase();
+        if name == "heater_bed" {
+            return Some(Self::Bed);
+        }
+        match name.strip_prefix("extruder")? {
+            "" => Some(Self::Hotend(0)),
+            index => index.parse().ok().map(Self::Hotend),
+        }
+    }
+}
+
+/// Target temperature of a heater command (`S`, or `R` for wait-to-cool)
+fn temperature_target(cmd: &Command) -> Option<f64> {
+    ['S', 'R'].into_iter().find_map(|letter| {
+        cmd.parameters
+            .iter()
+            .find(|p| p.letter.eq_ignore_ascii_case(&letter))
+            .and_then(|p| p.value.parse().ok())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{ParsedLine, parse_line};
+
+    fn run(lines: &[&str]) -> MachineState {
+        let mut state = MachineState::new();
+        for line in lines {
+            if state.apply_extended_command(line) {
+                continue;
+            }
+            if let ParsedLine::Command(cmd) = parse_line(line) {
+                state.apply(&cmd);
+            }
+        }
+        state
+    }
+
+    #[test]
+    fn test_klipper_heaters() {
+        let state = run(&[
+            "SET_HEATER_TEMPERATURE HEATER=heater_bed TARGET=60",
+            "SET_HEATER_TEMPERATURE HEATER=extruder1 TARGET=215",
+            "T1",
+            "TEMPERATURE_WAIT SENSOR=extruder1 MINIMUM=210",
+        ]);
+        assert_eq!(
+            state.hotend(),
+            Some(Heater {
+                target: 215.0,
+                waited: true
+            })
+        );
+        assert!(!state.bed.unwrap().waited);
+
+        let state = run(&["M140 S60", "M104 S200", "TURN_OFF_HEATERS"]);
+        assert!(!state.hotend().unwrap().is_on());
+        assert!(!state.bed.unwrap().is_on());
+    }
+
+    #[test]
+    fn test_position_unknown_until_homed() {
+        let state = run(&["G1 X10"]);
+        assert_eq!(state.position.x, Some(10.0));
+        assert_eq!(state.position.y, None);
+
+        let state = run(&["G91", "G1 X10"]);
+        assert_eq!(state.position.x, None);
+
+        let state = run(&["G28", "G91", "G1 X10 Y-5"]);
+        assert_eq!(state.position.x, Some(10.0));
+        assert_eq!(state.position.y, Some(-5.0));
+        assert_eq!(state.position.z, Some(0.0));
+    }
+
+    #[test]
+    fn test_home_selected_axes() {
+        let state = run(&["G28 Z", "G1 X5"]);
+        assert_eq!(state.position.z, Some(0.0));
+        assert_eq!(state.position.x, Some(5.0));
+        assert_eq!(state.position.y, None);
+    }
+
+    #[test]
+    fn test_extruder_modes() {
+        let state = run(&["G92 E0", "M83", "G1 E2", "G1 E3"]);
+        assert_eq!(state.position.e, Some(5.0));
+        assert_eq!(state.positioning, PositioningMode::Absolute);
+
+        let state = run(&["G92 E0", "G1 E2", "G1 E3"]);
+        assert_eq!(state.position.e, Some(3.0));
+    }
+
+    #[test]
+    fn test_inches_converted() {
+        let state = run(&["G20", "G1 X1 F10"]);
+        assert_eq!(state.position.x, Some(25.4));
+        assert_eq!(state.feedrate, Some(254.0));
+        assert_eq!(state.active_modes(), ["G17", "G90", "G20", "M82"]);
+    }
+
+    #[test]
+    fn test_heaters_and_tools() {
+        let state = run(&["M104 S215", "M140 S60", "M190 S60", "T1", "M109 S240"]);
+        assert_eq!(
+            state.hotends.get(&0),
+            Some(&Heater {
+                target: 215.0,
+                waited: false
+            })
+        );
+        assert_eq!(state.tool, 1);
+        assert_eq!(
+            state.hotend(),
+            Some(Heater {
+                target: 240.0,
+                waited: true
+            })
+        );
+        assert!(state.bed.unwrap().waited);
+
+        // Lowering a reached target keeps the wait, raising it does not
+        let state = run(&["M109 S215", "M104 S200"]);
+        assert!(state.hotend().unwrap().waited);
+        let state = run(&["M109 S215", "M104 S230"]);
+        assert!(!state.hotend().unwrap().waited);
+    }
+
+    #[test]
+    fn test_fan_speed() {
+        assert_eq!(run(&["M106 S127.5"]).fan, Some(0.5));
+        assert_eq!(run(&["M106"]).fan, Some(1.0));
+        assert_eq!(run(&["M106 S255", "M107"]).fan, Some(0.0));
+        assert_eq!(run(&["G28"]).fan, None);
+    }
+
+    #[test]
+    fn test_homed_axes() {
+        let state = run(&["G28 X Y"]);
+        assert!(state.homed.x && state.homed.y && !state.homed.z);
+
+        let state = run(&["G92 Z0"]);
+        assert!(state.homed.z && !state.homed.x);
+    }
+}
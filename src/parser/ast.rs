@@ -2,118 +2,175 @@
 //!
 //! Clean, minimal types representing parsed GCode structure.
 //! No validation logic or LSP concerns - pure data representation.
+//!
+//! Text fields borrow from the parsed line; [`ParsedLine::into_owned`]
+//! detaches a line from its source for callers that keep it around.
+
+use std::borrow::Cow;
+use std::ops::Range;
 
 use crate::parser::lexer::{Token, TokenKind};
 
 /// A parsed line of GCode
 #[derive(Debug, Clone, PartialEq)]
-pub enum ParsedLine {
+pub enum ParsedLine<'a> {
     /// A GCode command with parameters and optional comment
-    Command(Command),
-    /// A comment-only line  
-    Comment(Comment),
+    Command(Command<'a>),
+    /// A comment-only line
+    Comment(Comment<'a>),
     /// An empty or whitespace-only line
     Empty,
 }
 
 /// A GCode command like "G1" or "M104"
 #[derive(Debug, Clone, PartialEq)]
-pub struct Command {
+pub struct Command<'a> {
     /// Command name (e.g., "G1", "M104")
-    pub name: String,
+    pub name: Cow<'a, str>,
+    /// Byte range of the command name in the line
+    pub span: Range<usize>,
     /// Command parameters (e.g., X10, Y20)
-    pub parameters: Vec<Parameter>,
+    pub parameters: Vec<Parameter<'a>>,
     /// Optional trailing comment
-    pub comment: Option<Comment>,
+    pub comment: Option<Comment<'a>>,
 }
 
 /// A command parameter like "X10" or "S255"
 #[derive(Debug, Clone, PartialEq)]
-pub struct Parameter {
+pub struct Parameter<'a> {
     /// Parameter letter (e.g., 'X', 'Y', 'S')
     pub letter: char,
     /// Parameter value as string (parsing to numbers happens in validation)
-    pub value: String,
+    pub value: Cow<'a, str>,
+    /// Byte range of the whole parameter, letter included, in the line
+    pub span: Range<usize>,
 }
 
 /// A comment (semicolon or parenthetical)
 #[derive(Debug, Clone, PartialEq)]
-pub struct Comment {
+pub struct Comment<'a> {
     /// Comment text (without the delimiters)
-    pub text: String,
+    pub text: Cow<'a, str>,
+    /// Byte range of the comment, delimiters included, in the line
+    pub span: Range<usize>,
+}
+
+impl ParsedLine<'_> {
+    /// Copy borrowed text so the line outlives its source
+    pub fn into_owned(self) -> ParsedLine<'static> {
+        match self {
+            ParsedLine::Command(command) => ParsedLine::Command(command.into_owned()),
+            ParsedLine::Comment(comment) => ParsedLine::Comment(comment.into_owned()),
+            ParsedLine::Empty => ParsedLine::Empty,
+        }
+    }
+}
+
+impl Command<'_> {
+    /// Copy borrowed text so the command outlives its source
+    pub fn into_owned(self) -> Command<'static> {
+        Command {
+            name: Cow::Owned(self.name.into_owned()),
+            span: self.span,
+            parameters: self
+                .parameters
+                .into_iter()
+                .map(Parameter::into_owned)
+                .collect(),
+            comment: self.comment.map(Comment::into_owned),
+        }
+    }
+}
+
+impl Parameter<'_> {
+    /// Copy borrowed text so the parameter outlives its source
+    pub fn into_owned(self) -> Parameter<'static> {
+        Parameter {
+            letter: self.letter,
+            value: Cow::Owned(self.value.into_owned()),
+            span: self.span,
+        }
+    }
+}
+
+impl Comment<'_> {
+    /// Copy borrowed text so the comment outlives its source
+    pub fn into_owned(self) -> Comment<'static> {
+        Comment {
+            text: Cow::Owned(self.text.into_owned()),
+            span: self.span,
+        }
+    }
 }
 
 /// Convert tokens into a parsed line
 ///
-/// This is where the simple parsing logic lives - much cleaner than
-/// the current mixed tokenization/parsing/validation approach.
-pub fn tokens_to_parsed_line(tokens: Vec<Token>) -> ParsedLine {
-    if tokens.is_empty() {
-        return ParsedLine::Empty;
+/// The first command token names the command, every parameter token belongs
+/// to it and the first comment token is its trailing comment. Lines without
+/// a command are comment-only or empty.
+pub fn tokens_to_parsed_line<'a>(tokens: impl IntoIterator<Item = Token<'a>>) -> ParsedLine<'a> {
+    let mut command: Option<Token<'a>> = None;
+    let mut parameters = Vec::new();
+    let mut comment = None;
+
+    for token in tokens {
+        match token.kind {
+            TokenKind::Command if command.is_none() => command = Some(token),
+            // A tool after the command is a parameter, e.g. the `T1` of `M104 T1`
+            TokenKind::Command if token.text.starts_with(['T', 't']) => {
+                parameters.extend(parse_parameter_token(token))
+            }
+            TokenKind::Command => {}
+            TokenKind::Parameter => parameters.extend(parse_parameter_token(token)),
+            TokenKind::Comment if comment.is_none() => comment = Some(comment_from_token(token)),
+            TokenKind::Comment => {}
+        }
     }
 
-    // Find command token
-    let command_token = tokens.iter().find(|t| t.kind == TokenKind::Command);
-
-    if let Some(cmd_token) = command_token {
-        // Extract parameters
-        let parameters: Vec<Parameter> = tokens
-            .iter()
-            .filter(|t| t.kind == TokenKind::Parameter)
-            .filter_map(|t| parse_parameter_token(&t.text))
-            .collect();
-
-        // Extract comment
-        let comment = tokens
-            .iter()
-            .find(|t| t.kind == TokenKind::Comment)
-            .map(|t| Comment {
-                text: extract_comment_text(&t.text),
-            });
-
-        ParsedLine::Command(Command {
-            name: cmd_token.text.clone(),
+    match (command, comment) {
+        (Some(command), comment) => ParsedLine::Command(Command {
+            name: Cow::Borrowed(command.text),
+            span: command.span,
             parameters,
             comment,
-        })
-    } else {
-        // Check if it's a comment-only line
-        if let Some(comment_token) = tokens.iter().find(|t| t.kind == TokenKind::Comment) {
-            ParsedLine::Comment(Comment {
-                text: extract_comment_text(&comment_token.text),
-            })
-        } else {
-            ParsedLine::Empty
-        }
+        }),
+        (None, Some(comment)) => ParsedLine::Comment(comment),
+        (None, None) => ParsedLine::Empty,
     }
 }
 
 /// Parse a parameter token like "X10.5" into a Parameter
-fn parse_parameter_token(text: &str) -> Option<Parameter> {
-    if text.len() < 2 {
-        return None;
-    }
-
-    let mut chars = text.chars();
-    let letter = chars.next()?;
+///
+/// Bare letters like the "X" in "G28 X" are flags with an empty value.
+fn parse_parameter_token(token: Token<'_>) -> Option<Parameter<'_>> {
+    let letter = token.text.chars().next()?;
 
     if !letter.is_ascii_alphabetic() {
         return None;
     }
 
-    let value = chars.collect::<String>();
+    Some(Parameter {
+        letter,
+        value: Cow::Borrowed(&token.text[1..]),
+        span: token.span,
+    })
+}
 
-    Some(Parameter { letter, value })
+fn comment_from_token(token: Token<'_>) -> Comment<'_> {
+    Comment {
+        text: Cow::Borrowed(extract_comment_text(token.text)),
+        span: token.span,
+    }
 }
 
 /// Extract comment text, removing delimiters
-fn extract_comment_text(text: &str) -> String {
+fn extract_comment_text(text: &str) -> &str {
     if let Some(stripped) = text.strip_prefix(';') {
-        stripped.to_string()
-    } else if text.starts_with('(') && text.ends_with(')') {
-        text[1..text.len() - 1].to_string()
+        stripped
+    } else if text.len() >= 2 && text.starts_with('(') && text.ends_with(')') {
+        &text[1..text.len() - 1]
     } else {
-        text.to_string()
+        text
     }
 }
 
@@ -122,11 +179,23 @@ mod tests {
     use super::*;
     use crate::parser::lexer::{Token, TokenKind};
 
+    fn parameter(text: &str) -> Token<'_> {
+        Token {
+            kind: TokenKind::Parameter,
+            text,
+            span: 0..text.len(),
+        }
+    }
+
     #[test]
     fn test_parse_parameter_token() {
-        let param = parse_parameter_token("X10.5").unwrap();
+        let param = parse_parameter_token(parameter("X10.5")).unwrap();
         assert_eq!(param.letter, 'X');
         assert_eq!(param.value, "10.5");
+
+        let flag = parse_parameter_token(parameter("X")).unwrap();
+        assert_eq!(flag.letter, 'X');
+        assert_eq!(flag.value, "");
     }
 
     #[test]
@@ -146,15 +215,18 @@ mod tests {
         let tokens = vec![
             Token {
                 kind: TokenKind::Command,
-                text: "G1".to_string(),
+                text: "G1",
+                span: 0..2,
             },
             Token {
                 kind: TokenKind::Parameter,
-                text: "X10".to_string(),
+                text: "X10",
+                span: 3..6,
             },
             Token {
                 kind: TokenKind::Parameter,
-                text: "Y20".to_string(),
+                text: "Y20",
+                span: 7..10,
             },
         ];
 
@@ -162,11 +234,31 @@ mod tests {
 
         if let ParsedLine::Command(cmd) = result {
             assert_eq!(cmd.name, "G1");
+            assert_eq!(cmd.span, 0..2);
             assert_eq!(cmd.parameters.len(), 2);
             assert_eq!(cmd.parameters[0].letter, 'X');
             assert_eq!(cmd.parameters[0].value, "10");
+            assert_eq!(cmd.parameters[1].span, 7..10);
         } else {
             panic!("Expected command");
         }
     }
+
+    #[test]
+    fn test_into_owned() {
+        let line = String::from("M104 S215 ; heat");
+        let owned = {
+            let tokens = crate::parser::lexer::tokenize_line(&line);
+            tokens_to_parsed_line(tokens).into_owned()
+        };
+        drop(line);
+
+        let ParsedLine::Command(cmd) = owned else {
+            panic!("Expected command");
+        };
+        assert!(matches!(cmd.name, Cow::Owned(_)));
+        assert_eq!(cmd.name, "M104");
+        assert_eq!(cmd.parameters[0].value, "215");
+        assert_eq!(cmd.comment.unwrap().span, 10..16);
+    }
 }
@@ -0,0 +1,151 @@
+//! Parsed Documents
+//!
+//! Per-line parse results of a document, kept in sync with its text by
+//! re-parsing only the lines an edit touched.
+
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::parser::{ParsedLine, parse_line};
+
+/// The parsed lines of a document, indexed like `str::lines`
+///
+/// Lines are shared, so cloning a document to hand it to a background task
+/// copies pointers rather than parse results.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParsedDocument {
+    lines: Vec<Arc<ParsedLine<'static>>>,
+}
+
+impl ParsedDocument {
+    /// Parse every line of `content`
+    pub fn parse(content: &str) -> Self {
+        Self {
+            lines: content.lines().map(parse_owned).collect(),
+        }
+    }
+
+    /// Replace the parsed lines in `old` with the parses of `new`
+    ///
+    /// Used for ranged edits, where the caller knows which lines an edit
+    /// touched. `old` is clamped to the document. Returns the range of lines
+    /// that were parsed again.
+    pub fn splice<'a>(
+        &mut self,
+        old: Range<usize>,
+        new: impl IntoIterator<Item = &'a str>,
+    ) -> Range<usize> {
+        let start = old.start.min(self.lines.len());
+        let end = old.end.clamp(start, self.lines.len());
+        let parsed: Vec<_> = new.into_iter().map(parse_owned).collect();
+        let changed = start..start + parsed.len();
+        self.lines.splice(start..end, parsed);
+        changed
+    }
+
+    /// Bring the document from `old` to `new`, re-parsing only changed lines
+    ///
+    /// `old` must be the text the document was last parsed from. Lines shared
+    /// at the start and end of both texts are kept. Returns the range of lines
+    /// in `new` that were parsed again. Compares every line, so edits with a
+    /// known range go through [`Self::splice`] instead.
+    pub fn update(&mut self, old: &str, new: &str) -> Range<usize> {
+        let old_lines: Vec<&str> = old.lines().collect();
+        let new_lines: Vec<&str> = new.lines().collect();
+        debug_assert_eq!(self.lines.len(), old_lines.len());
+
+        let prefix = old_lines
+            .iter()
+            .zip(&new_lines)
+            .take_while(|(old, new)| old == new)
+            .count();
+        let suffix = old_lines[prefix..]
+            .iter()
+            .rev()
+            .zip(new_lines[prefix..].iter().rev())
+            .take_while(|(old, new)| old == new)
+            .count();
+
+        let changed = prefix..new_lines.len() - suffix;
+        self.lines.splice(
+            prefix..old_lines.len() - suffix,
+            new_lines[changed.clone()]
+                .iter()
+                .map(|line| parse_owned(line)),
+        );
+        changed
+    }
+
+    /// Number of lines
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// The parsed line at a zero-based index
+    pub fn get(&self, idx: usize) -> Option<&ParsedLine<'static>> {
+        self.lines.get(idx).map(|line| &**line)
+    }
+
+    /// Iterate over the parsed lines in order
+    pub fn iter(&self) -> impl Iterator<Item = &ParsedLine<'static>> {
+        self.lines.iter().map(|line| &**line)
+    }
+}
+
+fn parse_owned(line: &str) -> Arc<ParsedLine<'static>> {
+    Arc::new(parse_line(line).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_reparses_changed_lines() {
+        let old = "G28\nG1 X10\nM104 S200\nG1 Y20\n";
+        let mut document = ParsedDocument::parse(old);
+        let untouched = Arc::clone(&document.lines[3]);
+
+        let new = "G28\nG1 X15\nM104 S200\nG1 Y20\n";
+        assert_eq!(document.update(old, new), 1..2);
+        assert_eq!(document, ParsedDocument::parse(new));
+        assert!(Arc::ptr_eq(&untouched, &document.lines[3]));
+
+        let newer = "G28\nG1 X15\n; inserted\n; lines\nM104 S200\nG1 Y20\n";
+        assert_eq!(document.update(new, newer), 2..4);
+        assert_eq!(document, ParsedDocument::parse(newer));
+
+        assert_eq!(document.update(newer, "G28\n"), 1..1);
+        assert_eq!(document, ParsedDocument::parse("G28\n"));
+        assert_eq!(document.len(), 1);
+    }
+
+    #[test]
+    fn test_splice() {
+        let mut document = ParsedDocument::parse("G28\nG1 X10\nM104 S200\n");
+        let untouched = Arc::clone(&document.lines[2]);
+
+        assert_eq!(document.splice(1..2, ["G1 X15", "G1 Y20"]), 1..3);
+        assert_eq!(
+            document,
+            ParsedDocument::parse("G28\nG1 X15\nG1 Y20\nM104 S200\n")
+        );
+        assert!(Arc::ptr_eq(&untouched, &document.lines[3]));
+
+        assert_eq!(document.splice(3..9, []), 3..3);
+        assert_eq!(document.len(), 3);
+    }
+
+    #[test]
+    fn test_update_repeated_lines() {
+        let old = "G1 X1\nG1 X1\nG1 X1";
+        let mut document = ParsedDocument::parse(old);
+
+        assert_eq!(document.update(old, "G1 X1\nG1 X1"), 2..2);
+        assert_eq!(document.len(), 2);
+    }
+}
@@ -1,110 +1,151 @@
 //! GCode Lexer
 //!
 //! Fast, simple tokenization of GCode lines.
-//! Focus: extract tokens quickly with minimal allocations.
+//! Tokens borrow their text from the line, so lexing never allocates.
+
+use std::ops::Range;
 
 /// Token types in GCode
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum TokenKind {
     /// Command like "G1", "M104"
     Command,
-    /// Parameter like "X10", "S255"  
+    /// Parameter like "X10", "S255"
     Parameter,
     /// Comment (semicolon or parenthetical)
     Comment,
 }
 
-/// A token with its text content
+/// A token borrowing its text from the line
 #[derive(Debug, Clone, PartialEq)]
-pub struct Token {
+pub struct Token<'a> {
     pub kind: TokenKind,
-    pub text: String,
+    pub text: &'a str,
+    /// Byte range of the token in the line
+    pub span: Range<usize>,
 }
 
-/// Tokenize a line of GCode into tokens
+/// Iterator over the tokens of a line
 ///
-/// This is much simpler than the current approach - no position tracking,
-/// no streaming, just fast extraction of tokens from a line.
-pub fn tokenize_line(line: &str) -> Vec<Token> {
-    let mut tokens = Vec::new();
-    let mut chars = line.char_indices().peekable();
-
-    while let Some((start_idx, ch)) = chars.next() {
-        match ch {
-            // Skip whitespace
-            ' ' | '\t' | '\r' | '\n' => continue,
-
-            // Semicolon comment: consume rest of line
-            ';' => {
-                let text = line[start_idx..].to_string();
-                tokens.push(Token {
-                    kind: TokenKind::Comment,
-                    text,
-                });
-                break; // Rest of line is comment
-            }
+/// Works on bytes: every token starts at an ASCII character, so slicing at
+/// token boundaries is always valid UTF-8.
+#[derive(Debug, Clone)]
+pub struct Lexer<'a> {
+    line: &'a str,
+    pos: usize,
+    done: bool,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(line: &'a str) -> Self {
+        Self {
+            line,
+            pos: 0,
+            done: false,
+        }
+    }
+
+    fn token(&self, kind: TokenKind, span: Range<usize>) -> Token<'a> {
+        Token {
+            kind,
+            text: &self.line[span.clone()],
+            span,
+        }
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Token<'a>;
 
-            // Parenthetical comment
-            '(' => {
-                let mut end_idx = start_idx + 1;
-                let mut found_close = false;
-
-                for (idx, ch) in chars.by_ref() {
-                    if ch == ')' {
-                        end_idx = idx + 1;
-                        found_close = true;
-                        break;
-                    }
-                    end_idx = idx + 1;
+    fn next(&mut self) -> Option<Token<'a>> {
+        if self.done {
+            return None;
+        }
+        let bytes = self.line.as_bytes();
+
+        while self.pos < bytes.len() {
+            let start = self.pos;
+            match bytes[start] {
+                // Semicolon comment: consume rest of line
+                b';' => {
+                    self.pos = bytes.len();
+                    self.done = true;
+                    return Some(self.token(TokenKind::Comment, start..bytes.len()));
                 }
 
-                if !found_close {
-                    end_idx = line.len();
+                // Parenthetical comment, unterminated ones run to the end
+                b'(' => {
+                    let end = bytes[start..]
+                        .iter()
+                        .position(|&b| b == b')')
+                        .map_or(bytes.len(), |offset| start + offset + 1);
+                    self.pos = end;
+                    return Some(self.token(TokenKind::Comment, start..end));
                 }
 
-                let text = line[start_idx..end_idx].to_string();
-                tokens.push(Token {
-                    kind: TokenKind::Comment,
-                    text,
-                });
-            }
+                // Letter starts command or parameter
+                b if b.is_ascii_alphabetic() => {
+                    // Consume alphanumeric, dots, minus, plus
+                    let end = bytes[start + 1..]
+                        .iter()
+                        .position(|&b| {
+                            !(b.is_ascii_alphanumeric() || matches!(b, b'.' | b'-' | b'+'))
+                        })
+                        .map_or(bytes.len(), |offset| start + 1 + offset);
+                    self.pos = end;
 
-            // Letter starts command or parameter
-            c if c.is_ascii_alphabetic() => {
-                let mut end_idx = start_idx + 1;
-
-                // Consume alphanumeric, dots, minus, plus
-                while let Some(&(idx, next_ch)) = chars.peek() {
-                    if next_ch.is_ascii_alphanumeric()
-                        || next_ch == '.'
-                        || next_ch == '-'
-                        || next_ch == '+'
-                    {
-                        end_idx = idx + 1;
-                        chars.next();
+                    // Simple heuristic: Commands start with G, M, T
+                    let kind = if is_command(&self.line[start..end]) {
+                        TokenKind::Command
                     } else {
-                        break;
-                    }
+                        TokenKind::Parameter
+                    };
+                    return Some(self.token(kind, start..end));
                 }
 
-                let text = line[start_idx..end_idx].to_string();
-
-                // Simple heuristic: Commands start with G, M, T
-                let kind = if is_command(&text) {
-                    TokenKind::Command
-                } else {
-                    TokenKind::Parameter
-                };
-
-                tokens.push(Token { kind, text });
+                // Skip whitespace and other characters (malformed input)
+                _ => self.pos += 1,
             }
-
-            // Skip other characters (malformed input)
-            _ => continue,
         }
+
+        self.done = true;
+        None
     }
+}
 
-    tokens
+/// Tokenize a line of GCode into tokens
+///
+/// Collects a [`Lexer`]; iterate the lexer directly to avoid the vector.
+pub fn tokenize_line(line: &str) -> Vec<Token<'_>> {
+    Lexer::new(line).collect()
+}
+
+/// Find the byte range of the first parameter with the given letter
+///
+/// Used to point diagnostics at a parameter rather than the whole line.
+pub fn parameter_span(line: &str, letter: char) -> Option<Range<usize>> {
+    Lexer::new(line)
+        .find(|token| {
+            token.kind == TokenKind::Parameter
+                && token
+                    .text
+                    .chars()
+                    .next()
+                    .is_some_and(|c| c.eq_ignore_ascii_case(&letter))
+        })
+        .map(|token| token.span)
+}
+
+/// Find the byte range of a Klipper extended command starting the line
+///
+/// The lexer splits words at `_`, so names such as `SYNC_EXTRUDER_MOTION`
+/// are read from the raw line: the first word, if it contains a `_`.
+pub fn extended_command_span(line: &str) -> Option<Range<usize>> {
+    let start = line.len() - line.trim_start().len();
+    let end = line[start..]
+        .find(|c: char| c.is_whitespace() || c == ';')
+        .map_or(line.len(), |offset| start + offset);
+    line[start..end].contains('_').then_some(start..end)
 }
 
 /// Determine if a token is a command
@@ -152,6 +193,9 @@ mod tests {
         assert_eq!(tokens.len(), 3);
         assert_eq!(tokens[1].kind, TokenKind::Comment);
         assert_eq!(tokens[1].text, "(rapid move)");
+
+        let tokens = tokenize_line("G1 (unterminated");
+        assert_eq!(tokens[1].text, "(unterminated");
     }
 
     #[test]
@@ -178,6 +222,37 @@ mod tests {
         assert!(!is_command("S255"));
     }
 
+    #[test]
+    fn test_token_spans() {
+        let line = "  G2 X10 I-5 ; arc";
+        let tokens = tokenize_line(line);
+
+        let spans: Vec<Range<usize>> = tokens.iter().map(|t| t.span.clone()).collect();
+        assert_eq!(spans, vec![2..4, 5..8, 9..12, 13..18]);
+        assert!(tokens.iter().all(|t| &line[t.span.clone()] == t.text));
+        assert_eq!(parameter_span(line, 'i'), Some(9..12));
+        assert_eq!(parameter_span(line, 'J'), None);
+    }
+
+    #[test]
+    fn test_extended_command_span() {
+        assert_eq!(
+            extended_command_span("  SYNC_EXTRUDER_MOTION EXTRUDER=belted"),
+            Some(2..22)
+        );
+        assert_eq!(extended_command_span("TURN_OFF_HEATERS;done"), Some(0..16));
+        assert_eq!(extended_command_span("G1 X10"), None);
+        assert_eq!(extended_command_span("; SET_FAN_SPEED"), None);
+    }
+
+    #[test]
+    fn test_non_ascii_input() {
+        let tokens = tokenize_line("G1 é X10 ; température");
+
+        let texts: Vec<&str> = tokens.iter().map(|t| t.text).collect();
+        assert_eq!(texts, vec!["G1", "X10", "; température"]);
+    }
+
     #[test]
     fn test_float_parameters() {
         let tokens = tokenize_line("G1 X10.5 Y-2.3 Z+1.0");
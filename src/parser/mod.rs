@@ -1,21 +1,25 @@
 //! GCode Parser
 //!
-//! Clean, fast parsing of GCode with minimal allocations.
+//! Clean, fast parsing of GCode without copying the source text.
 //! Focused solely on tokenization and AST construction.
 
 pub mod ast;
+pub mod document;
 pub mod lexer;
+pub mod thumbnail;
 
 pub use ast::{Command, Comment, Parameter, ParsedLine};
-pub use lexer::{Token, TokenKind, tokenize_line};
+pub use document::ParsedDocument;
+pub use lexer::{Lexer, Token, TokenKind, extended_command_span, parameter_span, tokenize_line};
+pub use thumbnail::{ThumbnailBlock, ThumbnailFormat, thumbnail_blocks};
 
 /// Parse a single line of GCode into structured data
 ///
 /// This is the main entry point for parsing. It tokenizes the line
-/// and constructs a simple AST representation.
-pub fn parse_line(line: &str) -> ParsedLine {
-    let tokens = lexer::tokenize_line(line);
-    ast::tokens_to_parsed_line(tokens)
+/// and constructs a simple AST representation borrowing from `line`; only
+/// the parameter list is allocated.
+pub fn parse_line(line: &str) -> ParsedLine<'_> {
+    ast::tokens_to_parsed_line(Lexer::new(line))
 }
 
 #[cfg(test)]
@@ -36,6 +40,21 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_tool_parameter() {
+        let result = parse_line("M104 S200 T1");
+
+        if let ParsedLine::Command(cmd) = result {
+            assert_eq!(cmd.name, "M104");
+            assert_eq!(cmd.parameters.len(), 2);
+            assert_eq!(cmd.parameters[1].letter, 'T');
+            assert_eq!(cmd.parameters[1].value, "1");
+            assert_eq!(cmd.parameters[1].span, 10..12);
+        } else {
+            panic!("Expected command");
+        }
+    }
+
     #[test]
     fn test_parse_with_comment() {
         let result = parse_line("G1 X10 ; move to X10");
@@ -45,7 +64,8 @@ mod tests {
             assert_eq!(
                 cmd.comment,
                 Some(Comment {
-                    text: " move to X10".to_string()
+                    text: " move to X10".into(),
+                    span: 7..20,
                 })
             );
         } else {
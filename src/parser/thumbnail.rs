@@ -0,0 +1,226 @@
+//! Thumbnail Blocks
+//!
+//! Slicers embed preview images as base64 text in comment blocks:
+//!
+//! ```gcode
+//! ; thumbnail begin 16x16 460
+//! ; iVBORw0KGgoAAAANSUhEUgAAABAAAAAQCAYAAAAf8/9hAAAAAXNSR0IArs4c6QAAAA...
+//! ; thumbnail end
+//! ```
+//!
+//! The header gives the image size and the number of base64 characters.
+//! PrusaSlicer and OrcaSlicer also write `thumbnail_QOI` and `thumbnail_JPG`
+//! blocks.
+
+use std::ops::RangeInclusive;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+
+/// Image format of a thumbnail
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThumbnailFormat {
+    Png,
+    Qoi,
+    Jpg,
+}
+
+impl ThumbnailFormat {
+    fn from_keyword(keyword: &str) -> Option<Self> {
+        match keyword.to_ascii_lowercase().as_str() {
+            "thumbnail" | "thumbnail_png" => Some(Self::Png),
+            "thumbnail_qoi" => Some(Self::Qoi),
+            "thumbnail_jpg" | "thumbnail_jpeg" => Some(Self::Jpg),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Png => "PNG",
+            Self::Qoi => "QOI",
+            Self::Jpg => "JPG",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Qoi => "qoi",
+            Self::Jpg => "jpg",
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Qoi => "image/qoi",
+            Self::Jpg => "image/jpeg",
+        }
+    }
+}
+
+/// A thumbnail comment block
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThumbnailBlock {
+    pub format: ThumbnailFormat,
+    /// The keyword opening and closing the block, e.g. `thumbnail_QOI`
+    pub keyword: String,
+    /// Declared width and height in pixels
+    pub width: u32,
+    pub height: u32,
+    /// Declared number of base64 characters
+    pub length: usize,
+    /// Line of the `begin` comment
+    pub begin: usize,
+    /// Line of the `end` comment, or the last data line if the block is
+    /// not closed
+    pub end: usize,
+    pub closed: bool,
+    /// The base64 text, without comment markers
+    pub data: String,
+}
+
+impl ThumbnailBlock {
+    /// Lines of the block, from `begin` to `end`
+    pub fn lines(&self) -> RangeInclusive<usize> {
+        self.begin..=self.end
+    }
+
+    pub fn decode(&self) -> Result<Vec<u8>, base64::DecodeError> {
+        STANDARD.decode(&self.data)
+    }
+
+    /// Short description, e.g. `16x16 PNG`
+    pub fn label(&self) -> String {
+        format!("{}x{} {}", self.width, self.height, self.format.name())
+    }
+}
+
+/// Find the thumbnail blocks of a document's lines
+pub fn thumbnail_blocks<'a>(lines: impl IntoIterator<Item = &'a str>) -> Vec<ThumbnailBlock> {
+    let mut blocks = Vec::new();
+    let mut open: Option<ThumbnailBlock> = None;
+
+    for (idx, line) in lines.into_iter().enumerate() {
+        let Some(text) = line.trim_start().strip_prefix(';').map(str::trim) else {
+            // Code ends an unclosed block
+            blocks.extend(open.take());
+            continue;
+        };
+
+        if let Some(block) = &mut open {
+            let mut words = text.split_whitespace();
+            if words.next() == Some(block.keyword.as_str()) && words.next() == Some("end") {
+                block.end = idx;
+                block.closed = true;
+                blocks.extend(open.take());
+            } else if text.starts_with("thumbnail") && text.contains(" begin") {
+                blocks.extend(open.take());
+            } else {
+                block.end = idx;
+                block.data.push_str(text);
+                continue;
+            }
+        }
+
+        if let Some(block) = parse_header(idx, text) {
+            open = Some(block);
+        }
+    }
+
+    blocks.extend(open);
+    blocks
+}
+
+/// Parse `thumbnail begin <width>x<height> <length>`
+fn parse_header(idx: usize, text: &str) -> Option<ThumbnailBlock> {
+    let mut words = text.split_whitespace();
+    let keyword = words.next()?;
+    let format = ThumbnailFormat::from_keyword(keyword)?;
+    if words.next()? != "begin" {
+        return None;
+    }
+    let (width, height) = words.next()?.split_once('x')?;
+    Some(ThumbnailBlock {
+        format,
+        keyword: keyword.to_string(),
+        width: width.parse().ok()?,
+        height: height.parse().ok()?,
+        length: words.next()?.parse().ok()?,
+        begin: idx,
+        end: idx,
+        closed: false,
+        data: String::new(),
+    })
+}
+
+/// Format and size of an image, read from its header
+pub fn image_header(bytes: &[u8]) -> Option<(ThumbnailFormat, u32, u32)> {
+    let be32 = |at: usize| Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?));
+    let be16 = |at: usize| Some(u16::from_be_bytes(bytes.get(at..at + 2)?.try_into().ok()?));
+
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        // The IHDR chunk comes first
+        return Some((ThumbnailFormat::Png, be32(16)?, be32(20)?));
+    }
+    if bytes.starts_with(b"qoif") {
+        return Some((ThumbnailFormat::Qoi, be32(4)?, be32(8)?));
+    }
+    if bytes.starts_with(&[0xFF, 0xD8]) {
+        // Walk the segments up to the start of frame
+        let mut at = 2;
+        while *bytes.get(at)? == 0xFF {
+            let marker = *bytes.get(at + 1)?;
+            if matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+                let height = be16(at + 5)?;
+                let width = be16(at + 7)?;
+                return Some((ThumbnailFormat::Jpg, u32::from(width), u32::from(height)));
+            }
+            at += 2 + usize::from(be16(at + 2)?);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_thumbnail_blocks() {
+        let content = "; generated by PrusaSlicer\n;\n; thumbnail begin 2x1 8\n; iVBO\n; Rw0K\n\
+                       ; thumbnail end\n;\n; thumbnail_QOI begin 4x4 4\n; cW9p\nG28\n";
+        let blocks = thumbnail_blocks(content.lines());
+
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].format, ThumbnailFormat::Png);
+        assert_eq!((blocks[0].width, blocks[0].height), (2, 1));
+        assert_eq!(blocks[0].lines(), 2..=5);
+        assert!(blocks[0].closed);
+        assert_eq!(blocks[0].data, "iVBORw0K");
+
+        assert_eq!(blocks[1].format, ThumbnailFormat::Qoi);
+        assert_eq!(blocks[1].lines(), 7..=8);
+        assert!(!blocks[1].closed);
+        assert_eq!(blocks[1].decode().unwrap(), b"qoi");
+    }
+
+    #[test]
+    fn test_image_header() {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend([0, 0, 0, 16, 0, 0, 0, 8]);
+        assert_eq!(image_header(&png), Some((ThumbnailFormat::Png, 16, 8)));
+
+        let qoi = [b'q', b'o', b'i', b'f', 0, 0, 1, 0, 0, 0, 0, 32];
+        assert_eq!(image_header(&qoi), Some((ThumbnailFormat::Qoi, 256, 32)));
+
+        // SOI, an APP0 segment of 4 bytes, then SOF0 for 300x200
+        let jpg = [
+            0xFF, 0xD8, 0xFF, 0xE0, 0, 4, 0, 0, 0xFF, 0xC0, 0, 17, 8, 0, 200, 1, 44,
+        ];
+        assert_eq!(image_header(&jpg), Some((ThumbnailFormat::Jpg, 300, 200)));
+
+        assert_eq!(image_header(b"GIF89a"), None);
+    }
+}
@@ -0,0 +1,269 @@
+//! Arc Geometry Checks
+//!
+//! Validates G2/G3 arcs against the position before the move. Center-form
+//! arcs (I/J/K offsets) must have the same radius at both ends, and
+//! radius-form arcs (R) must have an endpoint the radius can reach.
+
+use std::ops::Range;
+
+use crate::machine::MachineState;
+use crate::machine::state::Plane;
+use crate::parser::{Command, parameter_span};
+use crate::validation::engine::ValidationResult;
+use crate::validation::rules::Rule;
+
+/// Absolute tolerance on arc radii, in millimeters
+pub const ARC_RADIUS_TOLERANCE: f64 = 0.05;
+
+/// Relative tolerance on arc radii, as a fraction of the radius
+pub const ARC_RADIUS_RELATIVE_TOLERANCE: f64 = 0.001;
+
+/// Axis and center offset letters of the arc plane
+struct PlaneAxes {
+    axes: [char; 2],
+    offsets: [char; 2],
+}
+
+impl PlaneAxes {
+    fn of(plane: Plane) -> Self {
+        match plane {
+            Plane::XY => Self {
+                axes: ['X', 'Y'],
+                offsets: ['I', 'J'],
+            },
+            Plane::XZ => Self {
+                axes: ['X', 'Z'],
+                offsets: ['I', 'K'],
+            },
+            Plane::YZ => Self {
+                axes: ['Y', 'Z'],
+                offsets: ['J', 'K'],
+            },
+        }
+    }
+}
+
+/// Check the geometry of an arc move
+///
+/// `before` and `after` are the machine states around the command. Arcs
+/// starting from an unknown position are not checked.
+pub fn check_arc(
+    line_num: usize,
+    line: &str,
+    cmd: &Command,
+    before: &MachineState,
+    after: &MachineState,
+    result: &mut ValidationResult,
+) {
+    let plane = PlaneAxes::of(before.plane);
+    let point = |state: &MachineState| -> Option<(f64, f64)> {
+        Some((
+            state.position.axis(plane.axes[0])?,
+            state.position.axis(plane.axes[1])?,
+        ))
+    };
+    let (Some(start), Some(end)) = (point(before), point(after)) else {
+        return;
+    };
+
+    let param_value = |letter: char| {
+        cmd.parameters
+            .iter()
+            .find(|p| p.letter.eq_ignore_ascii_case(&letter))
+            .and_then(|p| p.value.parse::<f64>().ok())
+            .map(|value| before.to_millimeters(value))
+    };
+    let spans = |letters: &[char]| -> Option<Range<usize>> {
+        let ranges: Vec<Range<usize>> = letters
+            .iter()
+            .filter_map(|letter| parameter_span(line, *letter))
+            .collect();
+        let start = ranges.iter().map(|r| r.start).min()?;
+        let end = ranges.iter().map(|r| r.end).max()?;
+        Some(start..end)
+    };
+
+    let offsets = [param_value(plane.offsets[0]), param_value(plane.offsets[1])];
+    let has_offsets = offsets.iter().any(Option::is_some);
+    let radius = param_value('R');
+
+    if has_offsets && radius.is_some() {
+        result.add_error_at(
+            line_num,
+            spans(&['R']),
+            Rule::ArcGeometry,
+            format!(
+                "Arc has both R and {}/{}, use one form only",
+                plane.offsets[0], plane.offsets[1]
+            ),
+        );
+        return;
+    }
+
+    let chord = distance(start, end);
+
+    if has_offsets {
+        let center = (
+            start.0 + offsets[0].unwrap_or(0.0),
+            start.1 + offsets[1].unwrap_or(0.0),
+        );
+        let start_radius = distance(start, center);
+        let end_radius = distance(end, center);
+
+        if start_radius == 0.0 {
+            result.add_error_at(
+                line_num,
+                spans(&plane.offsets),
+                Rule::ArcGeometry,
+                format!(
+                    "Arc center is at the start point, {}/{} must not both be zero",
+                    plane.offsets[0], plane.offsets[1]
+                ),
+            );
+        } else if (start_radius - end_radius).abs() > tolerance(start_radius) {
+            let mut letters = plane.offsets.to_vec();
+            letters.extend(plane.axes);
+            result.add_error_at(
+                line_num,
+                spans(&letters),
+                Rule::ArcGeometry,
+                format!(
+                    "Arc radius differs at start ({:.3}mm) and end ({:.3}mm), the endpoint is not on the circle",
+                    start_radius, end_radius
+                ),
+            );
+        }
+        // A center-form arc ending where it starts is a full circle
+    } else if let Some(radius) = radius {
+        if radius == 0.0 {
+            result.add_error_at(
+                line_num,
+                spans(&['R']),
+                Rule::ArcGeometry,
+                "Arc radius R must not be zero".to_string(),
+            );
+        } else if chord == 0.0 {
+            result.add_error_at(
+                line_num,
+                spans(&['R']),
+                Rule::ArcGeometry,
+                format!(
+                    "R-form arc ending at its start point is undefined, use {}/{} for a full circle",
+                    plane.offsets[0], plane.offsets[1]
+                ),
+            );
+        } else if chord > 2.0 * radius.abs() + tolerance(radius.abs()) {
+            result.add_error_at(
+                line_num,
+                spans(&['R']),
+                Rule::ArcGeometry,
+                format!(
+                    "Arc endpoint is {:.3}mm away, too far for radius R{} (at most {:.3}mm)",
+                    chord,
+                    radius,
+                    2.0 * radius.abs()
+                ),
+            );
+        }
+    }
+}
+
+/// Allowed radius difference for an arc of the given radius
+fn tolerance(radius: f64) -> f64 {
+    ARC_RADIUS_TOLERANCE.max(radius * ARC_RADIUS_RELATIVE_TOLERANCE)
+}
+
+fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    (a.0 - b.0).hypot(a.1 - b.1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{ParsedLine, parse_line};
+
+    /// Check `line` as an arc starting from X0 Y0
+    fn check(line: &str) -> ValidationResult {
+        let mut before = MachineState::new();
+        before.apply(&command("G92 X0 Y0 Z0"));
+        let cmd = command(line);
+        let mut after = before.clone();
+        after.apply(&cmd);
+
+        let mut result = ValidationResult::new();
+        check_arc(1, line, &cmd, &before, &after, &mut result);
+        result
+    }
+
+    fn command(line: &str) -> Command<'_> {
+        match parse_line(line) {
+            ParsedLine::Command(cmd) => cmd,
+            _ => panic!("expected a command"),
+        }
+    }
+
+    #[test]
+    fn test_valid_arcs() {
+        assert!(check("G2 X10 Y0 I5 J0").diagnostics.is_empty());
+        assert!(check("G3 X10 Y0 R5").diagnostics.is_empty());
+        assert!(check("G3 X10 Y0 R-5.01").diagnostics.is_empty());
+        // Full circle in center form
+        assert!(check("G2 I5 J5").diagnostics.is_empty());
+        // Within tolerance
+        assert!(check("G2 X10.02 Y0 I5 J0").diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_radius_mismatch() {
+        let line = "G2 X10 Y3 I5 J0";
+        let result = check(line);
+
+        assert_eq!(result.diagnostics.len(), 1);
+        let diagnostic = &result.diagnostics[0];
+        assert_eq!(diagnostic.rule, Rule::ArcGeometry);
+        assert!(diagnostic.message.contains("5.000mm"));
+        assert_eq!(diagnostic.span, Some(3..line.len()));
+    }
+
+    #[test]
+    fn test_unreachable_endpoint() {
+        let line = "G2 X20 Y0 R5 F1200";
+        let result = check(line);
+
+        assert_eq!(result.diagnostics.len(), 1);
+        assert!(result.diagnostics[0].message.contains("too far"));
+        assert_eq!(result.diagnostics[0].span, Some(10..12));
+    }
+
+    #[test]
+    fn test_degenerate_arcs() {
+        assert!(
+            check("G2 X0 Y0 R5").diagnostics[0]
+                .message
+                .contains("undefined")
+        );
+        assert!(
+            check("G2 X10 I0 J0").diagnostics[0]
+                .message
+                .contains("center")
+        );
+        assert!(check("G2 X10 R0").diagnostics[0].message.contains("zero"));
+        assert!(
+            check("G2 X10 I5 R5").diagnostics[0]
+                .message
+                .contains("both")
+        );
+    }
+
+    #[test]
+    fn test_unknown_start_is_skipped() {
+        let cmd = command("G2 X20 Y0 R5");
+        let before = MachineState::new();
+        let mut after = before.clone();
+        after.apply(&cmd);
+
+        let mut result = ValidationResult::new();
+        check_arc(1, "G2 X20 Y0 R5", &cmd, &before, &after, &mut result);
+        assert!(result.diagnostics.is_empty());
+    }
+}
@@ -2,8 +2,18 @@
 //!
 //! Core validation logic separated from parsing and LSP concerns.
 
+use std::ops::Range;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
 use crate::flavor::FlavorRegistry;
-use crate::parser::{Command, ParsedLine};
+use crate::flavor::schema::{CommandDef, ConstraintContext};
+use crate::machine::{MachineProfile, MachineState};
+use crate::parser::{Command, ParsedDocument, ParsedLine, extended_command_span, parse_line};
+use crate::validation::arcs::check_arc;
+use crate::validation::rules::{Rule, SeverityOverrides, Suppressions};
+use crate::validation::safety::{DEFAULT_MIN_EXTRUDE_TEMPERATURE, SafetyChecker};
+use crate::validation::thumbnails::check_thumbnails;
 
 /// Severity of a diagnostic message
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -13,12 +23,22 @@ pub enum Severity {
     Info,
 }
 
+/// Extra metadata clients use to render a diagnostic
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tag {
+    Deprecated,
+}
+
 /// A diagnostic message for a validation issue
 #[derive(Debug, Clone, PartialEq)]
 pub struct Diagnostic {
     pub line: usize,
     pub message: String,
     pub severity: Severity,
+    pub tags: Vec<Tag>,
+    pub rule: Rule,
+    /// Byte range within the line, or `None` for the whole line
+    pub span: Option<Range<usize>>,
 }
 
 /// Result of validating a document or line
@@ -40,19 +60,47 @@ impl ValidationResult {
         }
     }
 
-    pub fn add_error(&mut self, line: usize, message: String) {
+    pub fn add_error(&mut self, line: usize, rule: Rule, message: String) {
+        self.add_error_at(line, None, rule, message);
+    }
+
+    pub fn add_warning(&mut self, line: usize, rule: Rule, message: String) {
+        self.diagnostics.push(Diagnostic {
+            line,
+            message,
+            severity: Severity::Warning,
+            tags: Vec::new(),
+            rule,
+            span: None,
+        });
+    }
+
+    /// Add an error pointing at part of the line
+    pub fn add_error_at(
+        &mut self,
+        line: usize,
+        span: Option<Range<usize>>,
+        rule: Rule,
+        message: String,
+    ) {
         self.diagnostics.push(Diagnostic {
             line,
             message,
             severity: Severity::Error,
+            tags: Vec::new(),
+            rule,
+            span,
         });
     }
 
-    pub fn add_warning(&mut self, line: usize, message: String) {
+    pub fn add_deprecation(&mut self, line: usize, message: String) {
         self.diagnostics.push(Diagnostic {
             line,
             message,
             severity: Severity::Warning,
+            tags: vec![Tag::Deprecated],
+            rule: Rule::DeprecatedCommand,
+            span: None,
         });
     }
 
@@ -65,16 +113,28 @@ impl ValidationResult {
 }
 
 /// Validate a single line of GCode
+///
+/// The line is validated as if it appeared at the start of a document.
 pub fn validate_line(
     line_num: usize,
     parsed: &ParsedLine,
     flavor: &FlavorRegistry,
+) -> ValidationResult {
+    validate_line_in_context(line_num, parsed, flavor, &initial_context())
+}
+
+/// Validate a single line of GCode against the given document state
+pub fn validate_line_in_context(
+    line_num: usize,
+    parsed: &ParsedLine,
+    flavor: &FlavorRegistry,
+    context: &ConstraintContext,
 ) -> ValidationResult {
     let mut result = ValidationResult::new();
 
     match parsed {
         ParsedLine::Command(cmd) => {
-            validate_command(line_num, cmd, flavor, &mut result);
+            validate_command(line_num, cmd, flavor, context, &mut result);
         }
         ParsedLine::Comment(_) | ParsedLine::Empty => {
             // Comments and empty lines are always valid
@@ -84,16 +144,406 @@ pub fn validate_line(
     result
 }
 
+/// Smallest number of lines worth handing to a separate thread
+pub const MIN_CHUNK_LINES: usize = 1024;
+
+/// Lines processed between two cancellation checks
+const CANCEL_CHECK_INTERVAL: usize = 1024;
+
+/// A validation run's place in a sequence of runs
+///
+/// Starting a new generation on a shared counter makes every earlier one
+/// stale, and stale runs stop early.
+#[derive(Debug, Clone)]
+pub struct Generation {
+    counter: Arc<AtomicU64>,
+    value: u64,
+}
+
+impl Generation {
+    /// Start a new generation, making all earlier ones stale
+    pub fn next(counter: &Arc<AtomicU64>) -> Self {
+        let value = counter.fetch_add(1, Ordering::SeqCst) + 1;
+        Self {
+            counter: Arc::clone(counter),
+            value,
+        }
+    }
+
+    /// Check whether no newer generation has been started
+    pub fn is_current(&self) -> bool {
+        self.counter.load(Ordering::SeqCst) == self.value
+    }
+}
+
+/// Options for validating a whole document
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ValidationOptions<'a> {
+    /// Machine profile to check limits against
+    pub machine: Option<&'a MachineProfile>,
+    /// Per-rule severity overrides
+    pub severities: Option<&'a SeverityOverrides>,
+    /// Generation of this run; validation stops early once it is stale
+    pub generation: Option<&'a Generation>,
+}
+
+impl ValidationOptions<'_> {
+    fn is_cancelled(&self) -> bool {
+        self.generation
+            .is_some_and(|generation| !generation.is_current())
+    }
+}
+
 /// Validate an entire document
 pub fn validate_document(content: &str, flavor: &FlavorRegistry) -> ValidationResult {
+    validate_document_with(content, flavor, &ValidationOptions::default())
+}
+
+/// Validate an entire document with the given options
+///
+/// Lines are parsed and checked against the flavor in parallel chunks. The
+/// machine state is tracked in a single sequential pass in between, which
+/// also records the modal context each chunk needs.
+///
+/// Suppression comments in the document are honoured, then severity
+/// overrides are applied to the remaining diagnostics. A cancelled run
+/// returns an incomplete result that should be discarded.
+pub fn validate_document_with(
+    content: &str,
+    flavor: &FlavorRegistry,
+    options: &ValidationOptions,
+) -> ValidationResult {
+    let lines: Vec<&str> = content.lines().collect();
+
+    let parsed: Vec<ParsedLine> = map_chunks(lines.len(), |range| {
+        let mut parsed = Vec::with_capacity(range.len());
+        for (i, line) in lines[range].iter().enumerate() {
+            if i % CANCEL_CHECK_INTERVAL == 0 && options.is_cancelled() {
+                break;
+            }
+            parsed.push(parse_line(line));
+        }
+        parsed
+    });
+    if options.is_cancelled() {
+        return ValidationResult::new();
+    }
+
+    let parsed: Vec<&ParsedLine> = parsed.iter().collect();
+    validate_lines(&lines, &parsed, flavor, options)
+}
+
+/// Validate a document whose lines have already been parsed
+///
+/// `parsed` must be the parse of `content`, as kept by open documents.
+pub fn validate_parsed_with(
+    content: &str,
+    parsed: &ParsedDocument,
+    flavor: &FlavorRegistry,
+    options: &ValidationOptions,
+) -> ValidationResult {
+    let lines: Vec<&str> = content.lines().collect();
+    let parsed: Vec<&ParsedLine> = parsed.iter().collect();
+    validate_lines(&lines, &parsed, flavor, options)
+}
+
+fn validate_lines(
+    lines: &[&str],
+    parsed: &[&ParsedLine],
+    flavor: &FlavorRegistry,
+    options: &ValidationOptions,
+) -> ValidationResult {
+    let (mut result, contexts, suppressions) = track_machine_state(lines, parsed, options);
+    if options.is_cancelled() {
+        return ValidationResult::new();
+    }
+    check_thumbnails(lines, &mut result);
+
+    // Check each line against the flavor, in the context in effect before it
+    let flavor_diagnostics = map_chunks(parsed.len(), |range| {
+        let mut context_idx = contexts.partition_point(|(start, _)| *start <= range.start) - 1;
+        let mut diagnostics = Vec::new();
+        for idx in range {
+            if idx % CANCEL_CHECK_INTERVAL == 0 && options.is_cancelled() {
+                break;
+            }
+            while contexts
+                .get(context_idx + 1)
+                .is_some_and(|(start, _)| *start <= idx)
+            {
+                context_idx += 1;
+            }
+            let context = &contexts[context_idx].1;
+            diagnostics.extend(
+                validate_line_in_context(idx + 1, parsed[idx], flavor, context).diagnostics,
+            );
+            diagnostics.extend(validate_extended_command(idx + 1, lines[idx], flavor).diagnostics);
+        }
+        diagnostics
+    });
+
+    // Flavor diagnostics come first on each line, as in a sequential pass
+    let mut diagnostics = flavor_diagnostics;
+    diagnostics.append(&mut result.diagnostics);
+    diagnostics.sort_by_key(|d| d.line);
+    result.diagnostics = diagnostics;
+
+    suppressions.apply(&mut result.diagnostics);
+    if let Some(severities) = options.severities {
+        severities.apply(&mut result.diagnostics);
+    }
+
+    result
+}
+
+/// Walk the document in order, applying each command to the machine state
+///
+/// Returns the safety, arc and machine limit diagnostics, the constraint
+/// context starting at each line where the modes change, and the
+/// suppression comments.
+fn track_machine_state(
+    lines: &[&str],
+    parsed: &[&ParsedLine],
+    options: &ValidationOptions,
+) -> (
+    ValidationResult,
+    Vec<(usize, ConstraintContext)>,
+    Suppressions,
+) {
+    let machine = options.machine;
     let mut result = ValidationResult::new();
+    let mut suppressions = Suppressions::new();
+    let mut state = MachineState::new();
+    let mut context = initial_context();
+    if let Some(profile) = machine {
+        context.settings = profile.settings();
+    }
+    let mut safety = SafetyChecker::new(
+        machine
+            .and_then(|profile| profile.min_extrude_temperature())
+            .unwrap_or(DEFAULT_MIN_EXTRUDE_TEMPERATURE),
+    );
+    let mut contexts = vec![(0, context.clone())];
+
+    for (idx, parsed) in parsed.iter().enumerate() {
+        if idx % CANCEL_CHECK_INTERVAL == 0 && options.is_cancelled() {
+            break;
+        }
+
+        let line_num = idx + 1;
+        match parsed {
+            ParsedLine::Comment(comment) => suppressions.scan_comment(line_num, &comment.text),
+            ParsedLine::Command(Command {
+                comment: Some(comment),
+                ..
+            }) => suppressions.scan_comment(line_num, &comment.text),
+            _ => {}
+        }
+
+        if state.apply_extended_command(lines[idx]) {
+            safety.check_heater_command(line_num);
+            continue;
+        }
+        if let ParsedLine::Command(cmd) = parsed {
+            safety.check_command(line_num, cmd, &state, &mut result);
+
+            let modes = state.active_modes();
+            let before_arc = is_arc(cmd).then(|| state.clone());
+            state.apply(cmd);
+            if let Some(before) = &before_arc {
+                check_arc(line_num, lines[idx], cmd, before, &state, &mut result);
+            }
+            if state.active_modes() != modes {
+                context.modes = state.active_modes().map(String::from).into();
+                contexts.push((idx + 1, context.clone()));
+            }
+
+            if let Some(profile) = machine {
+                check_machine_limits(line_num, cmd, &state, profile, &mut result);
+            }
+        }
+    }
+
+    safety.finish(&state, &mut result);
+
+    (result, contexts, suppressions)
+}
+
+/// Split `0..len` into one chunk per available thread and concatenate the results
+fn map_chunks<T, F>(len: usize, f: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(Range<usize>) -> Vec<T> + Sync,
+{
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_len = len.div_ceil(threads).max(MIN_CHUNK_LINES);
+    if len <= chunk_len {
+        return f(0..len);
+    }
+
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..len)
+            .step_by(chunk_len)
+            .map(|start| {
+                let f = &f;
+                scope.spawn(move || f(start..(start + chunk_len).min(len)))
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("validation thread panicked"))
+            .collect()
+    })
+}
+
+/// Check whether a command is a G2/G3 arc move
+fn is_arc(cmd: &Command) -> bool {
+    cmd.name.eq_ignore_ascii_case("G2") || cmd.name.eq_ignore_ascii_case("G3")
+}
+
+/// Constraint context in effect at the start of a document
+pub fn initial_context() -> ConstraintContext {
+    ConstraintContext {
+        modes: MachineState::new().active_modes().map(String::from).into(),
+        ..Default::default()
+    }
+}
+
+/// Check a command against the physical limits of the machine
+///
+/// `state` is the machine state after the command has been applied.
+fn check_machine_limits(
+    line_num: usize,
+    cmd: &Command,
+    state: &MachineState,
+    profile: &MachineProfile,
+    result: &mut ValidationResult,
+) {
+    let name = cmd.name.to_uppercase();
+    let param_value = |letter: char| {
+        cmd.parameters
+            .iter()
+            .find(|p| p.letter.eq_ignore_ascii_case(&letter))
+            .and_then(|p| p.value.parse::<f64>().ok())
+    };
+
+    match name.as_str() {
+        "G0" | "G1" | "G2" | "G3" => {
+            for axis in ['X', 'Y', 'Z'] {
+                if param_value(axis).is_none() {
+                    continue;
+                }
+                if let (Some(position), Some(range)) =
+                    (state.position.axis(axis), profile.axis_range(axis))
+                    && (position < range.min || position > range.max)
+                {
+                    result.add_warning(
+                        line_num,
+                        Rule::OutsideBuildVolume,
+                        format!(
+                            "Move to {} {} is outside the build volume of '{}' ({} to {})",
+                            axis,
+                            position,
+                            profile.name(),
+                            range.min,
+                            range.max
+                        ),
+                    );
+                }
+            }
+
+            if let (Some(x), Some(y)) = (state.position.x, state.position.y)
+                && (param_value('X').is_some() || param_value('Y').is_some())
+                && !profile.contains_xy(x, y)
+            {
+                result.add_warning(
+                    line_num,
+                    Rule::OutsideBuildVolume,
+                    format!(
+                        "Move to X {} Y {} is outside the bed of '{}'",
+                        x,
+                        y,
+                        profile.name()
+                    ),
+                );
+            }
+        }
+        "M104" | "M109" | "M140" | "M190" => {
+            let (kind, limit) = if name == "M104" || name == "M109" {
+                ("Hotend", profile.max_hotend_temperature())
+            } else {
+                ("Bed", profile.max_bed_temperature())
+            };
+
+            let target = param_value('S').or_else(|| param_value('R'));
+            if let (Some(target), Some(limit)) = (target, limit)
+                && target > limit
+            {
+                result.add_error(
+                    line_num,
+                    Rule::TemperatureLimit,
+                    format!(
+                        "{} temperature {}°C exceeds the maximum of {}°C for '{}'",
+                        kind,
+                        target,
+                        limit,
+                        profile.name()
+                    ),
+                );
+            }
+
+            if kind == "Hotend"
+                && let Some(tool) = param_value('T')
+            {
+                check_tool_index(line_num, tool, profile, result);
+            }
+        }
+        _ if name.starts_with('T') => {
+            if let Ok(tool) = name[1..].parse::<f64>() {
+                check_tool_index(line_num, tool, profile, result);
+            }
+        }
+        _ => {}
+    }
+}
 
-    for (line_num, line) in content.lines().enumerate() {
-        let parsed = crate::parser::parse_line(line);
-        let line_result = validate_line(line_num + 1, &parsed, flavor);
-        result.diagnostics.extend(line_result.diagnostics);
+/// Check that a tool index exists on the machine
+fn check_tool_index(
+    line_num: usize,
+    tool: f64,
+    profile: &MachineProfile,
+    result: &mut ValidationResult,
+) {
+    let extruder_count = profile.machine.extruder_count;
+    if tool >= extruder_count as f64 {
+        result.add_error(
+            line_num,
+            Rule::UnknownTool,
+            format!(
+                "Tool T{} does not exist, '{}' has {} extruder(s)",
+                tool,
+                profile.name(),
+                extruder_count
+            ),
+        );
     }
+}
 
+/// Validate a Klipper extended command, which the parser doesn't keep whole
+///
+/// Only deprecation is reported, see [`extended_command_span`].
+fn validate_extended_command(
+    line_num: usize,
+    line: &str,
+    flavor: &FlavorRegistry,
+) -> ValidationResult {
+    let mut result = ValidationResult::new();
+    let command_def =
+        extended_command_span(line).and_then(|span| flavor.get_command(&line[span].to_uppercase()));
+    if let Some(message) = command_def.and_then(CommandDef::deprecation_message) {
+        result.add_deprecation(line_num, message);
+    }
     result
 }
 
@@ -102,23 +552,28 @@ fn validate_command(
     line_num: usize,
     cmd: &Command,
     flavor: &FlavorRegistry,
+    context: &ConstraintContext,
     result: &mut ValidationResult,
 ) {
     // Check if command exists in the active flavor
     if let Some(command_def) = flavor.get_command(&cmd.name) {
         // Command exists, validate parameters and constraints
+        if let Some(message) = command_def.deprecation_message() {
+            result.add_deprecation(line_num, message);
+        }
 
         // Validate parameter constraints (independent of parameter definitions)
-        let cmd_param_names: Vec<String> = cmd
+        let cmd_param_values: Vec<(String, String)> = cmd
             .parameters
             .iter()
-            .map(|p| p.letter.to_string().to_uppercase())
+            .map(|p| (p.letter.to_string().to_uppercase(), p.value.to_string()))
             .collect();
 
-        let constraint_errors = command_def.validate_constraints(&cmd_param_names);
+        let constraint_errors =
+            command_def.validate_constraints_in_context(&cmd_param_values, context);
 
         for error in constraint_errors {
-            result.add_error(line_num, error);
+            result.add_error(line_num, Rule::ConstraintViolation, error);
         }
 
         // Validate individual parameters if they're defined
@@ -133,6 +588,7 @@ fn validate_command(
                     if !found {
                         result.add_error(
                             line_num,
+                            Rule::MissingRequiredParam,
                             format!(
                                 "Missing required parameter '{}' for command '{}'",
                                 expected_param.name, cmd.name
@@ -149,6 +605,7 @@ fn validate_command(
                 if !found {
                     result.add_warning(
                         line_num,
+                        Rule::UnknownParameter,
                         format!(
                             "Unknown parameter '{}' for command '{}'",
                             param_name, cmd.name
@@ -159,24 +616,34 @@ fn validate_command(
         }
     } else {
         // Unknown command
-        result.add_warning(line_num, format!("Unknown command '{}'", cmd.name));
+        result.add_warning(
+            line_num,
+            Rule::UnknownCommand,
+            format!("Unknown command '{}'", cmd.name),
+        );
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    // use crate::parser::{Command, Parameter, Comment};
+
+    fn parse_command(line: &str) -> Command<'_> {
+        match parse_line(line) {
+            ParsedLine::Command(command) => command,
+            other => panic!("Expected command, got {:?}", other),
+        }
+    }
 
     #[test]
     fn test_validation_result() {
         let mut result = ValidationResult::new();
         assert!(result.is_valid());
 
-        result.add_warning(1, "Test warning".to_string());
+        result.add_warning(1, Rule::UnknownCommand, "Test warning".to_string());
         assert!(result.is_valid()); // Warnings don't make it invalid
 
-        result.add_error(2, "Test error".to_string());
+        result.add_error(2, Rule::ConstraintViolation, "Test error".to_string());
         assert!(!result.is_valid()); // Errors make it invalid
     }
 
@@ -190,7 +657,6 @@ mod tests {
     #[test]
     fn test_constraint_validation() {
         use crate::flavor::schema::{CommandDef, ConstraintType, ParameterConstraint};
-        use crate::parser::{Command, Parameter};
 
         // Create a mock flavor registry with constraint-enabled G0 command
         let mut registry = FlavorRegistry::new();
@@ -208,7 +674,12 @@ mod tests {
                     "Movement command requires at least one coordinate parameter (X, Y, or Z)"
                         .to_string(),
                 ),
+                when: None,
+                operator: None,
+                than: None,
             }]),
+            deprecated: false,
+            replaced_by: None,
         };
 
         commands.insert("G0".to_string(), g0_cmd);
@@ -218,37 +689,24 @@ mod tests {
             version: None,
             description: None,
             commands,
+            snippets: Vec::new(),
         };
 
         registry.add_flavor(flavor);
         registry.set_active_flavor("test");
 
         // Test 1: Valid G0 command with X parameter
-        let valid_cmd = Command {
-            name: "G0".to_string(),
-            parameters: vec![Parameter {
-                letter: 'X',
-                value: "10.0".to_string(),
-            }],
-            comment: None,
-        };
+        let valid_cmd = parse_command("G0 X10.0");
 
         let mut result = ValidationResult::new();
-        validate_command(1, &valid_cmd, &registry, &mut result);
+        validate_command(1, &valid_cmd, &registry, &initial_context(), &mut result);
         assert!(result.is_valid(), "G0 with X parameter should be valid");
 
         // Test 2: Invalid G0 command with no coordinates (only F parameter)
-        let invalid_cmd = Command {
-            name: "G0".to_string(),
-            parameters: vec![Parameter {
-                letter: 'F',
-                value: "1000.0".to_string(),
-            }],
-            comment: None,
-        };
+        let invalid_cmd = parse_command("G0 F1000.0");
 
         let mut result = ValidationResult::new();
-        validate_command(1, &invalid_cmd, &registry, &mut result);
+        validate_command(1, &invalid_cmd, &registry, &initial_context(), &mut result);
 
         assert!(
             !result.is_valid(),
@@ -262,26 +720,297 @@ mod tests {
         );
 
         // Test 3: Valid G0 command with multiple coordinates
-        let valid_multi_cmd = Command {
-            name: "G0".to_string(),
-            parameters: vec![
-                Parameter {
-                    letter: 'X',
-                    value: "10.0".to_string(),
-                },
-                Parameter {
-                    letter: 'Y',
-                    value: "20.0".to_string(),
-                },
-            ],
-            comment: None,
-        };
+        let valid_multi_cmd = parse_command("G0 X10.0 Y20.0");
 
         let mut result = ValidationResult::new();
-        validate_command(1, &valid_multi_cmd, &registry, &mut result);
+        validate_command(
+            1,
+            &valid_multi_cmd,
+            &registry,
+            &initial_context(),
+            &mut result,
+        );
         assert!(
             result.is_valid(),
             "G0 with multiple coordinates should be valid"
         );
     }
+
+    #[test]
+    fn test_deprecated_command_is_tagged() {
+        use crate::flavor::schema::CommandDef;
+        use crate::parser::parse_line;
+
+        let mut registry = FlavorRegistry::new();
+        let mut commands = std::collections::HashMap::new();
+        commands.insert(
+            "M999".to_string(),
+            CommandDef {
+                name: "M999".to_string(),
+                description_short: None,
+                description_long: None,
+                parameters: None,
+                constraints: None,
+                deprecated: true,
+                replaced_by: Some("M998".to_string()),
+            },
+        );
+        registry.add_flavor(crate::flavor::schema::Flavor {
+            name: "test".to_string(),
+            version: None,
+            description: None,
+            commands,
+            snippets: Vec::new(),
+        });
+        registry.set_active_flavor("test");
+
+        let result = validate_line(3, &parse_line("M999"), &registry);
+
+        assert!(result.is_valid(), "Deprecation is a warning, not an error");
+        assert_eq!(result.diagnostics.len(), 1);
+        assert_eq!(result.diagnostics[0].line, 3);
+        assert_eq!(result.diagnostics[0].tags, vec![Tag::Deprecated]);
+        assert!(result.diagnostics[0].message.contains("use 'M998' instead"));
+    }
+
+    #[test]
+    fn test_deprecated_klipper_commands() {
+        use crate::flavor::schema::{Flavor, FlavorFile};
+
+        let klipper = include_str!("../../resources/flavors/klipper.gcode-flavor.toml");
+        let file: FlavorFile = toml::from_str(klipper).unwrap();
+        let mut registry = FlavorRegistry::new();
+        registry.add_flavor(Flavor::from(file));
+        registry.set_active_flavor("klipper");
+
+        let content = "SET_EXTRUDER_ROTATION_DISTANCE EXTRUDER=extruder DISTANCE=22.6\n\
+                       set_extruder_step_distance EXTRUDER=extruder DISTANCE=0.0025\n\
+                       SYNC_STEPPER_TO_EXTRUDER STEPPER=belted EXTRUDER=extruder\n";
+        let deprecations: Vec<_> = validate_document(content, &registry)
+            .diagnostics
+            .into_iter()
+            .filter(|d| d.tags == vec![Tag::Deprecated])
+            .collect();
+
+        assert_eq!(deprecations.len(), 2);
+        assert_eq!(deprecations[0].line, 2);
+        assert!(
+            deprecations[0]
+                .message
+                .contains("use 'SET_EXTRUDER_ROTATION_DISTANCE' instead")
+        );
+        assert_eq!(deprecations[1].line, 3);
+        assert!(
+            deprecations[1]
+                .message
+                .contains("use 'SYNC_EXTRUDER_MOTION' instead")
+        );
+    }
+
+    #[test]
+    fn test_modal_state_tracked_across_document() {
+        use crate::flavor::schema::{
+            CommandDef, ConstraintCondition, ConstraintType, Flavor, ParameterConstraint,
+        };
+
+        let mut commands = std::collections::HashMap::new();
+        for name in ["G17", "G18"] {
+            commands.insert(
+                name.to_string(),
+                CommandDef {
+                    name: name.to_string(),
+                    description_short: None,
+                    description_long: None,
+                    parameters: None,
+                    constraints: None,
+                    deprecated: false,
+                    replaced_by: None,
+                },
+            );
+        }
+        commands.insert(
+            "G2".to_string(),
+            CommandDef {
+                name: "G2".to_string(),
+                description_short: None,
+                description_long: None,
+                parameters: None,
+                constraints: Some(vec![ParameterConstraint {
+                    constraint_type: ConstraintType::ForbiddenIf,
+                    parameters: vec!["K".to_string()],
+                    message: Some("K is not used in the XY plane".to_string()),
+                    when: Some(ConstraintCondition {
+                        modal: Some("G17".to_string()),
+                        ..Default::default()
+                    }),
+                    operator: None,
+                    than: None,
+                }]),
+                deprecated: false,
+                replaced_by: None,
+            },
+        );
+
+        let mut registry = FlavorRegistry::new();
+        registry.add_flavor(Flavor {
+            name: "test".to_string(),
+            version: None,
+            description: None,
+            commands,
+            snippets: Vec::new(),
+        });
+        registry.set_active_flavor("test");
+
+        let content = "G2 X10 K5\nG18\nG2 X10 K5\nG17\nG2 X10 K5\n";
+        let result = validate_document(content, &registry);
+
+        let error_lines: Vec<usize> = result
+            .diagnostics
+            .iter()
+            .filter(|d| d.severity == Severity::Error)
+            .map(|d| d.line)
+            .collect();
+        assert_eq!(error_lines, vec![1, 5]);
+    }
+
+    #[test]
+    fn test_machine_limits() {
+        use crate::machine::MachineRegistry;
+
+        let mut flavors = FlavorRegistry::new();
+        flavors.add_embedded_prusa_flavor();
+        flavors.set_active_flavor("prusa");
+
+        let mut machines = MachineRegistry::new();
+        machines.add_embedded_profiles();
+        let profile = machines.get_profile("prusa-mk4").unwrap();
+
+        let content = "\
+G28
+G1 X100 Y100 Z0.2
+G1 X300
+G91
+G1 Z250
+M104 S350
+M140 S60
+T1
+";
+        let options = ValidationOptions {
+            machine: Some(profile),
+            ..Default::default()
+        };
+        let result = validate_document_with(content, &flavors, &options);
+
+        let machine_lines: Vec<(usize, Severity)> = result
+            .diagnostics
+            .iter()
+            .filter(|d| d.message.contains("prusa-mk4"))
+            .map(|d| (d.line, d.severity))
+            .collect();
+        assert_eq!(
+            machine_lines,
+            vec![
+                (3, Severity::Warning),
+                (5, Severity::Warning),
+                (6, Severity::Error),
+                (8, Severity::Error),
+            ]
+        );
+
+        // Without a profile, no machine checks run
+        let result = validate_document(content, &flavors);
+        assert!(
+            !result
+                .diagnostics
+                .iter()
+                .any(|d| d.message.contains("prusa-mk4"))
+        );
+    }
+
+    #[test]
+    fn test_suppressions_and_severity_overrides() {
+        let mut flavors = FlavorRegistry::new();
+        flavors.add_embedded_prusa_flavor();
+        flavors.set_active_flavor("prusa");
+
+        let content = "\
+; gcode-ls: disable=move-before-homing
+G1 X10 W1
+; gcode-ls: disable-next-line=unknown-parameter
+G1 X20 W1
+G999 ; gcode-ls: disable-next-line
+G998
+";
+        let mut severities = SeverityOverrides::new();
+        severities.set(Rule::UnknownCommand, Some(Severity::Error));
+        let options = ValidationOptions {
+            severities: Some(&severities),
+            ..Default::default()
+        };
+        let result = validate_document_with(content, &flavors, &options);
+
+        let diagnostics: Vec<(usize, Rule, Severity)> = result
+            .diagnostics
+            .iter()
+            .map(|d| (d.line, d.rule, d.severity))
+            .collect();
+        assert_eq!(
+            diagnostics,
+            vec![
+                (2, Rule::UnknownParameter, Severity::Warning),
+                (5, Rule::UnknownCommand, Severity::Error),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_chunked_validation_matches_line_order() {
+        let mut flavors = FlavorRegistry::new();
+        flavors.add_embedded_prusa_flavor();
+        flavors.set_active_flavor("prusa");
+
+        // Enough lines for several chunks, with an unknown command in each
+        let mut content = String::from("G28\nM109 S215\n");
+        for i in 0..(MIN_CHUNK_LINES * 4) {
+            if i % 1000 == 0 {
+                content.push_str("G999\n");
+            } else {
+                content.push_str("G1 X10 Y10 E1\n");
+            }
+        }
+        content.push_str("M104 S0\n");
+
+        let result = validate_document(&content, &flavors);
+        let lines: Vec<usize> = result.diagnostics.iter().map(|d| d.line).collect();
+
+        assert_eq!(lines, vec![3, 1003, 2003, 3003, 4003]);
+        assert!(
+            result
+                .diagnostics
+                .iter()
+                .all(|d| d.rule == Rule::UnknownCommand)
+        );
+    }
+
+    #[test]
+    fn test_stale_generation_stops_validation() {
+        let mut flavors = FlavorRegistry::new();
+        flavors.add_embedded_prusa_flavor();
+        flavors.set_active_flavor("prusa");
+
+        let counter = Arc::new(AtomicU64::new(0));
+        let generation = Generation::next(&counter);
+        assert!(generation.is_current());
+
+        let newer = Generation::next(&counter);
+        assert!(!generation.is_current());
+        assert!(newer.is_current());
+
+        let options = ValidationOptions {
+            generation: Some(&generation),
+            ..Default::default()
+        };
+        let result = validate_document_with("G999\n", &flavors, &options);
+        assert!(result.diagnostics.is_empty());
+    }
 }
@@ -2,9 +2,17 @@
 //!
 //! Clean separation of validation logic from parsing and LSP concerns.
 
+pub mod arcs;
 pub mod engine;
+pub mod rules;
+pub mod safety;
+pub mod thumbnails;
 
-pub use engine::{Diagnostic, Severity, validate_document, validate_line};
+pub use engine::{
+    Diagnostic, Severity, Tag, ValidationOptions, initial_context, validate_document,
+    validate_document_with, validate_line, validate_line_in_context, validate_parsed_with,
+};
+pub use rules::{Rule, SeverityOverrides};
 
 // Re-export common types
 pub use engine::ValidationResult;
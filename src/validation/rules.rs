@@ -0,0 +1,338 @@
+//! Validation Rules
+//!
+//! Stable rule identifiers, per-rule severity overrides, and inline
+//! suppression comments:
+//!
+//! ```gcode
+//! ; gcode-ls: disable=cold-extrusion,heater-left-on
+//! ; gcode-ls: disable-next-line=unknown-parameter
+//! G1 X10 W1
+//! ```
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serde::{Deserialize, Deserializer};
+
+use crate::validation::engine::{Diagnostic, Severity};
+
+/// Identifier of the rule that produced a diagnostic
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Rule {
+    UnknownCommand,
+    UnknownParameter,
+    MissingRequiredParam,
+    ConstraintViolation,
+    DeprecatedCommand,
+    OutsideBuildVolume,
+    TemperatureLimit,
+    UnknownTool,
+    ColdExtrusion,
+    MoveBeforeHoming,
+    HeaterLeftOn,
+    ArcGeometry,
+    Thumbnail,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 13] = [
+        Rule::UnknownCommand,
+        Rule::UnknownParameter,
+        Rule::MissingRequiredParam,
+        Rule::ConstraintViolation,
+        Rule::DeprecatedCommand,
+        Rule::OutsideBuildVolume,
+        Rule::TemperatureLimit,
+        Rule::UnknownTool,
+        Rule::ColdExtrusion,
+        Rule::MoveBeforeHoming,
+        Rule::HeaterLeftOn,
+        Rule::ArcGeometry,
+        Rule::Thumbnail,
+    ];
+
+    /// Stable identifier used in diagnostics, config and suppression comments
+    pub fn code(self) -> &'static str {
+        match self {
+            Rule::UnknownCommand => "unknown-command",
+            Rule::UnknownParameter => "unknown-parameter",
+            Rule::MissingRequiredParam => "missing-required-param",
+            Rule::ConstraintViolation => "constraint-violation",
+            Rule::DeprecatedCommand => "deprecated-command",
+            Rule::OutsideBuildVolume => "outside-build-volume",
+            Rule::TemperatureLimit => "temperature-limit",
+            Rule::UnknownTool => "unknown-tool",
+            Rule::ColdExtrusion => "cold-extrusion",
+            Rule::MoveBeforeHoming => "move-before-homing",
+            Rule::HeaterLeftOn => "heater-left-on",
+            Rule::ArcGeometry => "arc-geometry",
+            Rule::Thumbnail => "thumbnail",
+        }
+    }
+
+    /// One-line description of what the rule reports
+    pub fn description(self) -> &'static str {
+        match self {
+            Rule::UnknownCommand => "Command is not defined by the active flavor",
+            Rule::UnknownParameter => "Parameter is not defined for the command",
+            Rule::MissingRequiredParam => "Required parameter is missing",
+            Rule::ConstraintViolation => "Parameters violate a flavor constraint",
+            Rule::DeprecatedCommand => "Command is deprecated",
+            Rule::OutsideBuildVolume => "Move leaves the machine's build volume",
+            Rule::TemperatureLimit => "Temperature exceeds the machine's limits",
+            Rule::UnknownTool => "Tool does not exist on the machine",
+            Rule::ColdExtrusion => "Extrusion before the hotend is hot",
+            Rule::MoveBeforeHoming => "Motion before the axis is homed",
+            Rule::HeaterLeftOn => "Heater still on at the end of the program",
+            Rule::ArcGeometry => "Arc geometry is inconsistent or undefined",
+            Rule::Thumbnail => "Embedded thumbnail is truncated or does not match its header",
+        }
+    }
+
+    /// Look up a rule by its identifier
+    pub fn from_code(code: &str) -> Option<Rule> {
+        Rule::ALL.into_iter().find(|rule| rule.code() == code)
+    }
+}
+
+/// Parse a severity level; `off` yields `None`
+pub fn parse_severity_level(level: &str) -> Result<Option<Severity>, String> {
+    match level.to_ascii_lowercase().as_str() {
+        "error" => Ok(Some(Severity::Error)),
+        "warning" | "warn" => Ok(Some(Severity::Warning)),
+        "info" => Ok(Some(Severity::Info)),
+        "off" | "none" => Ok(None),
+        _ => Err(format!(
+            "Unknown severity '{}' (expected error, warning, info or off)",
+            level
+        )),
+    }
+}
+
+/// Per-rule severity overrides
+///
+/// Kept in rule order, so equal overrides always print the same.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SeverityOverrides {
+    overrides: BTreeMap<Rule, Option<Severity>>,
+}
+
+impl SeverityOverrides {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Override the severity of a rule; `None` disables it
+    pub fn set(&mut self, rule: Rule, severity: Option<Severity>) {
+        self.overrides.insert(rule, severity);
+    }
+
+    /// Get the override for a rule, if any
+    pub fn get(&self, rule: Rule) -> Option<Option<Severity>> {
+        self.overrides.get(&rule).copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.overrides.is_empty()
+    }
+
+    /// Merge another set of overrides on top of this one
+    pub fn extend(&mut self, other: &SeverityOverrides) {
+        self.overrides.extend(other.overrides.iter());
+    }
+
+    /// Set an override from a `rule=level` assignment
+    pub fn parse_assignment(&mut self, assignment: &str) -> Result<(), String> {
+        let (code, level) = assignment
+            .split_once('=')
+            .ok_or_else(|| format!("Expected RULE=LEVEL, got '{}'", assignment))?;
+        self.set_level(code.trim(), level.trim())
+    }
+
+    /// Set an override from a rule identifier and a severity level
+    pub fn set_level(&mut self, code: &str, level: &str) -> Result<(), String> {
+        let rule = Rule::from_code(code).ok_or_else(|| format!("Unknown rule '{}'", code))?;
+        self.set(rule, parse_severity_level(level)?);
+        Ok(())
+    }
+
+    /// Apply the overrides, dropping diagnostics of disabled rules
+    pub fn apply(&self, diagnostics: &mut Vec<Diagnostic>) {
+        if self.overrides.is_empty() {
+            return;
+        }
+
+        diagnostics.retain_mut(|diagnostic| match self.get(diagnostic.rule) {
+            Some(Some(severity)) => {
+                diagnostic.severity = severity;
+                true
+            }
+            Some(None) => false,
+            None => true,
+        });
+    }
+}
+
+impl<'de> Deserialize<'de> for SeverityOverrides {
+    /// Deserialize from a `rule = "level"` table
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let levels = HashMap::<String, String>::deserialize(deserializer)?;
+        let mut overrides = SeverityOverrides::new();
+        for (code, level) in &levels {
+            overrides
+                .set_level(code, level)
+                .map_err(serde::de::Error::custom)?;
+        }
+        Ok(overrides)
+    }
+}
+
+/// Rules targeted by a suppression comment
+#[derive(Debug, Clone, PartialEq)]
+enum RuleFilter {
+    All,
+    Only(HashSet<Rule>),
+}
+
+impl RuleFilter {
+    fn matches(&self, rule: Rule) -> bool {
+        match self {
+            RuleFilter::All => true,
+            RuleFilter::Only(rules) => rules.contains(&rule),
+        }
+    }
+
+    fn parse(list: Option<&str>) -> Self {
+        match list {
+            None => RuleFilter::All,
+            Some(list) => RuleFilter::Only(
+                list.split(',')
+                    .filter_map(|code| Rule::from_code(code.trim()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// Suppressions collected from `gcode-ls:` comments in a document
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Suppressions {
+    file: Vec<RuleFilter>,
+    lines: HashMap<usize, Vec<RuleFilter>>,
+}
+
+impl Suppressions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a suppression directive found in a comment on `line_num`
+    ///
+    /// `comment` is the comment text without its delimiter. Comments that
+    /// are not `gcode-ls:` directives are ignored.
+    pub fn scan_comment(&mut self, line_num: usize, comment: &str) {
+        let Some(directive) = comment.trim().strip_prefix("gcode-ls:") else {
+            return;
+        };
+
+        let (name, list) = match directive.trim().split_once('=') {
+            Some((name, list)) => (name.trim(), Some(list)),
+            None => (directive.trim(), None),
+        };
+
+        match name {
+            "disable" => self.file.push(RuleFilter::parse(list)),
+            "disable-next-line" => self
+                .lines
+                .entry(line_num + 1)
+                .or_default()
+                .push(RuleFilter::parse(list)),
+            _ => {}
+        }
+    }
+
+    /// Check whether a diagnostic is suppressed
+    pub fn is_suppressed(&self, diagnostic: &Diagnostic) -> bool {
+        self.file
+            .iter()
+            .chain(self.lines.get(&diagnostic.line).into_iter().flatten())
+            .any(|filter| filter.matches(diagnostic.rule))
+    }
+
+    /// Drop suppressed diagnostics
+    pub fn apply(&self, diagnostics: &mut Vec<Diagnostic>) {
+        if self.file.is_empty() && self.lines.is_empty() {
+            return;
+        }
+
+        diagnostics.retain(|diagnostic| !self.is_suppressed(diagnostic));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostic(line: usize, rule: Rule) -> Diagnostic {
+        Diagnostic {
+            line,
+            message: String::new(),
+            severity: Severity::Warning,
+            tags: Vec::new(),
+            span: None,
+            rule,
+        }
+    }
+
+    #[test]
+    fn test_rule_codes_round_trip() {
+        for rule in Rule::ALL {
+            assert_eq!(Rule::from_code(rule.code()), Some(rule));
+        }
+        assert_eq!(Rule::from_code("no-such-rule"), None);
+    }
+
+    #[test]
+    fn test_severity_overrides() {
+        let mut overrides = SeverityOverrides::new();
+        overrides.parse_assignment("unknown-command=error").unwrap();
+        overrides.parse_assignment("cold-extrusion = off").unwrap();
+        assert!(overrides.parse_assignment("unknown-command").is_err());
+        assert!(overrides.parse_assignment("bogus=error").is_err());
+        assert!(overrides.parse_assignment("unknown-command=loud").is_err());
+
+        let mut diagnostics = vec![
+            diagnostic(1, Rule::UnknownCommand),
+            diagnostic(2, Rule::ColdExtrusion),
+            diagnostic(3, Rule::UnknownParameter),
+        ];
+        overrides.apply(&mut diagnostics);
+
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert_eq!(diagnostics[1].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn test_suppression_comments() {
+        let mut suppressions = Suppressions::new();
+        suppressions.scan_comment(1, " gcode-ls: disable=heater-left-on");
+        suppressions.scan_comment(4, " gcode-ls: disable-next-line=unknown-parameter");
+        suppressions.scan_comment(7, " gcode-ls: disable-next-line");
+        suppressions.scan_comment(9, " just a comment");
+
+        let mut diagnostics = vec![
+            diagnostic(2, Rule::HeaterLeftOn),
+            diagnostic(5, Rule::UnknownParameter),
+            diagnostic(5, Rule::UnknownCommand),
+            diagnostic(6, Rule::UnknownParameter),
+            diagnostic(8, Rule::MissingRequiredParam),
+        ];
+        suppressions.apply(&mut diagnostics);
+
+        let remaining: Vec<(usize, Rule)> = diagnostics.iter().map(|d| (d.line, d.rule)).collect();
+        assert_eq!(
+            remaining,
+            vec![(5, Rule::UnknownCommand), (6, Rule::UnknownParameter)]
+        );
+    }
+}
@@ -0,0 +1,293 @@
+//! Safety Rules
+//!
+//! Stateful checks for common causes of failed prints: extruding with a cold
+//! hotend, moving before homing, and leaving heaters on at the end.
+//! Each rule runs against the machine state *before* the command executes.
+
+use crate::machine::MachineState;
+use crate::machine::state::PositioningMode;
+use crate::parser::Command;
+use crate::validation::engine::ValidationResult;
+use crate::validation::rules::Rule;
+
+/// Minimum extrusion temperature when the machine profile doesn't set one
+///
+/// Matches Marlin's default `EXTRUDE_MINTEMP`.
+pub const DEFAULT_MIN_EXTRUDE_TEMPERATURE: f64 = 170.0;
+
+/// Stateful safety checks over a document
+///
+/// Each rule reports its first violation only, so a file that never homes
+/// doesn't get a warning on every move. The cold extrusion rule re-arms
+/// whenever a temperature or tool command changes the hotend state.
+#[derive(Debug, Clone)]
+pub struct SafetyChecker {
+    min_extrude_temperature: f64,
+    reported_unhomed_move: bool,
+    reported_cold_extrusion: bool,
+    last_command_line: Option<usize>,
+}
+
+impl SafetyChecker {
+    pub fn new(min_extrude_temperature: f64) -> Self {
+        Self {
+            min_extrude_temperature,
+            reported_unhomed_move: false,
+            reported_cold_extrusion: false,
+            last_command_line: None,
+        }
+    }
+
+    /// Check a command against the state before it executes
+    pub fn check_command(
+        &mut self,
+        line_num: usize,
+        cmd: &Command,
+        state: &MachineState,
+        result: &mut ValidationResult,
+    ) {
+        self.last_command_line = Some(line_num);
+
+        match cmd.name.to_uppercase().as_str() {
+            "G0" | "G1" | "G2" | "G3" => {
+                self.check_homing(line_num, cmd, state, result);
+                if extrudes(cmd, state) {
+                    self.check_hotend(line_num, state, result);
+                }
+            }
+            "M104" | "M109" => self.reported_cold_extrusion = false,
+            name if name.starts_with('T') => self.reported_cold_extrusion = false,
+            _ => {}
+        }
+    }
+
+    /// Note a Klipper heater command, see
+    /// [`MachineState::apply_extended_command`]
+    pub fn check_heater_command(&mut self, line_num: usize) {
+        self.last_command_line = Some(line_num);
+        self.reported_cold_extrusion = false;
+    }
+
+    /// Check the final state once the whole document has been processed
+    pub fn finish(&self, state: &MachineState, result: &mut ValidationResult) {
+        let Some(line_num) = self.last_command_line else {
+            return;
+        };
+
+        let hotends_on: Vec<String> = state
+            .hotends
+            .iter()
+            .filter(|(_, heater)| heater.is_on())
+            .map(|(tool, _)| format!("T{}", tool))
+            .collect();
+        if !hotends_on.is_empty() {
+            result.add_warning(
+                line_num,
+                Rule::HeaterLeftOn,
+                format!(
+                    "Hotend heater ({}) is still on at the end of the program, add M104 S0",
+                    hotends_on.join(", ")
+                ),
+            );
+        }
+
+        if state.bed.is_some_and(|bed| bed.is_on()) {
+            result.add_warning(
+                line_num,
+                Rule::HeaterLeftOn,
+                "Bed heater is still on at the end of the program, add M140 S0".to_string(),
+            );
+        }
+    }
+
+    fn check_homing(
+        &mut self,
+        line_num: usize,
+        cmd: &Command,
+        state: &MachineState,
+        result: &mut ValidationResult,
+    ) {
+        if self.reported_unhomed_move {
+            return;
+        }
+
+        let unhomed: Vec<String> = cmd
+            .parameters
+            .iter()
+            .map(|p| p.letter.to_ascii_uppercase())
+            .filter(|axis| matches!(axis, 'X' | 'Y' | 'Z') && !state.homed.axis(*axis))
+            .map(String::from)
+            .collect();
+
+        if !unhomed.is_empty() {
+            self.reported_unhomed_move = true;
+            result.add_warning(
+                line_num,
+                Rule::MoveBeforeHoming,
+                format!(
+                    "Move on {} before homing, add G28 first",
+                    unhomed.join(", ")
+                ),
+            );
+        }
+    }
+
+    fn check_hotend(
+        &mut self,
+        line_num: usize,
+        state: &MachineState,
+        result: &mut ValidationResult,
+    ) {
+        if self.reported_cold_extrusion {
+            return;
+        }
+
+        let message = match state.hotend() {
+            None => "Extrusion before any hotend temperature is set, add M109".to_string(),
+            Some(heater) if heater.target < self.min_extrude_temperature => format!(
+                "Extrusion with hotend target {}°C, below the minimum extrusion temperature of {}°C",
+                heater.target, self.min_extrude_temperature
+            ),
+            Some(heater) if !heater.waited => format!(
+                "Extrusion before the hotend has reached {}°C, use M109 to wait",
+                heater.target
+            ),
+            Some(_) => return,
+        };
+
+        self.reported_cold_extrusion = true;
+        result.add_warning(line_num, Rule::ColdExtrusion, message);
+    }
+}
+
+/// Check whether a move pushes filament forward
+///
+/// An unknown E position counts as 0, where it is at power-on.
+fn extrudes(cmd: &Command, state: &MachineState) -> bool {
+    let Some(value) = cmd
+        .parameters
+        .iter()
+        .find(|p| p.letter.eq_ignore_ascii_case(&'E'))
+        .and_then(|p| p.value.parse::<f64>().ok())
+    else {
+        return false;
+    };
+
+    match state.extruder_positioning {
+        PositioningMode::Relative => value > 0.0,
+        PositioningMode::Absolute => state.to_millimeters(value) > state.position.e.unwrap_or(0.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{ParsedLine, parse_line};
+
+    fn check(content: &str) -> Vec<(usize, String)> {
+        let mut state = MachineState::new();
+        let mut checker = SafetyChecker::new(DEFAULT_MIN_EXTRUDE_TEMPERATURE);
+        let mut result = ValidationResult::new();
+
+        for (idx, line) in content.lines().enumerate() {
+            if state.apply_extended_command(line) {
+                checker.check_heater_command(idx + 1);
+            } else if let ParsedLine::Command(cmd) = parse_line(line) {
+                checker.check_command(idx + 1, &cmd, &state, &mut result);
+                state.apply(&cmd);
+            }
+        }
+        checker.finish(&state, &mut result);
+
+        result
+            .diagnostics
+            .into_iter()
+            .map(|d| (d.line, d.message))
+            .collect()
+    }
+
+    #[test]
+    fn test_safe_program() {
+        let content = "\
+M140 S60
+M104 S215
+G28
+M190 S60
+M109 S215
+G92 E0
+G1 X10 Y10 E5
+M104 S0
+M140 S0
+";
+        assert!(check(content).is_empty());
+    }
+
+    #[test]
+    fn test_motion_before_homing() {
+        let diagnostics = check("G1 Z5\nG1 X10\nG28\nG1 X10\n");
+        assert_eq!(diagnostics.len(), 1, "Only the first move is reported");
+        assert_eq!(diagnostics[0].0, 1);
+        assert!(diagnostics[0].1.contains("Move on Z before homing"));
+    }
+
+    #[test]
+    fn test_cold_extrusion() {
+        // Extruding without waiting for the target temperature
+        let diagnostics = check("G28\nM104 S215\nM83\nG1 X10 E1\nG1 X20 E1\n");
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].0, 4);
+        assert!(diagnostics[0].1.contains("use M109 to wait"));
+        assert!(diagnostics[1].1.contains("still on at the end"));
+
+        // Target below the minimum extrusion temperature
+        let diagnostics = check("G28\nM109 S150\nM83\nG1 X10 E1\nM104 S0\n");
+        assert_eq!(diagnostics.len(), 1);
+        assert!(
+            diagnostics[0]
+                .1
+                .contains("below the minimum extrusion temperature")
+        );
+
+        // No temperature at all; retractions are not extrusion
+        let diagnostics = check("G28\nM83\nG1 E-1\nG1 X10 E1\n");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].0, 4);
+        assert!(diagnostics[0].1.contains("before any hotend temperature"));
+    }
+
+    #[test]
+    fn test_absolute_extrusion_from_power_on() {
+        let diagnostics = check("G28\nM82\nG1 X10 E5\n");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].0, 3);
+        assert!(diagnostics[0].1.contains("before any hotend temperature"));
+    }
+
+    #[test]
+    fn test_klipper_heater_commands() {
+        let content = "\
+SET_HEATER_TEMPERATURE HEATER=heater_bed TARGET=60
+SET_HEATER_TEMPERATURE HEATER=extruder TARGET=215
+G28
+TEMPERATURE_WAIT SENSOR=extruder MINIMUM=210
+M83
+G1 X10 Y10 E5
+TURN_OFF_HEATERS
+";
+        assert!(check(content).is_empty());
+
+        let diagnostics = check("SET_HEATER_TEMPERATURE HEATER=extruder TARGET=215\nM83\nG1 E1\n");
+        assert_eq!(diagnostics.len(), 2);
+        assert!(diagnostics[0].1.contains("use M109 to wait"));
+        assert!(diagnostics[1].1.contains("still on at the end"));
+    }
+
+    #[test]
+    fn test_heaters_left_on() {
+        let diagnostics = check("M140 S60\nM104 S200\nG28\n");
+        assert_eq!(diagnostics.len(), 2);
+        assert!(diagnostics.iter().all(|(line, _)| *line == 3));
+        assert!(diagnostics[0].1.contains("(T0)"));
+        assert!(diagnostics[1].1.contains("Bed heater"));
+    }
+}
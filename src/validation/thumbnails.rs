@@ -0,0 +1,148 @@
+//! Thumbnail Checks
+//!
+//! Validates embedded slicer thumbnails against their `begin` header: the
+//! block must be closed, hold as many base64 characters as declared, decode,
+//! and contain an image of the declared format and size.
+
+use crate::parser::thumbnail::{image_header, thumbnail_blocks};
+use crate::validation::engine::ValidationResult;
+use crate::validation::rules::Rule;
+
+/// Check every thumbnail block of a document
+pub fn check_thumbnails(lines: &[&str], result: &mut ValidationResult) {
+    for block in thumbnail_blocks(lines.iter().copied()) {
+        let line_num = block.begin + 1;
+
+        if !block.closed {
+            result.add_error(
+                line_num,
+                Rule::Thumbnail,
+                format!("Thumbnail block is not closed by '; {} end'", block.keyword),
+            );
+            continue;
+        }
+        if block.data.len() != block.length {
+            result.add_error(
+                line_num,
+                Rule::Thumbnail,
+                format!(
+                    "Thumbnail declares {} base64 characters but holds {}",
+                    block.length,
+                    block.data.len()
+                ),
+            );
+        }
+
+        let bytes = match block.decode() {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                result.add_error(
+                    line_num,
+                    Rule::Thumbnail,
+                    format!("Thumbnail is not valid base64: {}", e),
+                );
+                continue;
+            }
+        };
+        match image_header(&bytes) {
+            Some((format, _, _)) if format != block.format => result.add_error(
+                line_num,
+                Rule::Thumbnail,
+                format!(
+                    "Thumbnail is declared as {} but holds a {} image",
+                    block.format.name(),
+                    format.name()
+                ),
+            ),
+            Some((_, width, height)) if (width, height) != (block.width, block.height) => result
+                .add_error(
+                    line_num,
+                    Rule::Thumbnail,
+                    format!(
+                        "Thumbnail is declared as {}x{} but the image is {}x{}",
+                        block.width, block.height, width, height
+                    ),
+                ),
+            Some(_) => {}
+            None => result.add_error(
+                line_num,
+                Rule::Thumbnail,
+                format!("Thumbnail is not a {} image", block.format.name()),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD;
+
+    fn messages(content: &str) -> Vec<(usize, String)> {
+        let lines: Vec<&str> = content.lines().collect();
+        let mut result = ValidationResult::new();
+        check_thumbnails(&lines, &mut result);
+        result
+            .diagnostics
+            .into_iter()
+            .map(|d| (d.line, d.message))
+            .collect()
+    }
+
+    fn qoi(width: u8, height: u8) -> String {
+        STANDARD.encode([
+            b'q', b'o', b'i', b'f', 0, 0, 0, width, 0, 0, 0, height, 4, 0,
+        ])
+    }
+
+    #[test]
+    fn test_valid_thumbnail() {
+        let data = qoi(16, 16);
+        let content = format!(
+            "; thumbnail_QOI begin 16x16 {}\n; {}\n; thumbnail_QOI end\n",
+            data.len(),
+            data
+        );
+        assert!(messages(&content).is_empty());
+    }
+
+    #[test]
+    fn test_thumbnail_mismatches() {
+        let data = qoi(32, 16);
+        let content = format!(
+            "G28\n; thumbnail_QOI begin 16x16 {}\n; {}\n; thumbnail_QOI end\n\
+             ; thumbnail begin 16x16 {}\n; {}\n; thumbnail end\n\
+             ; thumbnail_JPG begin 16x16 4\n; AAAA\nG1 X1\n",
+            data.len() + 1,
+            data,
+            data.len(),
+            data,
+        );
+        assert_eq!(
+            messages(&content),
+            vec![
+                (
+                    2,
+                    format!(
+                        "Thumbnail declares {} base64 characters but holds {}",
+                        data.len() + 1,
+                        data.len()
+                    )
+                ),
+                (
+                    2,
+                    "Thumbnail is declared as 16x16 but the image is 32x16".to_string()
+                ),
+                (
+                    5,
+                    "Thumbnail is declared as PNG but holds a QOI image".to_string()
+                ),
+                (
+                    8,
+                    "Thumbnail block is not closed by '; thumbnail_JPG end'".to_string()
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,62 @@
+//! Tests for the `gcode-ls check` subcommand
+use std::process::Command;
+
+use serde_json::Value;
+
+fn gcode_ls() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_gcode-ls"))
+}
+
+#[test]
+fn test_check_reports_errors_as_json() {
+    let output = gcode_ls()
+        .args([
+            "check",
+            "--format",
+            "json",
+            "tests/fixtures/sample_prusa.gcode",
+        ])
+        .output()
+        .expect("run gcode-ls check");
+
+    assert_eq!(output.status.code(), Some(1));
+
+    let report: Value = serde_json::from_slice(&output.stdout).expect("valid JSON report");
+    let diagnostics = report[0]["diagnostics"].as_array().unwrap();
+    assert!(
+        diagnostics
+            .iter()
+            .any(|d| d["rule"] == "unknown-command" && d["line"] == 19)
+    );
+}
+
+#[test]
+fn test_check_severity_override_clears_errors() {
+    let output = gcode_ls()
+        .args([
+            "check",
+            "--severity",
+            "constraint-violation=warning",
+            "--format",
+            "sarif",
+            "tests/fixtures/sample_prusa.gcode",
+        ])
+        .output()
+        .expect("run gcode-ls check");
+
+    assert_eq!(output.status.code(), Some(0));
+
+    let log: Value = serde_json::from_slice(&output.stdout).expect("valid SARIF log");
+    assert_eq!(log["version"], "2.1.0");
+    assert!(!log["runs"][0]["results"].as_array().unwrap().is_empty());
+}
+
+#[test]
+fn test_check_missing_file() {
+    let output = gcode_ls()
+        .args(["check", "tests/fixtures/missing.gcode"])
+        .output()
+        .expect("run gcode-ls check");
+
+    assert_eq!(output.status.code(), Some(2));
+}
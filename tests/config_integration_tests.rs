@@ -1,5 +1,7 @@
 //! Tests for the configuration system
 use clap::Parser;
+use gcode_language_server::validation::Severity;
+use gcode_language_server::validation::rules::Rule;
 use gcode_language_server::{Args, Config};
 
 #[test]
@@ -14,3 +16,48 @@ fn test_config_parsing() {
     // Should have flavor directories set up (at least the user config dir)
     assert!(!config.flavor_dirs.is_empty());
 }
+
+#[test]
+fn test_machine_arguments() {
+    let args = Args::parse_from([
+        "gcode-ls",
+        "--machine",
+        "prusa-mk4",
+        "--machine-dir",
+        "/tmp/machines",
+    ]);
+    let config = Config::from_args(args).expect("create config");
+
+    assert_eq!(
+        config.get_effective_machine(),
+        Some("prusa-mk4".to_string())
+    );
+    assert_eq!(
+        config.machine_dirs.first(),
+        Some(&std::path::PathBuf::from("/tmp/machines"))
+    );
+}
+
+#[test]
+fn test_severity_arguments() {
+    let args = Args::parse_from([
+        "gcode-ls",
+        "--severity",
+        "unknown-command=error",
+        "--severity",
+        "heater-left-on=off",
+    ]);
+    let config = Config::from_args(args).expect("create config");
+
+    assert_eq!(
+        config.severity_overrides.get(Rule::UnknownCommand),
+        Some(Some(Severity::Error))
+    );
+    assert_eq!(
+        config.severity_overrides.get(Rule::HeaterLeftOn),
+        Some(None)
+    );
+
+    let args = Args::parse_from(["gcode-ls", "--severity", "no-such-rule=error"]);
+    assert!(Config::from_args(args).is_err());
+}